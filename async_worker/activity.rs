@@ -0,0 +1,94 @@
+use std::{
+	collections::BTreeMap,
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Arc, Mutex,
+	},
+};
+
+/// The order that a queue's workers were last active in, so that the least recently active worker can be found for eviction without looking at
+/// every worker. See `QueueBuilder::max_workers`.
+pub struct ActivityOrder<Id> {
+	state: Mutex<OrderState<Id>>,
+}
+
+struct OrderState<Id> {
+	/// Every tracked worker along with it's `is_evicted` flag, keyed by a stamp of it's latest activity. Stamps only ever increase, so the first
+	/// worker is the least recently active one.
+	workers: BTreeMap<u64, (Id, Arc<AtomicBool>)>,
+	next_stamp: u64,
+}
+
+impl<Id> OrderState<Id> {
+	fn insert(&mut self, worker: (Id, Arc<AtomicBool>)) -> u64 {
+		let stamp = self.next_stamp;
+
+		self.next_stamp += 1;
+		self.workers.insert(stamp, worker);
+
+		stamp
+	}
+}
+
+impl<Id> ActivityOrder<Id> {
+	pub fn new() -> ActivityOrder<Id> {
+		ActivityOrder {
+			state: Mutex::new(OrderState {
+				workers: BTreeMap::new(),
+				next_stamp: 0,
+			}),
+		}
+	}
+
+	/// Start tracking a new worker as the most recently active one
+	pub fn track(self: &Arc<Self>, id: Id, is_evicted: Arc<AtomicBool>) -> Activity<Id> {
+		let stamp = self.state.lock().unwrap().insert((id, is_evicted.clone()));
+
+		Activity {
+			order: self.clone(),
+			stamp,
+			is_evicted,
+		}
+	}
+
+	/// The number of workers that are being tracked
+	pub fn len(&self) -> usize {
+		self.state.lock().unwrap().workers.len()
+	}
+
+	/// Stop tracking the least recently active worker, returning it's id and `is_evicted` flag. It won't be tracked again, even if it is active
+	/// while it winds down.
+	pub fn pop_least_recent(&self) -> Option<(Id, Arc<AtomicBool>)> {
+		self.state.lock().unwrap().workers.pop_first().map(|(_, worker)| worker)
+	}
+}
+
+/// A worker's place in it's queue's `ActivityOrder`. The worker stops being tracked once this is dropped, which happens when it terminates.
+pub struct Activity<Id> {
+	order: Arc<ActivityOrder<Id>>,
+	stamp: u64,
+	is_evicted: Arc<AtomicBool>,
+}
+
+impl<Id> Activity<Id> {
+	/// Move the worker to the end of the order, as the most recently active worker
+	pub fn touch(&mut self) {
+		let mut state = self.order.state.lock().unwrap();
+
+		// a worker that is no longer tracked has already been popped, and must not be put back
+		if let Some(worker) = state.workers.remove(&self.stamp) {
+			self.stamp = state.insert(worker);
+		}
+	}
+
+	/// Whether the queue evicted the worker to make room for a new one
+	pub fn is_evicted(&self) -> bool {
+		self.is_evicted.load(Ordering::Acquire)
+	}
+}
+
+impl<Id> Drop for Activity<Id> {
+	fn drop(&mut self) {
+		self.order.state.lock().unwrap().workers.remove(&self.stamp);
+	}
+}
@@ -0,0 +1,142 @@
+use log::{debug, warn};
+use std::{
+	future::Future,
+	pin::Pin,
+	sync::{Arc, Weak},
+	time::Duration,
+};
+use tokio::time::Instant;
+
+use crate::{ticket::Ticket, worker::Worker, Error, Result};
+
+pub type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
+
+/// The operations that an `Address` can perform on a queue. This allows an `Address` to refer to a queue without knowing the type of it's handles.
+pub trait Route<W: Worker>: Send + Sync {
+	fn enqueue(self: Arc<Self>, id: W::Id, request: W::Request) -> BoxFuture<Result<Ticket>>;
+	fn request(self: Arc<Self>, id: W::Id, request: W::Request) -> BoxFuture<Result<W::Response>>;
+	fn enqueue_at(self: Arc<Self>, id: &W::Id, request: W::Request, at: Instant) -> Result<()>;
+	fn reply(self: Arc<Self>, id: W::Id, request: W::Request) -> BoxFuture<Result<()>>;
+}
+
+/// A cheaply clonable reference to a `Queue`, which can be used to send requests to it's workers. Obtained via `Queue::address` or
+/// `Mailbox::address`.
+///
+/// An address does not keep it's queue alive. Once the queue has been dropped, all operations will throw an `Error::NoWorker`.
+pub struct Address<W: Worker> {
+	route: Weak<dyn Route<W>>,
+}
+
+impl<W: Worker> Address<W> {
+	pub(crate) fn new(route: Weak<dyn Route<W>>) -> Address<W> {
+		Address { route }
+	}
+
+	/// Enqueue a request for the worker referenced by `id`, creating the worker if it does not exist. See `Queue::enqueue`.
+	pub async fn enqueue(&self, id: &W::Id, request: W::Request) -> Result<Ticket> {
+		self.upgrade()?.enqueue(id.clone(), request).await
+	}
+
+	/// Send a request to the worker referenced by `id`, creating the worker if it does not exist, and wait for it's response. See `Queue::request`.
+	///
+	/// Awaiting this from inside of `Worker::handle` will block the calling worker until the response arrives, so two workers that do this to
+	/// each other will deadlock. Workers should use `Mailbox::ask` instead.
+	pub async fn request(&self, id: &W::Id, request: W::Request) -> Result<W::Response> {
+		self.upgrade()?.request(id.clone(), request).await
+	}
+
+	/// Enqueue a request for an existing worker once `delay` has passed. See `Queue::enqueue_after`.
+	pub fn enqueue_after(&self, id: &W::Id, request: W::Request, delay: Duration) -> Result<()> {
+		self.enqueue_at(id, request, Instant::now() + delay)
+	}
+
+	/// Enqueue a request for an existing worker once `at` has been reached. See `Queue::enqueue_at`.
+	pub fn enqueue_at(&self, id: &W::Id, request: W::Request, at: Instant) -> Result<()> {
+		self.upgrade()?.enqueue_at(id, request, at)
+	}
+
+	/// Enqueue the reply to a `Mailbox::ask` for an existing worker. See `Queue::reply`.
+	async fn reply(&self, id: &W::Id, request: W::Request) -> Result<()> {
+		self.upgrade()?.reply(id.clone(), request).await
+	}
+
+	fn upgrade(&self) -> Result<Arc<dyn Route<W>>> {
+		self.route.upgrade().ok_or(Error::NoWorker)
+	}
+}
+
+impl<W: Worker> Clone for Address<W> {
+	fn clone(&self) -> Self {
+		Address { route: self.route.clone() }
+	}
+}
+
+/// Given to a worker in `Worker::create` and `Worker::handle`, allowing it to address itself, it's peers in the same queue, and the workers of
+/// other queues.
+pub struct Mailbox<W: Worker> {
+	id: W::Id,
+	address: Address<W>,
+}
+
+impl<W: Worker> Mailbox<W> {
+	pub(crate) fn new(id: W::Id, address: Address<W>) -> Mailbox<W> {
+		Mailbox { id, address }
+	}
+
+	/// The id of the worker that this mailbox belongs to.
+	pub fn id(&self) -> &W::Id {
+		&self.id
+	}
+
+	/// The address of the queue that this worker belongs to. Can be cloned and handed out to other workers or queues (via their context).
+	pub fn address(&self) -> &Address<W> {
+		&self.address
+	}
+
+	/// Enqueue a request for another worker in the same queue, creating it if it does not exist. The response will be sent to that worker's
+	/// handle or poller, just as if the request came from `Queue::enqueue`.
+	pub async fn enqueue(&self, id: &W::Id, request: W::Request) -> Result<Ticket> {
+		self.address.enqueue(id, request).await
+	}
+
+	/// Enqueue a request for this worker to pick up once `delay` has passed. See `Queue::enqueue_after`.
+	pub fn enqueue_self_after(&self, request: W::Request, delay: Duration) -> Result<()> {
+		self.address.enqueue_after(&self.id, request, delay)
+	}
+
+	/// Send `request` to the worker referenced by `id` at `address`, which may belong to this queue or another one. Once that worker has handled
+	/// the request, it's response is passed to `reply`, and the result is enqueued for this worker.
+	///
+	/// Because this does not wait for the response, it will never deadlock, even when the two workers are asking each other. The reply is
+	/// enqueued according to this queue's backpressure strategy, just like any other request. If this worker has terminated by the time the
+	/// response arrives, the reply is dropped.
+	pub fn ask<P, F>(&self, address: &Address<P>, id: &P::Id, request: P::Request, reply: F)
+	where
+		P: Worker,
+		F: FnOnce(Result<P::Response>) -> W::Request + Send + 'static,
+	{
+		let peer_address = address.clone();
+		let peer_id = id.clone();
+		let own_address = self.address.clone();
+		let own_id = self.id.clone();
+
+		tokio::spawn(async move {
+			let response = peer_address.request(&peer_id, request).await;
+
+			match own_address.reply(&own_id, reply(response)).await {
+				Ok(_) => (),
+				Err(Error::NoWorker) => debug!("dropped reply for worker {own_id:?}, which has terminated"),
+				Err(error) => warn!("dropped reply for worker {own_id:?}: {error}"),
+			}
+		});
+	}
+}
+
+impl<W: Worker> Clone for Mailbox<W> {
+	fn clone(&self) -> Self {
+		Mailbox {
+			id: self.id.clone(),
+			address: self.address.clone(),
+		}
+	}
+}
@@ -0,0 +1,163 @@
+use axum::{
+	extract::ws::{close_code, CloseFrame, Message, WebSocket},
+	response::sse::{Event, Sse},
+};
+use log::debug;
+use std::{convert::Infallible, error::Error as _, future::pending, io};
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio_stream::{wrappers::ReceiverStream, Stream, StreamExt};
+
+use crate::handle::{DropReason, SendResult, WorkerHandle};
+
+/// The close code that a `WebSocketHandle` sends when it is ceeded to a newer handle.
+pub const CEEDED_CLOSE_CODE: u16 = 4000;
+
+/// The number of events that an `SseHandle` will buffer before it holds back responses until the client catches up.
+const SSE_BUFFER_SIZE: usize = 16;
+
+/// A handle that sends responses over an axum websocket and enqueues the requests that come in over it.
+///
+/// If a response can't be sent but the socket is still usable, it is sent again along with the next one. Any other error closes the handle.
+///
+/// When the handle is ceeded to a newer one, the socket is closed with `CEEDED_CLOSE_CODE`, and when the worker terminates, it is closed with
+/// `close_code::AWAY`.
+pub struct WebSocketHandle<Request, Response> {
+	socket: WebSocket,
+	encode: Box<dyn Fn(&Response) -> Message + Send>,
+	decode: Box<dyn Fn(Message) -> Option<Request> + Send>,
+}
+
+impl<Request, Response> WebSocketHandle<Request, Response> {
+	/// Create a handle for `socket`. Every response is sent as `encode(&response)`. Every incoming text or binary message is passed to `decode`,
+	/// and enqueued if it returns a request. Messages that can't be decoded are ignored.
+	pub fn new(
+		socket: WebSocket,
+		encode: impl Fn(&Response) -> Message + Send + 'static,
+		decode: impl Fn(Message) -> Option<Request> + Send + 'static,
+	) -> WebSocketHandle<Request, Response> {
+		WebSocketHandle {
+			socket,
+			encode: Box::new(encode),
+			decode: Box::new(decode),
+		}
+	}
+}
+
+impl<Request, Response> WorkerHandle<Request, Response> for WebSocketHandle<Request, Response>
+where
+	Request: Send,
+	Response: Send,
+{
+	async fn recv(&mut self) -> Option<Request> {
+		loop {
+			match self.socket.recv().await? {
+				Ok(Message::Close(_)) | Err(_) => return None,
+				// pings are answered by axum itself
+				Ok(Message::Ping(_)) | Ok(Message::Pong(_)) => (),
+				Ok(message) => match (self.decode)(message) {
+					Some(request) => return Some(request),
+					None => debug!("ignoring a websocket message that could not be decoded"),
+				},
+			}
+		}
+	}
+
+	async fn send(&mut self, response: Response) -> SendResult<Response> {
+		match self.socket.send((self.encode)(&response)).await {
+			Ok(_) => SendResult::Sent,
+			Err(error) if is_transient(&error) => {
+				debug!("failed to send a response over a websocket, it will be sent again: {error}");
+
+				SendResult::Failed(response)
+			}
+			Err(_) => SendResult::Closed(response),
+		}
+	}
+
+	async fn will_drop(&mut self, reason: DropReason) {
+		let (code, reason) = match reason {
+			DropReason::Ceeded => (CEEDED_CLOSE_CODE, "ceeded to a newer connection"),
+			DropReason::WorkerTerminated => (close_code::AWAY, "worker terminated"),
+			DropReason::HandleClosed => return,
+		};
+
+		let _ = self
+			.socket
+			.send(Message::Close(Some(CloseFrame {
+				code,
+				reason: reason.into(),
+			})))
+			.await;
+	}
+}
+
+/// Whether an error from sending over a websocket leaves the socket usable. Only io errors that don't mean that the connection is gone are,
+/// and the socket keeps those as the source of it's error.
+fn is_transient(error: &axum::Error) -> bool {
+	let mut source = error.source();
+
+	while let Some(error) = source {
+		if let Some(error) = error.downcast_ref::<io::Error>() {
+			return matches!(error.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted | io::ErrorKind::TimedOut);
+		}
+
+		source = error.source();
+	}
+
+	false
+}
+
+/// A handle that sends responses as Server-Sent Events. Because SSE is one-directional, requests must still be delivered to the worker via the
+/// queue.
+///
+/// A slow client never holds up the worker. Once it falls behind by too many events, responses are held back and sent again along with the
+/// next one.
+///
+/// When the handle is ceeded to a newer one or the worker terminates, a final `close` event is sent, with data of `ceeded` or `terminated`
+/// respectively, and then the stream ends.
+pub struct SseHandle<Response> {
+	sender: mpsc::Sender<Event>,
+	encode: Box<dyn Fn(&Response) -> Event + Send>,
+}
+
+impl<Response> SseHandle<Response> {
+	/// Create a handle, along with the `Sse` response that it's events will be streamed through. Every response is sent as `encode(&response)`.
+	///
+	/// The handle is closed once the `Sse` response is dropped, which axum does when the client disconnects.
+	pub fn new(encode: impl Fn(&Response) -> Event + Send + 'static) -> (SseHandle<Response>, Sse<impl Stream<Item = Result<Event, Infallible>>>) {
+		let (sender, receiver) = mpsc::channel(SSE_BUFFER_SIZE);
+		let handle = SseHandle {
+			sender,
+			encode: Box::new(encode),
+		};
+
+		(handle, Sse::new(ReceiverStream::new(receiver).map(Ok)))
+	}
+}
+
+impl<Request, Response> WorkerHandle<Request, Response> for SseHandle<Response>
+where
+	Response: Send,
+{
+	async fn recv(&mut self) -> Option<Request> {
+		pending().await
+	}
+
+	async fn send(&mut self, response: Response) -> SendResult<Response> {
+		match self.sender.try_send((self.encode)(&response)) {
+			Ok(_) => SendResult::Sent,
+			Err(TrySendError::Full(_)) => SendResult::Failed(response),
+			Err(TrySendError::Closed(_)) => SendResult::Closed(response),
+		}
+	}
+
+	async fn will_drop(&mut self, reason: DropReason) {
+		let data = match reason {
+			DropReason::Ceeded => "ceeded",
+			DropReason::WorkerTerminated => "terminated",
+			DropReason::HandleClosed => return,
+		};
+
+		let _ = self.sender.try_send(Event::default().event("close").data(data));
+	}
+}
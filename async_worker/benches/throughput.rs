@@ -0,0 +1,149 @@
+//! Measures the enqueue/poll throughput and latency of `Queue` and `ShardedQueue` with a large number of concurrent workers.
+//!
+//! Run with `cargo bench -p async_worker`. The number of workers and rounds can be adjusted via the `BENCH_WORKERS` and `BENCH_ROUNDS` environment
+//! variables.
+
+use async_worker::{Error, Mailbox, Queue, QueueBuilder, ShardedQueue, Worker};
+use std::{
+	env,
+	future::Future,
+	num::NonZeroUsize,
+	sync::Arc,
+	thread,
+	time::{Duration, Instant},
+};
+use tokio::runtime::{self, Runtime};
+
+struct Echo;
+
+impl Worker for Echo {
+	type Context = ();
+	type Request = u64;
+	type Response = u64;
+	type Id = u64;
+
+	async fn create(_: &Self::Id, _: Self::Context, _: &Mailbox<Self>) -> Self {
+		Echo
+	}
+
+	async fn handle(&mut self, request: Self::Request, _: &Mailbox<Self>) -> Self::Response {
+		request
+	}
+
+	async fn destroy(self) {}
+}
+
+trait Target: Send + Sync + 'static {
+	fn enqueue(&self, id: u64, request: u64) -> impl Future<Output = Result<(), Error>> + Send;
+	fn poll(&self, id: u64) -> impl Future<Output = Result<u64, Error>> + Send;
+}
+
+impl Target for Queue<Echo> {
+	async fn enqueue(&self, id: u64, request: u64) -> Result<(), Error> {
+		Queue::enqueue(self, &id, request).await.map(|_| ())
+	}
+
+	async fn poll(&self, id: u64) -> Result<u64, Error> {
+		Queue::poll(self, &id).await
+	}
+}
+
+impl Target for ShardedQueue<Echo> {
+	async fn enqueue(&self, id: u64, request: u64) -> Result<(), Error> {
+		ShardedQueue::enqueue(self, &id, request).await.map(|_| ())
+	}
+
+	async fn poll(&self, id: u64) -> Result<u64, Error> {
+		ShardedQueue::poll(self, &id).await
+	}
+}
+
+struct Report {
+	operations: usize,
+	elapsed: Duration,
+	latencies: Vec<Duration>,
+}
+
+impl Report {
+	fn print(mut self, name: &str) {
+		self.latencies.sort();
+
+		let percentile = |percent: usize| self.latencies[(self.latencies.len() - 1) * percent / 100];
+		let throughput = self.operations as f64 / self.elapsed.as_secs_f64();
+
+		println!(
+			"{name:<24} {throughput:>12.0} round trips/s   p50 {:>10.2?}   p99 {:>10.2?}   max {:>10.2?}",
+			percentile(50),
+			percentile(99),
+			percentile(100)
+		);
+	}
+}
+
+/// Each worker gets it's own task, which enqueues a request and polls for the response `rounds` times. The latency of each round trip is recorded.
+async fn run<T: Target>(target: T, workers: u64, rounds: usize) -> Report {
+	let target = Arc::new(target);
+	let start = Instant::now();
+	let mut tasks = Vec::new();
+
+	for id in 0..workers {
+		let target = target.clone();
+
+		tasks.push(tokio::spawn(async move {
+			let mut latencies = Vec::with_capacity(rounds);
+
+			for round in 0..rounds {
+				let round_start = Instant::now();
+
+				target.enqueue(id, round as u64).await.expect("failed to enqueue");
+				target.poll(id).await.expect("failed to poll");
+
+				latencies.push(round_start.elapsed());
+			}
+
+			latencies
+		}));
+	}
+
+	let mut latencies = Vec::new();
+
+	for task in tasks {
+		latencies.extend(task.await.expect("benchmark task panicked"));
+	}
+
+	Report {
+		operations: latencies.len(),
+		elapsed: start.elapsed(),
+		latencies,
+	}
+}
+
+fn get_env_var(name: &str, default: usize) -> usize {
+	env::var(name).ok().and_then(|value| value.parse().ok()).unwrap_or(default)
+}
+
+fn main() {
+	let workers = get_env_var("BENCH_WORKERS", 10_000) as u64;
+	let rounds = get_env_var("BENCH_ROUNDS", 20);
+	let parallelism = thread::available_parallelism().map(NonZeroUsize::get).unwrap_or(1);
+
+	let runtime = runtime::Builder::new_multi_thread().enable_all().build().expect("failed to build runtime");
+	let shard_runtimes = (0..parallelism)
+		.map(|_| runtime::Builder::new_multi_thread().worker_threads(1).enable_all().build().expect("failed to build shard runtime"))
+		.collect::<Vec<Runtime>>();
+
+	println!("{workers} workers, {rounds} rounds each, {parallelism} shards\n");
+
+	runtime.block_on(async {
+		let queue = QueueBuilder::default().build::<Echo>(());
+		run(queue, workers, rounds).await.print("Queue");
+
+		let queue = QueueBuilder::default().shards(parallelism).build_sharded::<Echo>(());
+		run(queue, workers, rounds).await.print("ShardedQueue");
+
+		let queue = QueueBuilder::default()
+			.shard_runtimes(shard_runtimes.iter().map(|runtime| runtime.handle().clone()))
+			.build_sharded::<Echo>(());
+		run(queue, workers, rounds).await.print("ShardedQueue (runtimes)");
+	});
+}
@@ -0,0 +1,163 @@
+use std::{
+	collections::VecDeque,
+	pin::pin,
+	sync::{Arc, Mutex},
+	time::Duration,
+};
+use tokio::{
+	sync::Notify,
+	time::{timeout_at, Instant},
+};
+
+pub use tokio::sync::mpsc::error::TrySendError;
+
+struct State<T> {
+	items: VecDeque<T>,
+	sender_count: usize,
+	is_receiver_closed: bool,
+}
+
+struct Shared<T> {
+	state: Mutex<State<T>>,
+	capacity: usize,
+	item_sent: Notify,
+	item_received: Notify,
+}
+
+/// Creates a bounded, single-consumer channel. Unlike `tokio::sync::mpsc`, the pending items of this channel can be inspected and rearranged by
+/// the sending side, which is what makes backpressure strategies like dropping or coalescing pending requests possible.
+pub fn channel<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+	let shared = Arc::new(Shared {
+		state: Mutex::new(State {
+			items: VecDeque::new(),
+			sender_count: 1,
+			is_receiver_closed: false,
+		}),
+		capacity,
+		item_sent: Notify::new(),
+		item_received: Notify::new(),
+	});
+
+	(Sender { shared: shared.clone() }, Receiver { shared })
+}
+
+pub struct Sender<T> {
+	shared: Arc<Shared<T>>,
+}
+
+impl<T> Sender<T> {
+	/// Attempts to immediately send `item`, failing if the channel is full or the receiver has been dropped.
+	pub fn try_send(&self, item: T) -> Result<(), TrySendError<T>> {
+		self.try_send_or_else(item, |_, item| Err(item))
+	}
+
+	/// Attempts to immediately send `item`. If the channel is full, `make_room` is given the pending items and the rejected item. It can then
+	/// rearrange the pending items however it sees fit, either accepting the item (`Ok`) or handing it back (`Err`).
+	///
+	/// `make_room` is called while the channel is locked, so it should be quick.
+	pub fn try_send_or_else(&self, item: T, make_room: impl FnOnce(&mut VecDeque<T>, T) -> Result<(), T>) -> Result<(), TrySendError<T>> {
+		{
+			let mut state = self.shared.state.lock().unwrap();
+
+			if state.is_receiver_closed {
+				return Err(TrySendError::Closed(item));
+			}
+
+			if state.items.len() < self.shared.capacity {
+				state.items.push_back(item);
+			} else if let Err(item) = make_room(&mut state.items, item) {
+				return Err(TrySendError::Full(item));
+			}
+		}
+
+		self.shared.item_sent.notify_one();
+
+		Ok(())
+	}
+
+	/// Sends `item`, waiting up to `duration` for room to become available if the channel is full.
+	pub async fn send_timeout(&self, mut item: T, duration: Duration) -> Result<(), TrySendError<T>> {
+		let deadline = Instant::now() + duration;
+
+		loop {
+			let mut item_received = pin!(self.shared.item_received.notified());
+			item_received.as_mut().enable();
+
+			match self.try_send(item) {
+				Err(TrySendError::Full(rejected)) => item = rejected,
+				result => return result,
+			}
+
+			if timeout_at(deadline, item_received).await.is_err() {
+				return Err(TrySendError::Full(item));
+			}
+		}
+	}
+}
+
+impl<T> Clone for Sender<T> {
+	fn clone(&self) -> Self {
+		self.shared.state.lock().unwrap().sender_count += 1;
+
+		Sender { shared: self.shared.clone() }
+	}
+}
+
+impl<T> Drop for Sender<T> {
+	fn drop(&mut self) {
+		let is_last_sender = {
+			let mut state = self.shared.state.lock().unwrap();
+			state.sender_count -= 1;
+
+			state.sender_count == 0
+		};
+
+		if is_last_sender {
+			self.shared.item_sent.notify_one();
+		}
+	}
+}
+
+pub struct Receiver<T> {
+	shared: Arc<Shared<T>>,
+}
+
+impl<T> Receiver<T> {
+	/// Receives the next item. A return value of `None` indicates that all senders have been dropped and there are no items left.
+	///
+	/// This function is cancel safe.
+	pub async fn recv(&mut self) -> Option<T> {
+		loop {
+			{
+				let mut state = self.shared.state.lock().unwrap();
+
+				if let Some(item) = state.items.pop_front() {
+					drop(state);
+					self.shared.item_received.notify_waiters();
+
+					return Some(item);
+				}
+
+				if state.sender_count == 0 {
+					return None;
+				}
+			}
+
+			// there is only ever one receiver, so any notification sent between the check above and this line will be stored as a permit
+			self.shared.item_sent.notified().await;
+		}
+	}
+}
+
+impl<T> Drop for Receiver<T> {
+	fn drop(&mut self) {
+		let items = {
+			let mut state = self.shared.state.lock().unwrap();
+			state.is_receiver_closed = true;
+
+			std::mem::take(&mut state.items)
+		};
+
+		drop(items);
+	}
+}
@@ -0,0 +1,126 @@
+use log::error;
+use std::{collections::HashMap, fmt::Debug, future::Future, hash::Hash, sync::Arc};
+use tokio::sync::oneshot::{self, error::TryRecvError};
+
+use crate::{
+	address::{BoxFuture, Mailbox},
+	handle::NoopHandle,
+	queue::Queue,
+	worker::Worker,
+};
+
+/// A queue of workers that handle requests concurrently. See `ConcurrentWorker`.
+pub type ConcurrentQueue<W, H = NoopHandle> = Queue<Concurrent<W>, H>;
+
+/// The same as `Worker`, except that requests are handled via a shared reference, so a slow request doesn't hold up the ones behind it.
+///
+/// Each request is given an ordering key by `ConcurrentWorker::key`. Requests with the same key are handled one at a time, in the order that
+/// they were received. Requests with different keys are handled concurrently, so any state that they touch must live behind the worker's own locks.
+///
+/// Concurrent workers are used through the `Concurrent` adapter, e.g. `QueueBuilder::default().build::<Concurrent<MyWorker>>(context)`.
+pub trait ConcurrentWorker
+where
+	Self: Sized + Send + Sync + 'static,
+{
+	type Context: 'static + Send + Sync + Sized + Clone;
+	type Request: 'static + Send + Sized;
+	type Response: 'static + Send + Sized;
+	type Id: 'static + Hash + PartialOrd + Eq + Clone + Send + Sync + Debug;
+	type Key: 'static + Hash + Eq + Send;
+
+	/// See `Worker::create`
+	fn create(id: &Self::Id, context: Self::Context, mailbox: &Mailbox<Concurrent<Self>>) -> impl Future<Output = Self> + Send;
+
+	/// The ordering key of `request`. Returning the same key for every request makes the worker behave like a regular `Worker`.
+	fn key(request: &Self::Request) -> Self::Key;
+
+	/// See `Worker::handle`
+	fn handle(&self, request: Self::Request, mailbox: &Mailbox<Concurrent<Self>>) -> impl Future<Output = Self::Response> + Send;
+
+	/// See `Worker::cancelled`. Because other requests may be running at the same time, there is no way to tell which request was cancelled.
+	/// This is also called for requests that were cancelled while waiting on an earlier request with the same key.
+	fn cancelled(&self, mailbox: &Mailbox<Concurrent<Self>>) -> impl Future<Output = ()> + Send {
+		let _ = mailbox;
+
+		async {}
+	}
+
+	/// See `Worker::evicted`. Called once every ongoing request has completed.
+	fn evicted(&self, mailbox: &Mailbox<Concurrent<Self>>) -> impl Future<Output = ()> + Send {
+		let _ = mailbox;
+
+		async {}
+	}
+
+	/// See `Worker::destroy`. Called once every ongoing request has completed.
+	fn destroy(self) -> impl Future<Output = ()> + Send;
+}
+
+/// Adapts a `ConcurrentWorker` into a `Worker`.
+pub struct Concurrent<W: ConcurrentWorker> {
+	worker: Arc<W>,
+	/// For each key, resolves once the most recently started request with that key has completed
+	tails: HashMap<W::Key, oneshot::Receiver<()>>,
+}
+
+impl<W: ConcurrentWorker> Concurrent<W> {
+	/// Start `request` once the previous request with the same key has completed.
+	fn start(&mut self, request: W::Request, mailbox: &Mailbox<Self>) -> BoxFuture<W::Response> {
+		// forget about keys whose requests have all completed, so that the map doesn't grow forever
+		self.tails.retain(|_, tail| matches!(tail.try_recv(), Err(TryRecvError::Empty)));
+
+		let (done_sender, done_receiver) = oneshot::channel();
+		let previous = self.tails.insert(W::key(&request), done_receiver);
+		let worker = self.worker.clone();
+		let mailbox = mailbox.clone();
+
+		Box::pin(async move {
+			// if the previous request was cancelled, it's sender was dropped, which is just as good
+			if let Some(previous) = previous {
+				let _ = previous.await;
+			}
+
+			let response = worker.handle(request, &mailbox).await;
+			let _ = done_sender.send(());
+
+			response
+		})
+	}
+}
+
+impl<W: ConcurrentWorker> Worker for Concurrent<W> {
+	type Context = W::Context;
+	type Request = W::Request;
+	type Response = W::Response;
+	type Id = W::Id;
+
+	async fn create(id: &Self::Id, context: Self::Context, mailbox: &Mailbox<Self>) -> Self {
+		Concurrent {
+			worker: Arc::new(W::create(id, context, mailbox).await),
+			tails: HashMap::new(),
+		}
+	}
+
+	async fn handle(&mut self, request: Self::Request, mailbox: &Mailbox<Self>) -> Self::Response {
+		self.start(request, mailbox).await
+	}
+
+	async fn cancelled(&mut self, mailbox: &Mailbox<Self>) {
+		self.worker.cancelled(mailbox).await
+	}
+
+	fn handle_concurrently(&mut self, request: Self::Request, mailbox: &Mailbox<Self>) -> Result<BoxFuture<Self::Response>, Self::Request> {
+		Ok(self.start(request, mailbox))
+	}
+
+	async fn evicted(&mut self, mailbox: &Mailbox<Self>) {
+		self.worker.evicted(mailbox).await
+	}
+
+	async fn destroy(self) {
+		match Arc::into_inner(self.worker) {
+			Some(worker) => worker.destroy().await,
+			None => error!("a concurrent worker was dropped without being destroyed because one of it's requests is still running"),
+		}
+	}
+}
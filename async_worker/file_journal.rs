@@ -0,0 +1,357 @@
+use log::{error, warn};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+	collections::BTreeMap,
+	fs::{self, File, OpenOptions},
+	io::{self, BufRead, BufReader, Write},
+	marker::PhantomData,
+	mem,
+	path::{Path, PathBuf},
+	sync::atomic::{AtomicU64, Ordering},
+};
+use tokio::{
+	sync::{mpsc, oneshot},
+	task::spawn_blocking,
+};
+
+use crate::{
+	address::BoxFuture,
+	journal::{Acknowledge, JournalEntry, RequestJournal},
+};
+
+type OpenedJournal<Id, Request> = (OpenFileJournal<Id, Request>, Vec<JournalEntry<Id, Request>>);
+
+/// The default for `FileJournal::compact_after`
+const DEFAULT_COMPACT_AFTER: usize = 1024;
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+enum Record<Id, Request> {
+	Enqueue { sequence: u64, id: Id, request: Request },
+	Acknowledge { sequences: Vec<u64> },
+}
+
+/// A request journal that is kept in a local file, with one JSON record per line. See `QueueBuilder::build_journaled`.
+///
+/// The journal is compacted down to the requests that still need to be replayed whenever it is opened, and again every time enough requests
+/// have been acknowledged (see `FileJournal::compact_after`). Records are written by a blocking task, so recording a request never blocks the
+/// runtime, and records that arrive while a write is in progress are written together in the next one.
+pub struct FileJournal {
+	path: PathBuf,
+	sync: bool,
+	compact_after: usize,
+}
+
+impl FileJournal {
+	pub fn new(path: impl Into<PathBuf>) -> FileJournal {
+		FileJournal {
+			path: path.into(),
+			sync: true,
+			compact_after: DEFAULT_COMPACT_AFTER,
+		}
+	}
+
+	/// Whether every write to the journal should be flushed all the way to disk before the queue moves on. Defaults to true.
+	///
+	/// Turning this off makes enqueuing much faster, but requests that were accepted just before the machine (not just the process) crashed may
+	/// be lost.
+	pub fn sync(mut self, sync: bool) -> FileJournal {
+		self.sync = sync;
+
+		self
+	}
+
+	/// The number of requests that can be acknowledged before the journal is compacted, which rewrites it with only the requests that still
+	/// need to be replayed. Defaults to 1024.
+	pub fn compact_after(mut self, count: usize) -> FileJournal {
+		self.compact_after = count.max(1);
+
+		self
+	}
+
+	/// Open the journal, returning a writer for it along with every request that was never acknowledged, in the order that they were recorded.
+	pub(crate) async fn open<Id, Request>(self) -> io::Result<OpenedJournal<Id, Request>>
+	where
+		Id: Serialize + DeserializeOwned + Send + 'static,
+		Request: Serialize + DeserializeOwned + Send + 'static,
+	{
+		let (writer, entries) = spawn_blocking(move || self.read()).await??;
+		let next_sequence = writer.pending.keys().next_back().map_or(0, |sequence| sequence + 1);
+		let (command_sender, command_receiver) = mpsc::unbounded_channel();
+
+		spawn_blocking(move || writer.run(command_receiver));
+
+		let journal = OpenFileJournal {
+			command_sender,
+			next_sequence: AtomicU64::new(next_sequence),
+			marker: PhantomData,
+		};
+
+		Ok((journal, entries))
+	}
+
+	/// Read every record in the journal, and compact it down to the requests that were never acknowledged
+	fn read<Id, Request>(self) -> io::Result<(JournalWriter, Vec<JournalEntry<Id, Request>>)>
+	where
+		Id: Serialize + DeserializeOwned,
+		Request: Serialize + DeserializeOwned,
+	{
+		let mut pending = BTreeMap::<u64, (Id, Request)>::new();
+		let mut next_sequence = 0;
+
+		if self.path.exists() {
+			let lines = BufReader::new(File::open(&self.path)?).lines().collect::<io::Result<Vec<_>>>()?;
+			let line_count = lines.len();
+
+			for (index, line) in lines.into_iter().enumerate() {
+				match serde_json::from_str::<Record<Id, Request>>(&line) {
+					Ok(Record::Enqueue { sequence, id, request }) => {
+						next_sequence = next_sequence.max(sequence + 1);
+						pending.insert(sequence, (id, request));
+					}
+					Ok(Record::Acknowledge { sequences }) => {
+						for sequence in sequences {
+							pending.remove(&sequence);
+						}
+					}
+					// a crash can leave the last record half-written, but it was never acknowledged to the client, so it is safe to ignore
+					Err(error) if index + 1 == line_count => warn!("ignoring an incomplete record at the end of {}: {error}", self.path.display()),
+					Err(error) => {
+						return Err(io::Error::new(
+							io::ErrorKind::InvalidData,
+							format!("corrupt record in {}: {error}", self.path.display()),
+						))
+					}
+				}
+			}
+		}
+
+		let pending_lines = pending
+			.iter()
+			.map(|(sequence, (id, request))| {
+				Ok((
+					*sequence,
+					to_line(&Record::Enqueue {
+						sequence: *sequence,
+						id,
+						request,
+					})?,
+				))
+			})
+			.collect::<io::Result<BTreeMap<_, _>>>()?;
+
+		let writer = JournalWriter {
+			file: compact(&self.path, pending_lines.values())?,
+			path: self.path,
+			sync: self.sync,
+			compact_after: self.compact_after,
+			pending: pending_lines,
+			acknowledged_since_compaction: 0,
+		};
+
+		let entries = pending
+			.into_iter()
+			.map(|(sequence, (id, request))| JournalEntry { sequence, id, request })
+			.collect();
+
+		Ok((writer, entries))
+	}
+}
+
+enum Command {
+	Enqueue {
+		sequence: u64,
+		line: Vec<u8>,
+		responder: oneshot::Sender<io::Result<()>>,
+	},
+	Acknowledge {
+		sequences: Vec<u64>,
+	},
+}
+
+/// Owns the journal's file, and writes every record to it from a blocking task
+struct JournalWriter {
+	path: PathBuf,
+	file: File,
+	sync: bool,
+	compact_after: usize,
+	/// The records of every request that has not been acknowledged, which are all that is kept when the journal is compacted
+	pending: BTreeMap<u64, Vec<u8>>,
+	acknowledged_since_compaction: usize,
+}
+
+impl JournalWriter {
+	/// Write commands until every `OpenFileJournal` for this journal, along with all of it's receipts, have been dropped
+	fn run(mut self, mut command_receiver: mpsc::UnboundedReceiver<Command>) {
+		while let Some(command) = command_receiver.blocking_recv() {
+			let mut commands = Vec::from([command]);
+
+			while let Ok(command) = command_receiver.try_recv() {
+				commands.push(command);
+			}
+
+			self.write(commands);
+
+			if self.acknowledged_since_compaction >= self.compact_after {
+				match compact(&self.path, self.pending.values()) {
+					Ok(file) => {
+						self.file = file;
+						self.acknowledged_since_compaction = 0;
+					}
+					Err(error) => error!("failed to compact the journal at {}: {error}", self.path.display()),
+				}
+			}
+		}
+	}
+
+	/// Write a batch of commands with a single sync, and only then let the requests that were recorded know that they are durable
+	fn write(&mut self, commands: Vec<Command>) {
+		let mut buffer = Vec::new();
+		let mut enqueued = Vec::new();
+		let mut acknowledged = Vec::new();
+
+		for command in commands {
+			match command {
+				Command::Enqueue { sequence, line, responder } => {
+					buffer.extend_from_slice(&line);
+					enqueued.push((sequence, line, responder));
+				}
+				Command::Acknowledge { sequences } => match to_line(&Record::<(), ()>::Acknowledge { sequences: sequences.clone() }) {
+					Ok(line) => {
+						buffer.extend_from_slice(&line);
+						acknowledged.extend(sequences);
+					}
+					Err(error) => error!("failed to acknowledge requests {sequences:?} in the journal, so they will be replayed after a restart: {error}"),
+				},
+			}
+		}
+
+		let length = self.file.metadata().map(|metadata| metadata.len()).ok();
+		let write_res = self
+			.file
+			.write_all(&buffer)
+			.and_then(|_| if self.sync { self.file.sync_data() } else { Ok(()) });
+
+		if let Err(error) = &write_res {
+			if !acknowledged.is_empty() {
+				error!("failed to acknowledge requests {acknowledged:?} in the journal, so they will be replayed after a restart: {error}");
+			}
+
+			self.discard_failed_write(length);
+		} else {
+			for (sequence, line, _) in &mut enqueued {
+				self.pending.insert(*sequence, mem::take(line));
+			}
+
+			for sequence in &acknowledged {
+				self.pending.remove(sequence);
+			}
+
+			self.acknowledged_since_compaction += acknowledged.len();
+		}
+
+		for (_, _, responder) in enqueued {
+			let _ = responder.send(match &write_res {
+				Ok(_) => Ok(()),
+				Err(error) => Err(io::Error::new(error.kind(), error.to_string())),
+			});
+		}
+	}
+
+	/// Remove whatever part of a failed write made it into the file, so that a torn record isn't left for later records to be appended after,
+	/// which would make the journal unreadable. If the file can't be truncated back to `length`, it is rewritten from the pending requests.
+	fn discard_failed_write(&mut self, length: Option<u64>) {
+		let truncate_res = match length {
+			Some(length) => self.file.set_len(length),
+			None => Err(io::Error::other("the length of the journal before the write is unknown")),
+		};
+
+		if let Err(error) = truncate_res {
+			warn!(
+				"failed to truncate the journal at {} after a failed write, so it will be compacted instead: {error}",
+				self.path.display()
+			);
+
+			match compact(&self.path, self.pending.values()) {
+				Ok(file) => {
+					self.file = file;
+					self.acknowledged_since_compaction = 0;
+				}
+				Err(error) => error!(
+					"failed to remove a partially written record from the journal at {}: {error}",
+					self.path.display()
+				),
+			}
+		}
+	}
+}
+
+pub(crate) struct OpenFileJournal<Id, Request> {
+	command_sender: mpsc::UnboundedSender<Command>,
+	next_sequence: AtomicU64,
+	marker: PhantomData<fn(&Id, &Request)>,
+}
+
+impl<Id, Request> Acknowledge for OpenFileJournal<Id, Request>
+where
+	Id: Serialize,
+	Request: Serialize,
+{
+	fn acknowledge(&self, sequences: &[u64]) -> io::Result<()> {
+		self.command_sender
+			.send(Command::Acknowledge { sequences: sequences.to_vec() })
+			.map_err(|_| writer_stopped())
+	}
+}
+
+impl<Id, Request> RequestJournal<Id, Request> for OpenFileJournal<Id, Request>
+where
+	Id: Serialize,
+	Request: Serialize,
+{
+	fn record(&self, id: &Id, request: &Request) -> BoxFuture<io::Result<u64>> {
+		let sequence = self.next_sequence.fetch_add(1, Ordering::Relaxed);
+		let (responder, receiver) = oneshot::channel();
+
+		let send_res = to_line(&Record::Enqueue { sequence, id, request }).and_then(|line| {
+			self.command_sender
+				.send(Command::Enqueue { sequence, line, responder })
+				.map_err(|_| writer_stopped())
+		});
+
+		Box::pin(async move {
+			send_res?;
+			receiver.await.map_err(|_| writer_stopped())??;
+
+			Ok(sequence)
+		})
+	}
+}
+
+/// Rewrite the journal with only `lines` by writing them to a new file and swapping it in, returning the new file opened for appending
+fn compact<'a>(path: &Path, lines: impl Iterator<Item = &'a Vec<u8>>) -> io::Result<File> {
+	let mut compacted_path = path.to_owned().into_os_string();
+	compacted_path.push(".compacting");
+
+	let mut compacted_file = File::create(&compacted_path)?;
+
+	for line in lines {
+		compacted_file.write_all(line)?;
+	}
+
+	compacted_file.sync_all()?;
+	fs::rename(&compacted_path, path)?;
+
+	OpenOptions::new().append(true).open(path)
+}
+
+fn to_line<Id: Serialize, Request: Serialize>(record: &Record<Id, Request>) -> io::Result<Vec<u8>> {
+	let mut line = serde_json::to_vec(record)?;
+	line.push(b'\n');
+
+	Ok(line)
+}
+
+fn writer_stopped() -> io::Error {
+	io::Error::new(io::ErrorKind::BrokenPipe, "the journal's writer has stopped")
+}
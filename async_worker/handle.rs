@@ -1,13 +1,17 @@
 use std::future::{pending, Future};
+use tokio::sync::mpsc;
 
 use crate::worker::Worker;
 
 pub enum SendResult<T> {
 	Sent,
+	/// The handle can't take any more responses. It is dropped, and the response is kept for polling.
 	Closed(T),
+	/// The handle couldn't take the response this time, but is still open. The response is kept, and sent again before the next response.
 	Failed(T),
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum DropReason {
 	Ceeded,
 	WorkerTerminated,
@@ -40,6 +44,89 @@ where
 	async fn will_drop(&mut self, _: DropReason) {}
 }
 
+/// A handle that sends responses into a tokio `mpsc` channel, and optionally receives requests from another one. See also `Queue::subscribe`.
+///
+/// If the response channel is full, the worker will wait for room before handling it's next request. Once the response channel is closed, the
+/// handle is closed as well.
+pub struct ChannelHandle<Request, Response> {
+	sender: mpsc::Sender<Response>,
+	receiver: Option<mpsc::Receiver<Request>>,
+}
+
+impl<Request, Response> ChannelHandle<Request, Response> {
+	/// Create a handle that only sends responses. Requests must still be delivered to the worker via the queue.
+	pub fn new(sender: mpsc::Sender<Response>) -> ChannelHandle<Request, Response> {
+		ChannelHandle { sender, receiver: None }
+	}
+
+	/// Create a handle that sends responses to `sender` and enqueues every request that comes through `receiver`. The handle is closed when
+	/// either channel is closed.
+	pub fn with_receiver(sender: mpsc::Sender<Response>, receiver: mpsc::Receiver<Request>) -> ChannelHandle<Request, Response> {
+		ChannelHandle {
+			sender,
+			receiver: Some(receiver),
+		}
+	}
+}
+
+impl<Request, Response> WorkerHandle<Request, Response> for ChannelHandle<Request, Response>
+where
+	Request: Send,
+	Response: Send,
+{
+	async fn recv(&mut self) -> Option<Request> {
+		match &mut self.receiver {
+			Some(receiver) => receiver.recv().await,
+			None => pending().await,
+		}
+	}
+
+	async fn send(&mut self, response: Response) -> SendResult<Response> {
+		match self.sender.send(response).await {
+			Ok(_) => SendResult::Sent,
+			Err(error) => SendResult::Closed(error.0),
+		}
+	}
+
+	// dropping the sender is enough to let the receiving side know that no more responses are coming
+	async fn will_drop(&mut self, _: DropReason) {}
+}
+
+/// The handle that a worker is sending it's responses to. This is either a handle that was registered via `Queue::register_handle`, or a
+/// subscription from `Queue::subscribe`, which is kept apart so that it can replace a handle of any type.
+pub enum AttachedHandle<Request, Response, H> {
+	Registered(H),
+	Subscription(ChannelHandle<Request, Response>),
+}
+
+impl<Request, Response, H> WorkerHandle<Request, Response> for AttachedHandle<Request, Response, H>
+where
+	Request: Send,
+	Response: Send,
+	H: WorkerHandle<Request, Response> + Send,
+{
+	async fn recv(&mut self) -> Option<Request> {
+		match self {
+			Self::Registered(handle) => handle.recv().await,
+			Self::Subscription(handle) => handle.recv().await,
+		}
+	}
+
+	async fn send(&mut self, response: Response) -> SendResult<Response> {
+		match self {
+			Self::Registered(handle) => handle.send(response).await,
+			Self::Subscription(handle) => handle.send(response).await,
+		}
+	}
+
+	async fn will_drop(&mut self, reason: DropReason) {
+		match self {
+			Self::Registered(handle) => handle.will_drop(reason).await,
+			Self::Subscription(handle) => handle.will_drop(reason).await,
+		}
+	}
+}
+
 pub async fn recv_from_handle<W, H>(handle: Option<&mut H>) -> Option<W::Request>
 where
 	W: Worker,
@@ -0,0 +1,56 @@
+use log::error;
+use std::{io, sync::Arc};
+
+use crate::address::BoxFuture;
+
+/// A request that was recorded in the journal but never acknowledged.
+#[cfg(feature = "journal")]
+pub struct JournalEntry<Id, Request> {
+	pub sequence: u64,
+	pub id: Id,
+	pub request: Request,
+}
+
+/// Marks requests as handled, so that they will not be replayed. This must not block, so journals that write acknowledgements to disk should do
+/// so in the background.
+pub trait Acknowledge: Send + Sync {
+	fn acknowledge(&self, sequences: &[u64]) -> io::Result<()>;
+}
+
+/// A write-ahead log of the requests that have been accepted by a queue. See `QueueBuilder::build_journaled`.
+pub trait RequestJournal<Id, Request>: Acknowledge {
+	/// Durably record a request before it is delivered to it's worker, returning the sequence number that it can later be acknowledged by.
+	fn record(&self, id: &Id, request: &Request) -> BoxFuture<io::Result<u64>>;
+}
+
+/// Travels alongside a journaled request, and acknowledges it once it has been dealt with. Because a receipt that is dropped without being
+/// acknowledged leaves it's request in the journal, requests that are lost to a crash or shutdown will be replayed.
+pub struct Receipt {
+	journal: Arc<dyn Acknowledge>,
+	sequences: Vec<u64>,
+}
+
+impl Receipt {
+	pub fn new(journal: Arc<dyn Acknowledge>, sequence: u64) -> Receipt {
+		Receipt {
+			journal,
+			sequences: Vec::from([sequence]),
+		}
+	}
+
+	/// Combine the receipts of two requests that were merged into one.
+	pub fn merge(mut self, other: Receipt) -> Receipt {
+		self.sequences.extend(other.sequences);
+
+		self
+	}
+
+	pub fn acknowledge(self) {
+		if let Err(error) = self.journal.acknowledge(&self.sequences) {
+			error!(
+				"failed to acknowledge requests {:?} in the journal, so they will be replayed after a restart: {error}",
+				self.sequences
+			);
+		}
+	}
+}
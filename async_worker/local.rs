@@ -0,0 +1,252 @@
+use log::error;
+use std::{fmt::Debug, future::Future, hash::Hash, sync::Arc, thread};
+use tokio::{
+	runtime, select,
+	sync::{mpsc, oneshot},
+	task::{spawn_local, LocalSet},
+};
+
+use crate::{address::Mailbox, handle::NoopHandle, queue::Queue, sharded::get_shard_index, worker::Worker, Error, Result};
+
+/// A queue of workers that are not `Send`. See `LocalWorker` and `QueueBuilder::build_local`.
+///
+/// Each response is a `Result`, which is an `Error::LocalWorkerStopped` if the worker stopped before it could respond.
+pub type LocalQueue<W, H = NoopHandle> = Queue<Local<W>, H>;
+
+/// The same as `Worker`, except that neither the worker nor the futures it returns need to be `Send`. This allows workers to hold onto things like
+/// `Rc`s, or database connections and scripting engines that must stay on a single thread.
+///
+/// Local workers are driven on a pool of single-threaded runtimes, with each worker pinned to one of them based on it's id.
+pub trait LocalWorker
+where
+	Self: Sized + 'static,
+{
+	type Context: 'static + Send + Sync + Sized + Clone;
+	type Request: 'static + Send + Sized;
+	type Response: 'static + Send + Sized;
+	type Id: 'static + Hash + PartialOrd + Eq + Clone + Send + Sync + Debug;
+
+	/// See `Worker::create`
+	fn create(id: &Self::Id, context: Self::Context, mailbox: &Mailbox<Local<Self>>) -> impl Future<Output = Self>;
+
+	/// See `Worker::handle`
+	fn handle(&mut self, request: Self::Request, mailbox: &Mailbox<Local<Self>>) -> impl Future<Output = Self::Response>;
+
+	/// See `Worker::cancelled`
+	fn cancelled(&mut self, mailbox: &Mailbox<Local<Self>>) -> impl Future<Output = ()> {
+		let _ = mailbox;
+
+		async {}
+	}
+
+	/// See `Worker::evicted`
+	fn evicted(&mut self, mailbox: &Mailbox<Local<Self>>) -> impl Future<Output = ()> {
+		let _ = mailbox;
+
+		async {}
+	}
+
+	/// See `Worker::destroy`
+	fn destroy(self) -> impl Future<Output = ()>;
+}
+
+enum Command<W: LocalWorker> {
+	Handle {
+		request: W::Request,
+		mailbox: Mailbox<Local<W>>,
+		responder: oneshot::Sender<W::Response>,
+	},
+	Evicted {
+		mailbox: Mailbox<Local<W>>,
+		responder: oneshot::Sender<()>,
+	},
+	Destroy {
+		responder: oneshot::Sender<()>,
+	},
+}
+
+struct SpawnLocalMessage<W: LocalWorker> {
+	id: W::Id,
+	context: W::Context,
+	mailbox: Mailbox<Local<W>>,
+	command_receiver: mpsc::UnboundedReceiver<Command<W>>,
+	created_responder: oneshot::Sender<()>,
+}
+
+/// The context of a `Local` worker. Contains the context that was given to the queue when it was built, along with the runtimes that local
+/// workers are driven on.
+pub struct LocalContext<W: LocalWorker> {
+	context: W::Context,
+	shards: Arc<Vec<mpsc::UnboundedSender<SpawnLocalMessage<W>>>>,
+}
+
+impl<W: LocalWorker> LocalContext<W> {
+	pub(crate) fn new(context: W::Context, thread_count: usize) -> LocalContext<W> {
+		let shards = (0..thread_count.max(1)).map(|index| spawn_shard::<W>(index)).collect();
+
+		LocalContext {
+			context,
+			shards: Arc::new(shards),
+		}
+	}
+
+	fn get_shard(&self, id: &W::Id) -> &mpsc::UnboundedSender<SpawnLocalMessage<W>> {
+		&self.shards[get_shard_index(id, self.shards.len())]
+	}
+}
+
+impl<W: LocalWorker> Clone for LocalContext<W> {
+	fn clone(&self) -> Self {
+		LocalContext {
+			context: self.context.clone(),
+			shards: self.shards.clone(),
+		}
+	}
+}
+
+/// A `Send` stand-in for a `LocalWorker`, which forwards all of it's calls to the runtime that the local worker lives on.
+pub struct Local<W: LocalWorker> {
+	command_sender: mpsc::UnboundedSender<Command<W>>,
+}
+
+impl<W: LocalWorker> Worker for Local<W> {
+	type Context = LocalContext<W>;
+	type Request = W::Request;
+	/// An `Error::LocalWorkerStopped` is given in place of the response if the local worker stopped before it could respond, such as by panicking
+	type Response = Result<W::Response>;
+	type Id = W::Id;
+
+	async fn create(id: &Self::Id, context: Self::Context, mailbox: &Mailbox<Self>) -> Self {
+		let (command_sender, command_receiver) = mpsc::unbounded_channel();
+		let (created_responder, created_receiver) = oneshot::channel();
+
+		let send_res = context.get_shard(id).send(SpawnLocalMessage {
+			id: id.clone(),
+			context: context.context.clone(),
+			mailbox: mailbox.clone(),
+			command_receiver,
+			created_responder,
+		});
+
+		if send_res.is_err() || created_receiver.await.is_err() {
+			error!("local worker {id:?} failed to be created; it's runtime appears to have stopped");
+		}
+
+		Local { command_sender }
+	}
+
+	async fn handle(&mut self, request: Self::Request, mailbox: &Mailbox<Self>) -> Self::Response {
+		let (responder, receiver) = oneshot::channel();
+
+		let _ = self.command_sender.send(Command::Handle {
+			request,
+			mailbox: mailbox.clone(),
+			responder,
+		});
+
+		receiver.await.map_err(|_| {
+			error!("local worker {:?} stopped while handling a request", mailbox.id());
+
+			Error::LocalWorkerStopped
+		})
+	}
+
+	async fn evicted(&mut self, mailbox: &Mailbox<Self>) {
+		let (responder, receiver) = oneshot::channel();
+
+		if self
+			.command_sender
+			.send(Command::Evicted {
+				mailbox: mailbox.clone(),
+				responder,
+			})
+			.is_ok()
+		{
+			let _ = receiver.await;
+		}
+	}
+
+	async fn destroy(self) {
+		let (responder, receiver) = oneshot::channel();
+
+		if self.command_sender.send(Command::Destroy { responder }).is_ok() {
+			let _ = receiver.await;
+		}
+	}
+}
+
+fn spawn_shard<W: LocalWorker>(index: usize) -> mpsc::UnboundedSender<SpawnLocalMessage<W>> {
+	let (spawn_sender, mut spawn_receiver) = mpsc::unbounded_channel::<SpawnLocalMessage<W>>();
+
+	thread::Builder::new()
+		.name(format!("async_worker-local-{index}"))
+		.spawn(move || {
+			let runtime = runtime::Builder::new_current_thread()
+				.enable_all()
+				.build()
+				.expect("failed to build a runtime for local workers");
+			let local_set = LocalSet::new();
+
+			local_set.block_on(&runtime, async move {
+				while let Some(message) = spawn_receiver.recv().await {
+					spawn_local(drive_local_worker(message));
+				}
+			});
+
+			// let workers that are still being destroyed finish up
+			runtime.block_on(local_set);
+		})
+		.expect("failed to spawn a thread for local workers");
+
+	spawn_sender
+}
+
+async fn drive_local_worker<W: LocalWorker>(message: SpawnLocalMessage<W>) {
+	let SpawnLocalMessage {
+		id,
+		context,
+		mailbox,
+		mut command_receiver,
+		created_responder,
+	} = message;
+
+	let mut worker = W::create(&id, context, &mailbox).await;
+	let _ = created_responder.send(());
+
+	while let Some(command) = command_receiver.recv().await {
+		match command {
+			Command::Handle {
+				request,
+				mailbox,
+				mut responder,
+			} => {
+				// the `Local` side drops it's receiver when the request is cancelled
+				let response = select! {
+					biased;
+
+					response = worker.handle(request, &mailbox) => Some(response),
+					_ = responder.closed() => None,
+				};
+
+				match response {
+					Some(response) => {
+						let _ = responder.send(response);
+					}
+					None => worker.cancelled(&mailbox).await,
+				}
+			}
+			Command::Evicted { mailbox, responder } => {
+				worker.evicted(&mailbox).await;
+				let _ = responder.send(());
+			}
+			Command::Destroy { responder } => {
+				worker.destroy().await;
+				let _ = responder.send(());
+
+				return;
+			}
+		}
+	}
+
+	worker.destroy().await;
+}
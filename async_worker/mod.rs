@@ -1,13 +1,36 @@
-use log::error;
 use std::fmt::Debug;
 use thiserror::Error;
 
+mod activity;
+mod address;
+#[cfg(feature = "axum")]
+mod axum_handle;
+mod channel;
+mod concurrent;
+#[cfg(feature = "journal")]
+mod file_journal;
 mod handle;
+mod journal;
+mod local;
 mod queue;
+mod schedule;
+mod sharded;
+#[cfg(feature = "testing")]
+pub mod testing;
+mod ticket;
 mod worker;
 
-pub use handle::{DropReason, NoopHandle, SendResult, WorkerHandle};
-pub use queue::{Queue, QueueBuilder};
+pub use address::{Address, Mailbox};
+#[cfg(feature = "axum")]
+pub use axum_handle::{SseHandle, WebSocketHandle, CEEDED_CLOSE_CODE};
+pub use concurrent::{Concurrent, ConcurrentQueue, ConcurrentWorker};
+#[cfg(feature = "journal")]
+pub use file_journal::FileJournal;
+pub use handle::{ChannelHandle, DropReason, NoopHandle, SendResult, WorkerHandle};
+pub use local::{Local, LocalContext, LocalQueue, LocalWorker};
+pub use queue::{Backpressure, Merge, NoMerge, Queue, QueueBuilder, WorkerOverflow};
+pub use sharded::ShardedQueue;
+pub use ticket::Ticket;
 pub use worker::Worker;
 
 #[derive(Debug, Error)]
@@ -16,6 +39,10 @@ pub enum Error {
 	#[error("Worker is at capacity, meaning that the number of pending operations has reached it's configued limit. This limit can be adjusted via QueueBuilder::max_length")]
 	WorkerAtCapacity,
 
+	/// Thrown when a new worker is needed, but the queue has reached it's maximum number of workers and is configured to reject new ones.
+	#[error("The queue has reached it's maximum number of workers. This limit can be adjusted via QueueBuilder::max_workers")]
+	TooManyWorkers,
+
 	/// Thrown when there is no worker available to complete the requested operation
 	#[error("No worker exists for the given id")]
 	NoWorker,
@@ -29,6 +56,14 @@ pub enum Error {
 	#[error("The worker was terminated while this operation was in progress")]
 	WorkerTerminated,
 
+	/// Given in place of a response when a `LocalWorker` stopped before it finished handling the request, usually because it panicked.
+	#[error("The local worker stopped before it finished handling the request")]
+	LocalWorkerStopped,
+
+	/// Thrown when a request could not be recorded in the journal, in which case it was not enqueued. See `QueueBuilder::build_journaled`.
+	#[error("Failed to record the request in the journal: {0}")]
+	Journal(std::io::Error),
+
 	/// Thrown when an operation timed out. Only thrown in the `Queue::*_while` methods: `Queue::poll_while` and `Queue::poll_many_while`
 	#[error("This operation timed out")]
 	Timeout,
@@ -1,21 +1,126 @@
 use dashmap::DashMap;
-use log::error;
-use std::time::Duration;
+use log::{debug, error};
+use std::{
+	collections::VecDeque,
+	num::NonZeroUsize,
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Arc, Mutex,
+	},
+	thread,
+	time::Duration,
+};
 use tokio::{
+	runtime::Handle,
 	select,
 	sync::{mpsc, oneshot},
-	time::sleep,
+	time::{sleep, Instant},
 };
+use tokio_stream::{wrappers::ReceiverStream, Stream};
 
 use crate::{
+	activity::ActivityOrder,
+	address::{Address, BoxFuture, Route},
+	channel::{channel, Sender, TrySendError},
 	handle::{NoopHandle, WorkerHandle},
+	journal::{Acknowledge, Receipt, RequestJournal},
+	local::{LocalContext, LocalQueue, LocalWorker},
+	sharded::ShardedQueue,
+	ticket::Ticket,
 	worker::{drive_workers, InternalPollResponse, SpawnMessage, TaskMessage, Worker},
 	Error, Result,
 };
 
+#[cfg(feature = "journal")]
+use crate::{file_journal::FileJournal, journal::JournalEntry};
+#[cfg(feature = "journal")]
+use log::info;
+#[cfg(feature = "journal")]
+use serde::{de::DeserializeOwned, Serialize};
+#[cfg(feature = "journal")]
+use std::{collections::HashMap, io};
+
+/// What `Queue::enqueue` should do when a worker has reached it's `QueueBuilder::max_length`.
+#[derive(Debug, Clone, Copy, Default)]
+pub enum Backpressure {
+	/// Immediately fail with `Error::WorkerAtCapacity`. This is the default.
+	#[default]
+	Reject,
+	/// Wait for up to the specified duration for the worker to make room for the request. If no room is made in that time,
+	/// `Error::WorkerAtCapacity` is thrown.
+	Wait(Duration),
+	/// Drop the oldest request that is still waiting to be handled in order to make room for the new one. Requests that are already being handled
+	/// are never dropped. If there are no pending requests to drop (for example, the worker's queue is full of polls), `Error::WorkerAtCapacity`
+	/// is thrown.
+	DropOldest,
+}
+
+/// What a queue should do when a new worker is needed, but it has already reached it's `QueueBuilder::max_workers`.
+#[derive(Debug, Clone, Copy, Default)]
+pub enum WorkerOverflow {
+	/// Terminate the worker that has gone the longest without activity to make room for the new one. The evicted worker is terminated just as if
+	/// `Queue::terminate` had been called on it, except that `Worker::evicted` is called before `Worker::destroy`. This is the default.
+	#[default]
+	EvictLeastRecentlyActive,
+	/// Do not create the new worker, throwing an `Error::TooManyWorkers` instead.
+	Reject,
+}
+
+/// A function that can merge two requests into one. Used by `QueueBuilder::coalesce`.
+pub trait Merge<Request> {
+	fn into_merge(self) -> Option<Box<dyn Fn(Request, Request) -> Request + Send + Sync>>;
+}
+
+/// Signifies that no merge function has been supplied to the `QueueBuilder`.
+pub struct NoMerge;
+
+impl<Request> Merge<Request> for NoMerge {
+	fn into_merge(self) -> Option<Box<dyn Fn(Request, Request) -> Request + Send + Sync>> {
+		None
+	}
+}
+
+impl<Request, F> Merge<Request> for F
+where
+	F: Fn(Request, Request) -> Request + Send + Sync + 'static,
+{
+	fn into_merge(self) -> Option<Box<dyn Fn(Request, Request) -> Request + Send + Sync>> {
+		Some(Box::new(self))
+	}
+}
+
+#[derive(Debug, Clone, Copy)]
+enum BackpressureOption {
+	Strategy(Backpressure),
+	Coalesce,
+}
+
+enum BackpressureStrategy<Request> {
+	Reject,
+	Wait(Duration),
+	DropOldest,
+	Coalesce(Arc<dyn Fn(Request, Request) -> Request + Send + Sync>),
+}
+
+impl<Request> Clone for BackpressureStrategy<Request> {
+	fn clone(&self) -> Self {
+		match self {
+			BackpressureStrategy::Reject => BackpressureStrategy::Reject,
+			BackpressureStrategy::Wait(duration) => BackpressureStrategy::Wait(*duration),
+			BackpressureStrategy::DropOldest => BackpressureStrategy::DropOldest,
+			BackpressureStrategy::Coalesce(merge) => BackpressureStrategy::Coalesce(merge.clone()),
+		}
+	}
+}
+
+#[derive(Clone, Copy)]
 struct QueueOptions {
 	max_length: usize,
 	terminate_worker_after: Duration,
+	backpressure: BackpressureOption,
+	max_workers: Option<(usize, WorkerOverflow)>,
+	local_threads: usize,
+	shards: usize,
 }
 
 impl Default for QueueOptions {
@@ -23,23 +128,38 @@ impl Default for QueueOptions {
 		QueueOptions {
 			max_length: 5,
 			terminate_worker_after: Duration::from_secs(60 * 20),
+			backpressure: BackpressureOption::Strategy(Backpressure::Reject),
+			max_workers: None,
+			local_threads: thread::available_parallelism().map(NonZeroUsize::get).unwrap_or(1),
+			shards: thread::available_parallelism().map(NonZeroUsize::get).unwrap_or(1),
 		}
 	}
 }
 
-#[derive(Default)]
-pub struct QueueBuilder {
+pub struct QueueBuilder<M = NoMerge> {
 	options: QueueOptions,
+	shard_runtimes: Vec<Handle>,
+	merge: M,
 }
 
-impl QueueBuilder {
+impl Default for QueueBuilder {
+	fn default() -> Self {
+		QueueBuilder {
+			options: QueueOptions::default(),
+			shard_runtimes: Vec::new(),
+			merge: NoMerge,
+		}
+	}
+}
+
+impl<M> QueueBuilder<M> {
 	/// The maxium length of the queue for a given worker. If `length` tasks are enqueued before the worker has an opportunity to get to them,
 	/// the `length + 1` enqueue call will yield `EnqueueResult::WorkerAtCapacity`
 	///
 	/// It is not just `Queue::enqueue` that is held to this standard. `Queue::attach_waiter`, `Queue::poll`, and `Queue::poll_until` will not
 	/// perform their respective operations if the queue is full. Additionally, if a single `Worker::handle` call is taking a long time, the
 	/// aforementinoed operations contribute to the queue reaching it's max length.
-	pub fn max_length(mut self, length: usize) -> QueueBuilder {
+	pub fn max_length(mut self, length: usize) -> QueueBuilder<M> {
 		self.options.max_length = length;
 
 		self
@@ -48,119 +168,359 @@ impl QueueBuilder {
 	/// The amount of inactivity after which a worker is automatically terminated. Defaults to 20 minutes.
 	///
 	/// NOTE: polling is considered to be activity
-	pub fn terminate_worker_after(mut self, duration: Duration) -> QueueBuilder {
+	pub fn terminate_worker_after(mut self, duration: Duration) -> QueueBuilder<M> {
 		self.options.terminate_worker_after = duration;
 
 		self
 	}
 
-	pub fn build<W: Worker + Send + 'static>(self, context: W::Context) -> Queue<W> {
-		Queue::new(self.options, context)
+	/// What to do when `Queue::enqueue` is called on a worker that has reached it's max length (see `QueueBuilder::max_length`). Defaults to
+	/// `Backpressure::Reject`.
+	///
+	/// Only `Queue::enqueue` is subject to this strategy. Polls and handle registrations on a full worker will always be rejected.
+	pub fn backpressure(mut self, backpressure: Backpressure) -> QueueBuilder<M> {
+		self.options.backpressure = BackpressureOption::Strategy(backpressure);
+
+		self
+	}
+
+	/// When `Queue::enqueue` is called on a worker that has reached it's max length, merge the new request into the most recent pending request
+	/// via `merge(pending, new)`, instead of rejecting it. Useful for collapsing rapid-fire events, such as text input changes, into the latest one.
+	///
+	/// If there are no pending requests to merge into (for example, the worker's queue is full of polls), `Error::WorkerAtCapacity` is thrown.
+	///
+	/// This overrides any strategy set via `QueueBuilder::backpressure`, and is overridden by any later calls to it.
+	pub fn coalesce<F>(self, merge: F) -> QueueBuilder<F> {
+		QueueBuilder {
+			options: QueueOptions {
+				backpressure: BackpressureOption::Coalesce,
+				..self.options
+			},
+			shard_runtimes: self.shard_runtimes,
+			merge,
+		}
+	}
+
+	/// The maximum number of workers that can exist at once. When a worker needs to be created beyond this limit, `overflow` decides whether an
+	/// existing worker is evicted or the new worker is rejected. By default, there is no limit.
+	///
+	/// Workers that are created concurrently may briefly exceed this limit.
+	pub fn max_workers(mut self, count: usize, overflow: WorkerOverflow) -> QueueBuilder<M> {
+		self.options.max_workers = Some((count, overflow));
+
+		self
+	}
+
+	/// The number of threads that local workers are driven on. Each thread runs it's own single-threaded runtime, and every worker is pinned
+	/// to one of them based on it's id. Defaults to the available parallelism. Only used by `QueueBuilder::build_local`.
+	pub fn local_threads(mut self, count: usize) -> QueueBuilder<M> {
+		self.options.local_threads = count;
+
+		self
+	}
+
+	/// The number of shards that a `ShardedQueue` splits it's workers between. Defaults to the available parallelism. Only used by
+	/// `QueueBuilder::build_sharded`.
+	pub fn shards(mut self, count: usize) -> QueueBuilder<M> {
+		self.options.shards = count;
+
+		self
+	}
+
+	/// Give each shard of a `ShardedQueue` it's own runtime to spawn workers on, instead of spawning them all on the current runtime. There will be
+	/// one shard per runtime, overriding `QueueBuilder::shards`. Only used by `QueueBuilder::build_sharded`.
+	pub fn shard_runtimes(mut self, runtimes: impl IntoIterator<Item = Handle>) -> QueueBuilder<M> {
+		self.shard_runtimes = runtimes.into_iter().collect();
+
+		self
+	}
+
+	pub fn build<W: Worker + Send + 'static>(self, context: W::Context) -> Queue<W>
+	where
+		M: Merge<W::Request>,
+	{
+		let (options, backpressure) = self.into_parts();
+
+		Queue::new(options, backpressure, context)
+	}
+
+	/// The same as `QueueBuilder::build`, but for a queue whose workers can have handles of type `H` registered. See `WorkerHandle`.
+	pub fn build_with_handle<W, H>(self, context: W::Context) -> Queue<W, H>
+	where
+		W: Worker + Send + 'static,
+		H: WorkerHandle<W::Request, W::Response> + Send + 'static,
+		M: Merge<W::Request>,
+	{
+		let (options, backpressure) = self.into_parts();
+
+		Queue::new(options, backpressure, context)
+	}
+
+	/// Build a queue of workers that are not `Send`. See `LocalWorker`.
+	pub fn build_local<W: LocalWorker>(self, context: W::Context) -> LocalQueue<W>
+	where
+		M: Merge<W::Request>,
+	{
+		let (options, backpressure) = self.into_parts();
+		let context = LocalContext::new(context, options.local_threads);
+
+		Queue::new(options, backpressure, context)
+	}
+
+	/// Build a queue that records every enqueued request in `journal` before accepting it. When a worker has dealt with a request, it is marked as
+	/// acknowledged. Requests that were never acknowledged, such as those that were pending when the process crashed, are replayed into their
+	/// workers when the queue is built again with the same journal.
+	///
+	/// Only requests that are delivered via `Queue::enqueue` (or an `Address` or `Mailbox`) are journaled. Replayed requests will not produce
+	/// responses for pollers or handles until they re-attach.
+	#[cfg(feature = "journal")]
+	pub async fn build_journaled<W>(self, context: W::Context, journal: FileJournal) -> io::Result<Queue<W>>
+	where
+		W: Worker + Send + 'static,
+		W::Id: Serialize + DeserializeOwned,
+		W::Request: Serialize + DeserializeOwned,
+		M: Merge<W::Request>,
+	{
+		self.build_journaled_with_handle(context, journal).await
+	}
+
+	/// The same as `QueueBuilder::build_journaled`, but for a queue whose workers can have handles of type `H` registered. See `WorkerHandle`.
+	#[cfg(feature = "journal")]
+	pub async fn build_journaled_with_handle<W, H>(self, context: W::Context, journal: FileJournal) -> io::Result<Queue<W, H>>
+	where
+		W: Worker + Send + 'static,
+		W::Id: Serialize + DeserializeOwned,
+		W::Request: Serialize + DeserializeOwned,
+		H: WorkerHandle<W::Request, W::Response> + Send + 'static,
+		M: Merge<W::Request>,
+	{
+		let (journal, entries) = journal.open::<W::Id, W::Request>().await?;
+		let (options, backpressure) = self.into_parts();
+
+		let queue = Queue::new_on(options, backpressure, context, &Handle::current(), None, Some(Arc::new(journal)));
+		queue.replay(entries).await;
+
+		Ok(queue)
+	}
+
+	/// Build a queue that splits it's workers between independent shards, each with their own spawner and routing table. See `ShardedQueue`.
+	pub fn build_sharded<W: Worker + Send + 'static>(self, context: W::Context) -> ShardedQueue<W>
+	where
+		M: Merge<W::Request>,
+	{
+		self.build_sharded_with_handle(context)
+	}
+
+	/// The same as `QueueBuilder::build_sharded`, but for a queue whose workers can have handles of type `H` registered. See `WorkerHandle`.
+	pub fn build_sharded_with_handle<W, H>(mut self, context: W::Context) -> ShardedQueue<W, H>
+	where
+		W: Worker + Send + 'static,
+		H: WorkerHandle<W::Request, W::Response> + Send + 'static,
+		M: Merge<W::Request>,
+	{
+		let mut runtimes = std::mem::take(&mut self.shard_runtimes);
+		let (mut options, backpressure) = self.into_parts();
+
+		if runtimes.is_empty() {
+			runtimes = vec![Handle::current(); options.shards.max(1)];
+		}
+
+		// the worker limit is for the whole queue, so each shard gets it's share
+		options.max_workers = options.max_workers.map(|(count, overflow)| (count.div_ceil(runtimes.len()), overflow));
+
+		ShardedQueue::new(runtimes, |runtime, address| Queue::new_on(options, backpressure.clone(), context.clone(), runtime, Some(address), None))
+	}
+
+	fn into_parts<Request>(self) -> (QueueOptions, BackpressureStrategy<Request>)
+	where
+		M: Merge<Request>,
+	{
+		let backpressure = match self.options.backpressure {
+			BackpressureOption::Strategy(Backpressure::Reject) => BackpressureStrategy::Reject,
+			BackpressureOption::Strategy(Backpressure::Wait(duration)) => BackpressureStrategy::Wait(duration),
+			BackpressureOption::Strategy(Backpressure::DropOldest) => BackpressureStrategy::DropOldest,
+			BackpressureOption::Coalesce => match self.merge.into_merge() {
+				Some(merge) => BackpressureStrategy::Coalesce(Arc::from(merge)),
+				None => BackpressureStrategy::Reject,
+			},
+		};
+
+		(self.options, backpressure)
 	}
 }
 
-pub struct Queue<W: Worker, H: WorkerHandle<W::Request, W::Response> = NoopHandle> {
+type TaskSender<W, H> = Sender<TaskMessage<<W as Worker>::Request, <W as Worker>::Response, H>>;
+
+struct WorkerEntry<W: Worker, H: WorkerHandle<W::Request, W::Response>> {
+	sender: TaskSender<W, H>,
+	is_evicted: Arc<AtomicBool>,
+}
+
+struct QueueState<W: Worker, H: WorkerHandle<W::Request, W::Response>> {
 	max_length: usize,
+	max_workers: Option<(usize, WorkerOverflow)>,
+	backpressure: BackpressureStrategy<W::Request>,
 	spawn_sender: mpsc::Sender<SpawnMessage<W, H>>,
-	map: DashMap<W::Id, mpsc::Sender<TaskMessage<W::Request, W::Response, H>>>,
+	map: DashMap<W::Id, WorkerEntry<W, H>>,
+	activity_order: Arc<ActivityOrder<W::Id>>,
+	spawn_lock: Mutex<()>,
+	address: Option<Address<W>>,
+	journal: Option<Arc<dyn RequestJournal<W::Id, W::Request>>>,
 	context: W::Context,
 }
 
+pub struct Queue<W: Worker, H: WorkerHandle<W::Request, W::Response> = NoopHandle> {
+	state: Arc<QueueState<W, H>>,
+}
+
 impl<W, H> Queue<W, H>
 where
 	W: Worker + Send + 'static,
-	H: WorkerHandle<W::Request, W::Response> + Send + Sync + 'static,
+	H: WorkerHandle<W::Request, W::Response> + Send + 'static,
 {
-	fn new(options: QueueOptions, context: W::Context) -> Queue<W, H> {
+	fn new(options: QueueOptions, backpressure: BackpressureStrategy<W::Request>, context: W::Context) -> Queue<W, H> {
+		Queue::new_on(options, backpressure, context, &Handle::current(), None, None)
+	}
+
+	/// Create a queue whose workers are spawned on `runtime`. If `address` is supplied, workers will be given it in place of an address for this
+	/// queue, which allows a `ShardedQueue` to hand out an address that covers all of it's shards. If `journal` is supplied, every enqueued
+	/// request is recorded in it.
+	fn new_on(
+		options: QueueOptions,
+		backpressure: BackpressureStrategy<W::Request>,
+		context: W::Context,
+		runtime: &Handle,
+		address: Option<Address<W>>,
+		journal: Option<Arc<dyn RequestJournal<W::Id, W::Request>>>,
+	) -> Queue<W, H> {
 		let (spawn_sender, spawn_receiver) = mpsc::channel(1000);
 
-		tokio::spawn(async move { drive_workers(options.terminate_worker_after, spawn_receiver).await });
+		runtime.spawn(async move { drive_workers(options.terminate_worker_after, spawn_receiver).await });
 
 		Queue {
-			max_length: options.max_length,
-			spawn_sender,
-			map: DashMap::new(),
-			context,
+			state: Arc::new(QueueState {
+				max_length: options.max_length,
+				max_workers: options.max_workers,
+				backpressure,
+				spawn_sender,
+				map: DashMap::new(),
+				activity_order: Arc::new(ActivityOrder::new()),
+				spawn_lock: Mutex::new(()),
+				address,
+				journal,
+				context,
+			}),
 		}
 	}
 
+	/// Get an address for this queue, which workers can use to send requests to it's workers. See `Address`.
+	pub fn address(&self) -> Address<W> {
+		if let Some(address) = &self.state.address {
+			return address.clone();
+		}
+
+		let state: Arc<dyn Route<W>> = self.state.clone();
+
+		Address::new(Arc::downgrade(&state))
+	}
+
 	/// Attach a waiter to the worker referenced by `id`. Waiters always take precident over polling, so if there is an active waiter, all responses will be immediately
 	/// piped to it and poll calls will hang until the the next response after the waiter is dropped.
 	pub fn register_handle(&self, id: &W::Id, handle: H) -> Result<()> {
-		let message = TaskMessage::RegisterHandle { handle };
+		self.send_message(id, TaskMessage::RegisterHandle { handle })
+	}
 
-		let send_res = {
-			match self.map.get(id) {
-				Some(sender) => sender.try_send(message),
-				None => return Err(Error::NoWorker),
-			}
-		};
+	/// Subscribe to the responses of the worker referenced by `id`. The subscription takes the place of a handle, whatever the queue's handle type
+	/// is, so it will ceed any handle that is already registered, and the stream will end once the worker terminates or a newer handle or
+	/// subscription is registered.
+	///
+	/// If a worker does not exist for this id, an `Error::NoWorker` will be thrown.
+	pub fn subscribe(&self, id: &W::Id) -> Result<impl Stream<Item = W::Response>> {
+		let (sender, receiver) = mpsc::channel(self.state.max_length.max(1));
+		self.send_message(id, TaskMessage::Subscribe { sender })?;
 
-		match send_res {
-			Err(mpsc::error::TrySendError::Full(_)) => return Err(Error::WorkerAtCapacity),
-			Err(mpsc::error::TrySendError::Closed(_)) => {
-				self.map.remove(id);
+		Ok(ReceiverStream::new(receiver))
+	}
 
-				return Err(Error::NoWorker);
+	/// Enqueue a new request for the worker referenced by `id` to pick up. The response can be retrived by either polling or attaching a handle.
+	///
+	/// If a worker does not exist for this id, a new worker will be created.
+	///
+	/// Once the worker is ready for a new task, `Worker::handle` will be called with this `request`. The returned `Ticket` can be used to cancel
+	/// the request.
+	pub async fn enqueue(&self, id: &W::Id, request: W::Request) -> Result<Ticket> {
+		let ticket = Ticket::new();
+
+		let sequence = match &self.state.journal {
+			Some(journal) => Some(journal.record(id, &request).await.map_err(Error::Journal)?),
+			None => None,
+		};
+
+		let receipt = sequence.map(|sequence| self.get_receipt(sequence));
+		let deliver_res = self.deliver(
+			id,
+			TaskMessage::Enqueue {
+				request,
+				ticket: Some(ticket.clone()),
+				receipt,
+			},
+			true,
+		);
+
+		if let Err(error) = deliver_res.await {
+			// the request was never accepted, so it shouldn't be replayed
+			if let Some(sequence) = sequence {
+				self.get_receipt(sequence).acknowledge();
 			}
-			_ => (),
+
+			return Err(error);
 		}
 
-		Ok(())
+		Ok(ticket)
 	}
 
-	/// Enqueue a new request for the worker referenced by `id` to pick up. The response can be retrived by either polling or attaching a handle.
+	/// Send a request to the worker referenced by `id` and wait for it's response. Unlike `Queue::enqueue`, the response is returned directly,
+	/// instead of being sent to the worker's handle or poller.
 	///
 	/// If a worker does not exist for this id, a new worker will be created.
 	///
-	/// Once the worker is ready for a new task, `Worker::handle` will be called with this `request`.
-	pub async fn enqueue(&self, id: &W::Id, request: W::Request) -> Result<()> {
-		enum Action<Request> {
-			Close,
-			None,
-			Spawn(Request),
-		}
-
-		let action = {
-			match self.map.get(id) {
-				Some(sender) => match sender.try_send(TaskMessage::Enqueue { request }) {
-					Err(mpsc::error::TrySendError::Full(_)) => return Err(Error::WorkerAtCapacity),
-					Err(mpsc::error::TrySendError::Closed(_)) => Action::Close, // we return close instead of closing right away in order to prevent deadlock
-					_ => Action::None,
-				},
-				None => Action::Spawn(request),
-			}
-		};
+	/// If the worker is terminated before it handles the request, an `Error::WorkerTerminated` will be thrown.
+	pub async fn request(&self, id: &W::Id, request: W::Request) -> Result<W::Response> {
+		let (responder, receiver) = oneshot::channel();
 
-		if let Action::Close = action {
-			self.map.remove(id);
+		self.deliver(id, TaskMessage::Request { request, responder }, true).await?;
 
-			return Err(Error::NoWorker);
-		} else if let Action::Spawn(request) = action {
-			let (sender, receiver) = mpsc::channel(self.max_length);
-			let id_to_insert = id.clone();
+		receiver.await.map_err(|_| Error::WorkerTerminated)
+	}
 
-			// We want to do as little as possible in here because it will keep a mutex locked
-			{
-				self.map.insert(id_to_insert, sender);
-			}
+	/// Enqueue a request for the worker referenced by `id` to pick up once `delay` has passed. See `Queue::enqueue_at`.
+	pub fn enqueue_after(&self, id: &W::Id, request: W::Request, delay: Duration) -> Result<()> {
+		self.enqueue_at(id, request, Instant::now() + delay)
+	}
 
-			let send_res = self
-				.spawn_sender
-				.send(SpawnMessage {
-					id: id.clone(),
-					context: self.context.clone(),
-					message_receiver: receiver,
-					initial_request: request,
-				})
-				.await;
-
-			if let Err(_) = send_res {
-				error!("The spawning task was closed, which should only happen when this object is dropped. It wasn't dropped though, because we are using it");
-			}
-		}
+	/// Enqueue a request for the worker referenced by `id` to pick up once `at` has been reached. The response can be retrived by either polling
+	/// or attaching a handle.
+	///
+	/// Unlike `Queue::enqueue`, this will not create a worker, throwing an `Error::NoWorker` instead. If the worker is terminated before `at`, the
+	/// request is dropped without ever being handled. Delivery of the request is not considered activity (see `QueueBuilder::terminate_worker_after`).
+	pub fn enqueue_at(&self, id: &W::Id, request: W::Request, at: Instant) -> Result<()> {
+		self.send_message(id, TaskMessage::Schedule { request, at })
+	}
 
-		Ok(())
+	/// Every `period`, starting one `period` from now, call `make_request` and enqueue the result for the worker referenced by `id`. If the worker
+	/// is still handling an earlier request when the next one is due, the due request is delivered as soon as the worker is free, and the one after
+	/// it will be due one `period` later.
+	///
+	/// The interval is cancelled when the worker terminates. If a worker does not exist for this id, an `Error::NoWorker` will be thrown. Delivery of
+	/// these requests is not considered activity (see `QueueBuilder::terminate_worker_after`).
+	pub fn register_interval(&self, id: &W::Id, period: Duration, make_request: impl FnMut() -> W::Request + Send + 'static) -> Result<()> {
+		self.send_message(
+			id,
+			TaskMessage::RegisterInterval {
+				period,
+				make_request: Box::new(make_request),
+			},
+		)
 	}
 
 	/// Poll for the next worker response. If there is already a waiting poll, the ongoing poll will immediately throw an `Error::Ceeded`.
@@ -175,18 +535,18 @@ where
 		let message = TaskMessage::Poll { responder };
 
 		let send_res = {
-			let task_sender = match self.map.get(id) {
-				Some(sender) => sender,
+			let entry = match self.state.map.get(id) {
+				Some(entry) => entry,
 				None => return Err(Error::NoWorker),
 			};
 
-			task_sender.try_send(message)
+			entry.sender.try_send(message)
 		};
 
 		match send_res {
-			Err(mpsc::error::TrySendError::Full(_)) => return Err(Error::WorkerAtCapacity),
-			Err(mpsc::error::TrySendError::Closed(_)) => {
-				self.map.remove(id);
+			Err(TrySendError::Full(_)) => return Err(Error::WorkerAtCapacity),
+			Err(TrySendError::Closed(_)) => {
+				self.state.map.remove(id);
 
 				return Err(Error::NoWorker);
 			}
@@ -195,7 +555,7 @@ where
 
 		let response = match receiver.await {
 			Ok(InternalPollResponse::Ok(response)) => response,
-			Ok(InternalPollResponse::Ceeded) => return Err(Error::NoWorker),
+			Ok(InternalPollResponse::Ceeded) => return Err(Error::Ceeded),
 			Ok(InternalPollResponse::WorkerTerminated) => return Err(Error::WorkerTerminated),
 			Err(_) => return Err(Error::NoWorker),
 		};
@@ -215,18 +575,18 @@ where
 		let message = TaskMessage::PollMany { responder };
 
 		let send_res = {
-			let task_sender = match self.map.get(id) {
-				Some(sender) => sender,
+			let entry = match self.state.map.get(id) {
+				Some(entry) => entry,
 				None => return Err(Error::NoWorker),
 			};
 
-			task_sender.try_send(message)
+			entry.sender.try_send(message)
 		};
 
 		match send_res {
-			Err(mpsc::error::TrySendError::Full(_)) => return Err(Error::WorkerAtCapacity),
-			Err(mpsc::error::TrySendError::Closed(_)) => {
-				self.map.remove(id);
+			Err(TrySendError::Full(_)) => return Err(Error::WorkerAtCapacity),
+			Err(TrySendError::Closed(_)) => {
+				self.state.map.remove(id);
 
 				return Err(Error::NoWorker);
 			}
@@ -235,7 +595,7 @@ where
 
 		let response = match receiver.await {
 			Ok(InternalPollResponse::Ok(response)) => response,
-			Ok(InternalPollResponse::Ceeded) => return Err(Error::NoWorker),
+			Ok(InternalPollResponse::Ceeded) => return Err(Error::Ceeded),
 			Ok(InternalPollResponse::WorkerTerminated) => return Err(Error::WorkerTerminated),
 			Err(_) => return Err(Error::NoWorker),
 		};
@@ -275,6 +635,313 @@ where
 	/// This function does not guarantee that the worker is instantly terminated. Instead, is queues a termination to be performed once the async runtime and worker
 	/// have capacity to perform the termination. In other words, this queues a graceful termination instead of forcefully shutting down the worker.
 	pub fn terminate(&self, id: &W::Id) {
-		self.map.remove(id);
+		self.state.map.remove(id);
+	}
+
+	/// Send a message to an existing worker without waiting.
+	fn send_message(&self, id: &W::Id, message: TaskMessage<W::Request, W::Response, H>) -> Result<()> {
+		let send_res = {
+			match self.state.map.get(id) {
+				Some(entry) => entry.sender.try_send(message),
+				None => return Err(Error::NoWorker),
+			}
+		};
+
+		match send_res {
+			Err(TrySendError::Full(_)) => return Err(Error::WorkerAtCapacity),
+			Err(TrySendError::Closed(_)) => {
+				self.state.map.remove(id);
+
+				return Err(Error::NoWorker);
+			}
+			_ => (),
+		}
+
+		Ok(())
+	}
+
+	/// Enqueue the reply to a `Mailbox::ask` for the worker referenced by `id`. Unlike `Queue::enqueue_at`, room is made for the reply according
+	/// to the configured backpressure strategy, so that it isn't lost just because the worker is busy. The worker is not created if it doesn't
+	/// exist, because the worker that asked has since terminated.
+	pub(crate) async fn reply(&self, id: &W::Id, request: W::Request) -> Result<()> {
+		self.deliver(
+			id,
+			TaskMessage::Enqueue {
+				request,
+				ticket: None,
+				receipt: None,
+			},
+			false,
+		)
+		.await
+	}
+
+	/// Deliver a message to the worker referenced by `id`, creating the worker if it does not exist and `create_worker` is set. If the worker
+	/// is full, room is made for the message according to the configured backpressure strategy.
+	async fn deliver(&self, id: &W::Id, message: TaskMessage<W::Request, W::Response, H>, create_worker: bool) -> Result<()> {
+		enum Action<W: Worker, H: WorkerHandle<W::Request, W::Response>> {
+			Close,
+			None,
+			Spawn(TaskMessage<W::Request, W::Response, H>),
+			Wait(TaskSender<W, H>, TaskMessage<W::Request, W::Response, H>, Duration),
+		}
+
+		let action = {
+			match self.state.map.get(id) {
+				Some(entry) => match &self.state.backpressure {
+					// the sender is cloned so that we don't hold on to the map's lock while waiting
+					BackpressureStrategy::Wait(duration) => Action::<W, H>::Wait(entry.sender.clone(), message, *duration),
+					_ => match self.send_with_backpressure(&entry.sender, message) {
+						Err(Error::NoWorker) => Action::Close, // we return close instead of closing right away in order to prevent deadlock
+						Err(error) => return Err(error),
+						Ok(_) => Action::None,
+					},
+				},
+				None if create_worker => Action::Spawn(message),
+				None => return Err(Error::NoWorker),
+			}
+		};
+
+		if let Action::Close = action {
+			self.state.map.remove(id);
+
+			return Err(Error::NoWorker);
+		} else if let Action::Wait(sender, message, duration) = action {
+			let send_res = sender.send_timeout(message, duration).await;
+			drop(sender);
+
+			match send_res {
+				Err(TrySendError::Full(_)) => return Err(Error::WorkerAtCapacity),
+				Err(TrySendError::Closed(_)) => {
+					self.state.map.remove(id);
+
+					return Err(Error::NoWorker);
+				}
+				_ => (),
+			}
+		} else if let Action::Spawn(message) = action {
+			self.spawn(id, vec![message]).await?;
+		}
+
+		Ok(())
+	}
+
+	/// Create a new worker for `id`, which will handle `messages` before anything else.
+	async fn spawn(&self, id: &W::Id, messages: Vec<TaskMessage<W::Request, W::Response, H>>) -> Result<()> {
+		// there must be room for all of the initial messages, even if there are more than the max length
+		let (sender, receiver) = channel(self.state.max_length.max(messages.len()));
+		let is_evicted = Arc::new(AtomicBool::new(false));
+		let id_to_insert = id.clone();
+
+		// the worker will pick these up as soon as it is created
+		for message in messages {
+			if sender.try_send(message).is_err() {
+				return Err(Error::WorkerAtCapacity);
+			}
+		}
+
+		// We want to do as little as possible in here because it will keep a mutex locked
+		let activity = {
+			let _spawn_guard = self.state.spawn_lock.lock().unwrap();
+			self.make_room_for_worker()?;
+
+			self.state.map.insert(
+				id_to_insert,
+				WorkerEntry {
+					sender,
+					is_evicted: is_evicted.clone(),
+				},
+			);
+
+			self.state.activity_order.track(id.clone(), is_evicted)
+		};
+
+		let send_res = self
+			.state
+			.spawn_sender
+			.send(SpawnMessage {
+				id: id.clone(),
+				context: self.state.context.clone(),
+				message_receiver: receiver,
+				address: self.address(),
+				activity,
+			})
+			.await;
+
+		if send_res.is_err() {
+			error!("The spawning task was closed, which should only happen when this object is dropped. It wasn't dropped though, because we are using it");
+		}
+
+		Ok(())
+	}
+
+	fn get_receipt(&self, sequence: u64) -> Receipt {
+		let journal: Arc<dyn Acknowledge> = self.state.journal.clone().expect("receipts are only issued by journaled queues");
+
+		Receipt::new(journal, sequence)
+	}
+
+	/// Deliver requests that were recorded in the journal but never acknowledged, spawning a worker for each id with it's requests in the order
+	/// that they were recorded.
+	#[cfg(feature = "journal")]
+	async fn replay(&self, entries: Vec<JournalEntry<W::Id, W::Request>>) {
+		let mut workers = Vec::<(W::Id, Vec<TaskMessage<W::Request, W::Response, H>>)>::new();
+		let mut worker_indices = HashMap::<W::Id, usize>::new();
+
+		for JournalEntry { sequence, id, request } in entries {
+			let index = *worker_indices.entry(id.clone()).or_insert_with(|| {
+				workers.push((id, Vec::new()));
+
+				workers.len() - 1
+			});
+
+			workers[index].1.push(TaskMessage::Enqueue {
+				request,
+				ticket: None,
+				receipt: Some(self.get_receipt(sequence)),
+			});
+		}
+
+		for (id, messages) in workers {
+			let message_count = messages.len();
+
+			match self.spawn(&id, messages).await {
+				Ok(_) => info!("replayed {message_count} journaled requests for worker {id:?}"),
+				Err(error) => error!("failed to replay {message_count} journaled requests for worker {id:?}, they will be replayed after the next restart: {error}"),
+			}
+		}
+	}
+
+	/// Ensure that there is room for one more worker under `QueueBuilder::max_workers`, evicting the least recently active worker if configured to.
+	fn make_room_for_worker(&self) -> Result<()> {
+		let (max_workers, overflow) = match self.state.max_workers {
+			Some(max_workers) => max_workers,
+			None => return Ok(()),
+		};
+
+		// only workers that are still running are tracked, so ones that terminated on their own don't count against the limit
+		while self.state.activity_order.len() >= max_workers {
+			if let WorkerOverflow::Reject = overflow {
+				return Err(Error::TooManyWorkers);
+			}
+
+			let (id, is_evicted) = match self.state.activity_order.pop_least_recent() {
+				Some(worker) => worker,
+				None => break,
+			};
+
+			// the id may have been given to a newer worker since this one was terminated, which must not be removed in it's place
+			if let Some((_, entry)) = self.state.map.remove_if(&id, |_, entry| Arc::ptr_eq(&entry.is_evicted, &is_evicted)) {
+				debug!("evicting worker {id:?} to make room for a new worker");
+
+				entry.is_evicted.store(true, Ordering::Release);
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Send a message to a worker without waiting, making room for it according to the configured backpressure strategy if the worker is full.
+	fn send_with_backpressure(&self, sender: &TaskSender<W, H>, message: TaskMessage<W::Request, W::Response, H>) -> Result<()> {
+		let send_res = match &self.state.backpressure {
+			BackpressureStrategy::Reject | BackpressureStrategy::Wait(_) => sender.try_send(message),
+			BackpressureStrategy::DropOldest => {
+				let mut dropped_receipt = None;
+
+				let send_res = sender.try_send_or_else(message, |pending, message| {
+					let oldest_index = match pending.iter().position(|message| matches!(message, TaskMessage::Enqueue { .. })) {
+						Some(index) => index,
+						None => return Err(message),
+					};
+
+					if let Some(TaskMessage::Enqueue { receipt, .. }) = pending.remove(oldest_index) {
+						dropped_receipt = receipt;
+					}
+
+					pending.push_back(message);
+					debug!("dropped the oldest pending request to make room for a new one");
+
+					Ok(())
+				});
+
+				// dropping the request was intentional, so it shouldn't be replayed
+				if let Some(receipt) = dropped_receipt {
+					receipt.acknowledge();
+				}
+
+				send_res
+			}
+			BackpressureStrategy::Coalesce(merge) => sender.try_send_or_else(message, |pending, message| coalesce_into(pending, message, merge.as_ref())),
+		};
+
+		match send_res {
+			Err(TrySendError::Full(_)) => Err(Error::WorkerAtCapacity),
+			Err(TrySendError::Closed(_)) => Err(Error::NoWorker),
+			_ => Ok(()),
+		}
+	}
+}
+
+fn coalesce_into<Request, Response, H: WorkerHandle<Request, Response>>(
+	pending: &mut VecDeque<TaskMessage<Request, Response, H>>,
+	message: TaskMessage<Request, Response, H>,
+	merge: &(dyn Fn(Request, Request) -> Request + Send + Sync),
+) -> std::result::Result<(), TaskMessage<Request, Response, H>> {
+	if !matches!(message, TaskMessage::Enqueue { .. }) {
+		return Err(message);
+	}
+
+	let newest_index = match pending.iter().rposition(|message| matches!(message, TaskMessage::Enqueue { .. })) {
+		Some(index) => index,
+		None => return Err(message),
+	};
+
+	let (
+		TaskMessage::Enqueue {
+			request: pending_request,
+			ticket: pending_ticket,
+			receipt: pending_receipt,
+		},
+		TaskMessage::Enqueue { request, ticket, receipt },
+	) = (pending.remove(newest_index).unwrap(), message)
+	else {
+		unreachable!("only enqueue messages are coalesced")
+	};
+
+	// a cancelled request shouldn't be brought back to life by merging a new one into it
+	let request = match pending_ticket {
+		Some(pending_ticket) if pending_ticket.is_cancelled() => request,
+		_ => merge(pending_request, request),
+	};
+
+	let receipt = match (pending_receipt, receipt) {
+		(Some(pending_receipt), Some(receipt)) => Some(pending_receipt.merge(receipt)),
+		(pending_receipt, receipt) => pending_receipt.or(receipt),
+	};
+
+	pending.insert(newest_index, TaskMessage::Enqueue { request, ticket, receipt });
+
+	Ok(())
+}
+
+impl<W, H> Route<W> for QueueState<W, H>
+where
+	W: Worker + Send + 'static,
+	H: WorkerHandle<W::Request, W::Response> + Send + 'static,
+{
+	fn enqueue(self: Arc<Self>, id: W::Id, request: W::Request) -> BoxFuture<Result<Ticket>> {
+		Box::pin(async move { Queue { state: self }.enqueue(&id, request).await })
+	}
+
+	fn request(self: Arc<Self>, id: W::Id, request: W::Request) -> BoxFuture<Result<W::Response>> {
+		Box::pin(async move { Queue { state: self }.request(&id, request).await })
+	}
+
+	fn enqueue_at(self: Arc<Self>, id: &W::Id, request: W::Request, at: Instant) -> Result<()> {
+		Queue { state: self }.enqueue_at(id, request, at)
+	}
+
+	fn reply(self: Arc<Self>, id: W::Id, request: W::Request) -> BoxFuture<Result<()>> {
+		Box::pin(async move { Queue { state: self }.reply(&id, request).await })
 	}
 }
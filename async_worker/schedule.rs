@@ -0,0 +1,76 @@
+use std::{
+	cmp::Reverse,
+	collections::{BinaryHeap, HashMap},
+	future::pending,
+	time::Duration,
+};
+use tokio::time::{sleep_until, Instant};
+
+enum Timer<Request> {
+	Once(Request),
+	Interval {
+		period: Duration,
+		make_request: Box<dyn FnMut() -> Request + Send>,
+	},
+}
+
+/// The delayed and periodic requests of a single worker. Because these live inside of the worker's task, they are dropped along with the worker.
+pub struct Timers<Request> {
+	deadlines: BinaryHeap<Reverse<(Instant, u64)>>,
+	timers: HashMap<u64, Timer<Request>>,
+	next_timer_id: u64,
+}
+
+impl<Request> Timers<Request> {
+	pub fn new() -> Timers<Request> {
+		Timers {
+			deadlines: BinaryHeap::new(),
+			timers: HashMap::new(),
+			next_timer_id: 0,
+		}
+	}
+
+	/// Deliver `request` once `at` has been reached.
+	pub fn insert_once(&mut self, at: Instant, request: Request) {
+		self.insert(at, Timer::Once(request));
+	}
+
+	/// Deliver the result of `make_request` every `period`, starting one `period` from now. If the worker is busy when a request is due, the
+	/// next request will be delivered one `period` after the late one.
+	pub fn insert_interval(&mut self, period: Duration, make_request: Box<dyn FnMut() -> Request + Send>) {
+		self.insert(Instant::now() + period, Timer::Interval { period, make_request });
+	}
+
+	/// Waits for the next request to become due. Never resolves if there are no timers.
+	///
+	/// This function is cancel safe.
+	pub async fn next(&mut self) -> Request {
+		let deadline = match self.deadlines.peek() {
+			Some(Reverse((deadline, _))) => *deadline,
+			None => pending().await,
+		};
+
+		sleep_until(deadline).await;
+
+		let Reverse((_, timer_id)) = self.deadlines.pop().expect("deadline was just peeked");
+		let timer = self.timers.remove(&timer_id).expect("every deadline has a timer");
+
+		match timer {
+			Timer::Once(request) => request,
+			Timer::Interval { period, mut make_request } => {
+				let request = make_request();
+				self.insert(Instant::now() + period, Timer::Interval { period, make_request });
+
+				request
+			}
+		}
+	}
+
+	fn insert(&mut self, at: Instant, timer: Timer<Request>) {
+		let timer_id = self.next_timer_id;
+		self.next_timer_id += 1;
+
+		self.deadlines.push(Reverse((at, timer_id)));
+		self.timers.insert(timer_id, timer);
+	}
+}
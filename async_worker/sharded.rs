@@ -0,0 +1,155 @@
+use std::{
+	hash::{DefaultHasher, Hash, Hasher},
+	sync::{Arc, Weak},
+	time::Duration,
+};
+use tokio::{runtime::Handle, time::Instant};
+use tokio_stream::Stream;
+
+use crate::{
+	address::{Address, BoxFuture, Route},
+	handle::{NoopHandle, WorkerHandle},
+	queue::Queue,
+	ticket::Ticket,
+	worker::Worker,
+	Result,
+};
+
+/// Pick one of `count` shards for `value`. The same value will always be assigned the same shard.
+pub(crate) fn get_shard_index<T: Hash>(value: &T, count: usize) -> usize {
+	let mut hasher = DefaultHasher::new();
+	value.hash(&mut hasher);
+
+	hasher.finish() as usize % count
+}
+
+struct ShardedState<W: Worker, H: WorkerHandle<W::Request, W::Response>> {
+	shards: Vec<Queue<W, H>>,
+}
+
+/// A queue that splits it's workers between a number of independent `Queue`s, based on the hash of their id. Each shard has it's own spawner and
+/// routing table, so creating and addressing workers does not contend on a single lock or channel. Shards can also be given their own runtimes
+/// via `QueueBuilder::shard_runtimes`.
+///
+/// The api is the same as that of `Queue`. Built via `QueueBuilder::build_sharded`.
+pub struct ShardedQueue<W: Worker, H: WorkerHandle<W::Request, W::Response> = NoopHandle> {
+	state: Arc<ShardedState<W, H>>,
+}
+
+impl<W, H> ShardedQueue<W, H>
+where
+	W: Worker + Send + 'static,
+	H: WorkerHandle<W::Request, W::Response> + Send + 'static,
+{
+	/// Create a shard on each of `runtimes` via `make_shard`, which is given the address that the shard's workers should use.
+	pub(crate) fn new(runtimes: Vec<Handle>, make_shard: impl Fn(&Handle, Address<W>) -> Queue<W, H>) -> ShardedQueue<W, H> {
+		let state = Arc::new_cyclic(|state: &Weak<ShardedState<W, H>>| {
+			let address = Address::new(state.clone());
+
+			ShardedState {
+				shards: runtimes.iter().map(|runtime| make_shard(runtime, address.clone())).collect(),
+			}
+		});
+
+		ShardedQueue { state }
+	}
+
+	/// Get an address for this queue, which covers all of it's shards. See `Queue::address`.
+	pub fn address(&self) -> Address<W> {
+		let state: Arc<dyn Route<W>> = self.state.clone();
+
+		Address::new(Arc::downgrade(&state))
+	}
+
+	/// See `Queue::register_handle`.
+	pub fn register_handle(&self, id: &W::Id, handle: H) -> Result<()> {
+		self.state.get_shard(id).register_handle(id, handle)
+	}
+
+	/// See `Queue::subscribe`.
+	pub fn subscribe(&self, id: &W::Id) -> Result<impl Stream<Item = W::Response>> {
+		self.state.get_shard(id).subscribe(id)
+	}
+
+	/// See `Queue::enqueue`.
+	pub async fn enqueue(&self, id: &W::Id, request: W::Request) -> Result<Ticket> {
+		self.state.get_shard(id).enqueue(id, request).await
+	}
+
+	/// See `Queue::request`.
+	pub async fn request(&self, id: &W::Id, request: W::Request) -> Result<W::Response> {
+		self.state.get_shard(id).request(id, request).await
+	}
+
+	/// See `Queue::enqueue_after`.
+	pub fn enqueue_after(&self, id: &W::Id, request: W::Request, delay: Duration) -> Result<()> {
+		self.state.get_shard(id).enqueue_after(id, request, delay)
+	}
+
+	/// See `Queue::enqueue_at`.
+	pub fn enqueue_at(&self, id: &W::Id, request: W::Request, at: Instant) -> Result<()> {
+		self.state.get_shard(id).enqueue_at(id, request, at)
+	}
+
+	/// See `Queue::register_interval`.
+	pub fn register_interval(&self, id: &W::Id, period: Duration, make_request: impl FnMut() -> W::Request + Send + 'static) -> Result<()> {
+		self.state.get_shard(id).register_interval(id, period, make_request)
+	}
+
+	/// See `Queue::poll`.
+	pub async fn poll(&self, id: &W::Id) -> Result<W::Response> {
+		self.state.get_shard(id).poll(id).await
+	}
+
+	/// See `Queue::poll_many`.
+	pub async fn poll_many(&self, id: &W::Id) -> Result<Vec<W::Response>> {
+		self.state.get_shard(id).poll_many(id).await
+	}
+
+	/// See `Queue::poll_while`.
+	pub async fn poll_while(&self, id: &W::Id, duration: Duration) -> Result<W::Response> {
+		self.state.get_shard(id).poll_while(id, duration).await
+	}
+
+	/// See `Queue::poll_many_while`.
+	pub async fn poll_many_while(&self, id: &W::Id, duration: Duration) -> Result<Vec<W::Response>> {
+		self.state.get_shard(id).poll_many_while(id, duration).await
+	}
+
+	/// See `Queue::terminate`.
+	pub fn terminate(&self, id: &W::Id) {
+		self.state.get_shard(id).terminate(id)
+	}
+}
+
+impl<W, H> ShardedState<W, H>
+where
+	W: Worker,
+	H: WorkerHandle<W::Request, W::Response>,
+{
+	fn get_shard(&self, id: &W::Id) -> &Queue<W, H> {
+		&self.shards[get_shard_index(id, self.shards.len())]
+	}
+}
+
+impl<W, H> Route<W> for ShardedState<W, H>
+where
+	W: Worker + Send + 'static,
+	H: WorkerHandle<W::Request, W::Response> + Send + 'static,
+{
+	fn enqueue(self: Arc<Self>, id: W::Id, request: W::Request) -> BoxFuture<Result<Ticket>> {
+		Box::pin(async move { self.get_shard(&id).enqueue(&id, request).await })
+	}
+
+	fn request(self: Arc<Self>, id: W::Id, request: W::Request) -> BoxFuture<Result<W::Response>> {
+		Box::pin(async move { self.get_shard(&id).request(&id, request).await })
+	}
+
+	fn enqueue_at(self: Arc<Self>, id: &W::Id, request: W::Request, at: Instant) -> Result<()> {
+		self.get_shard(id).enqueue_at(id, request, at)
+	}
+
+	fn reply(self: Arc<Self>, id: W::Id, request: W::Request) -> BoxFuture<Result<()>> {
+		Box::pin(async move { self.get_shard(&id).reply(&id, request).await })
+	}
+}
@@ -0,0 +1,304 @@
+//! Utilities for testing queues and workers deterministically. Enabled by the `testing` feature.
+//!
+//! Tests should run on a paused clock (`#[tokio::test(start_paused = true)]`), so that inactivity timeouts, timers and `Queue::*_while`
+//! methods can be driven with `advance` instead of waiting in real time.
+
+use std::{
+	fmt::Debug,
+	mem,
+	sync::{Arc, Mutex},
+	time::Duration,
+};
+use tokio::{task::yield_now, time};
+
+use crate::{
+	address::Mailbox,
+	concurrent::{Concurrent, ConcurrentWorker},
+	handle::{DropReason, SendResult, WorkerHandle},
+	worker::Worker,
+};
+
+/// The number of times `settle` yields. Enough for a message to make it from the queue, through the spawner and a worker, and back.
+const SETTLE_YIELDS: usize = 64;
+
+/// Let every task that is ready to run do so, without moving the clock.
+pub async fn settle() {
+	for _ in 0..SETTLE_YIELDS {
+		yield_now().await;
+	}
+}
+
+/// Move the paused clock forward by `duration`, firing any timers that come due, and then `settle`.
+pub async fn advance(duration: Duration) {
+	time::advance(duration).await;
+	settle().await;
+}
+
+/// A lifecycle event of a worker, as recorded by a `Recorder`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Lifecycle<Id> {
+	Created(Id),
+	/// Recorded by `Worker::cancelled`, after a request that was being handled is cancelled
+	Cancelled(Id),
+	/// Recorded by `Worker::evicted`, before the worker is destroyed
+	Evicted(Id),
+	Destroyed(Id),
+}
+
+/// Records the order in which workers are created, evicted and destroyed, along with the requests they had cancelled. Cloning a recorder shares it's events.
+#[derive(Clone)]
+pub struct Recorder<Id> {
+	events: Arc<Mutex<Vec<Lifecycle<Id>>>>,
+}
+
+impl<Id> Default for Recorder<Id> {
+	fn default() -> Self {
+		Recorder {
+			events: Arc::new(Mutex::new(Vec::new())),
+		}
+	}
+}
+
+impl<Id: Clone + PartialEq + Debug> Recorder<Id> {
+	pub fn new() -> Recorder<Id> {
+		Recorder::default()
+	}
+
+	pub fn record(&self, event: Lifecycle<Id>) {
+		self.events.lock().unwrap().push(event);
+	}
+
+	/// Every event that has been recorded so far, in order.
+	pub fn events(&self) -> Vec<Lifecycle<Id>> {
+		self.events.lock().unwrap().clone()
+	}
+
+	/// Returns true if the worker for `id` has been created and not yet destroyed.
+	pub fn is_alive(&self, id: &Id) -> bool {
+		let events = self.events.lock().unwrap();
+		let last = events.iter().rev().find(|event| match event {
+			Lifecycle::Created(other) | Lifecycle::Destroyed(other) => other == id,
+			Lifecycle::Cancelled(_) | Lifecycle::Evicted(_) => false,
+		});
+
+		matches!(last, Some(Lifecycle::Created(_)))
+	}
+
+	/// Panics unless the recorded events are exactly `expected`.
+	#[track_caller]
+	pub fn assert_events(&self, expected: &[Lifecycle<Id>]) {
+		assert_eq!(self.events(), expected, "workers were not created and destroyed in the expected order");
+	}
+
+	/// Panics unless every worker was destroyed only after being created, and was never created twice without being destroyed in between.
+	#[track_caller]
+	pub fn assert_well_ordered(&self) {
+		let mut alive = Vec::<Id>::new();
+
+		for event in self.events() {
+			match event {
+				Lifecycle::Created(id) => {
+					assert!(!alive.contains(&id), "worker {id:?} was created while it was already alive");
+					alive.push(id);
+				}
+				Lifecycle::Cancelled(id) => {
+					assert!(alive.contains(&id), "worker {id:?} had a request cancelled without being alive");
+				}
+				Lifecycle::Evicted(id) => {
+					assert!(alive.contains(&id), "worker {id:?} was evicted without being alive");
+				}
+				Lifecycle::Destroyed(id) => {
+					let index = alive.iter().position(|alive| alive == &id);
+					assert!(index.is_some(), "worker {id:?} was destroyed without being alive");
+					alive.remove(index.unwrap());
+				}
+			}
+		}
+	}
+}
+
+/// A request for a `FakeWorker`.
+#[derive(Debug, Clone)]
+pub enum FakeRequest {
+	/// Respond with the given value right away.
+	Echo(u64),
+	/// Wait for the given duration, and then respond with the given value.
+	Sleep(Duration, u64),
+	/// The same as `Sleep`, but when the worker is used as a `ConcurrentWorker`, it is only ordered with the requests of the same key (the first
+	/// field). `Echo` and `Sleep` requests all share a key of their own.
+	Keyed(u32, Duration, u64),
+}
+
+impl FakeRequest {
+	async fn respond(self) -> u64 {
+		match self {
+			FakeRequest::Echo(value) => value,
+			FakeRequest::Sleep(duration, value) | FakeRequest::Keyed(_, duration, value) => {
+				time::sleep(duration).await;
+
+				value
+			}
+		}
+	}
+}
+
+/// A worker that echoes back the values it is sent, and records it's lifecycle in the `Recorder` that is it's context.
+pub struct FakeWorker {
+	id: u32,
+	recorder: Recorder<u32>,
+}
+
+impl Worker for FakeWorker {
+	type Context = Recorder<u32>;
+	type Request = FakeRequest;
+	type Response = u64;
+	type Id = u32;
+
+	async fn create(id: &Self::Id, recorder: Self::Context, _: &Mailbox<Self>) -> Self {
+		recorder.record(Lifecycle::Created(*id));
+
+		FakeWorker { id: *id, recorder }
+	}
+
+	async fn handle(&mut self, request: Self::Request, _: &Mailbox<Self>) -> Self::Response {
+		request.respond().await
+	}
+
+	async fn cancelled(&mut self, _: &Mailbox<Self>) {
+		self.recorder.record(Lifecycle::Cancelled(self.id));
+	}
+
+	async fn evicted(&mut self, _: &Mailbox<Self>) {
+		self.recorder.record(Lifecycle::Evicted(self.id));
+	}
+
+	async fn destroy(self) {
+		self.recorder.record(Lifecycle::Destroyed(self.id));
+	}
+}
+
+/// Used via `Concurrent<FakeWorker>`. Requests are ordered by the key of `FakeRequest::Keyed`.
+impl ConcurrentWorker for FakeWorker {
+	type Context = Recorder<u32>;
+	type Request = FakeRequest;
+	type Response = u64;
+	type Id = u32;
+	type Key = Option<u32>;
+
+	async fn create(id: &Self::Id, recorder: Self::Context, _: &Mailbox<Concurrent<Self>>) -> Self {
+		recorder.record(Lifecycle::Created(*id));
+
+		FakeWorker { id: *id, recorder }
+	}
+
+	fn key(request: &Self::Request) -> Self::Key {
+		match request {
+			FakeRequest::Keyed(key, _, _) => Some(*key),
+			_ => None,
+		}
+	}
+
+	async fn handle(&self, request: Self::Request, _: &Mailbox<Concurrent<Self>>) -> Self::Response {
+		request.respond().await
+	}
+
+	async fn cancelled(&self, _: &Mailbox<Concurrent<Self>>) {
+		self.recorder.record(Lifecycle::Cancelled(self.id));
+	}
+
+	async fn evicted(&self, _: &Mailbox<Concurrent<Self>>) {
+		self.recorder.record(Lifecycle::Evicted(self.id));
+	}
+
+	async fn destroy(self) {
+		self.recorder.record(Lifecycle::Destroyed(self.id));
+	}
+}
+
+struct FakeHandleState<Response> {
+	responses: Vec<Response>,
+	drop_reason: Option<DropReason>,
+	is_closed: bool,
+	failures_left: usize,
+}
+
+/// A handle that collects the responses it is sent, and records why it was dropped. Cloning a handle shares it's state, so a clone can be
+/// kept to inspect a handle that was given to a queue.
+pub struct FakeHandle<Response> {
+	state: Arc<Mutex<FakeHandleState<Response>>>,
+}
+
+impl<Response> Clone for FakeHandle<Response> {
+	fn clone(&self) -> Self {
+		FakeHandle { state: self.state.clone() }
+	}
+}
+
+impl<Response> Default for FakeHandle<Response> {
+	fn default() -> Self {
+		FakeHandle {
+			state: Arc::new(Mutex::new(FakeHandleState {
+				responses: Vec::new(),
+				drop_reason: None,
+				is_closed: false,
+				failures_left: 0,
+			})),
+		}
+	}
+}
+
+impl<Response> FakeHandle<Response> {
+	pub fn new() -> FakeHandle<Response> {
+		FakeHandle::default()
+	}
+
+	/// Take every response that the handle has received so far.
+	pub fn take_responses(&self) -> Vec<Response> {
+		mem::take(&mut self.state.lock().unwrap().responses)
+	}
+
+	/// The reason that was given to `WorkerHandle::will_drop`, if it has been called.
+	pub fn drop_reason(&self) -> Option<DropReason> {
+		self.state.lock().unwrap().drop_reason
+	}
+
+	/// Close the handle, so that the next response sent to it is rejected with `SendResult::Closed`.
+	pub fn close(&self) {
+		self.state.lock().unwrap().is_closed = true;
+	}
+
+	/// Reject the next `count` responses sent to the handle with `SendResult::Failed`, without closing it.
+	pub fn fail_next(&self, count: usize) {
+		self.state.lock().unwrap().failures_left = count;
+	}
+}
+
+impl<Request, Response> WorkerHandle<Request, Response> for FakeHandle<Response>
+where
+	Response: Send,
+{
+	async fn recv(&mut self) -> Option<Request> {
+		std::future::pending().await
+	}
+
+	async fn send(&mut self, response: Response) -> SendResult<Response> {
+		let mut state = self.state.lock().unwrap();
+
+		if state.is_closed {
+			return SendResult::Closed(response);
+		}
+
+		if state.failures_left > 0 {
+			state.failures_left -= 1;
+
+			return SendResult::Failed(response);
+		}
+
+		state.responses.push(response);
+		SendResult::Sent
+	}
+
+	async fn will_drop(&mut self, reason: DropReason) {
+		self.state.lock().unwrap().drop_reason = Some(reason);
+	}
+}
@@ -0,0 +1,213 @@
+#![cfg(feature = "journal")]
+
+use async_worker::{FileJournal, Mailbox, Queue, QueueBuilder, Worker};
+use std::{
+	fs::{self, OpenOptions},
+	future::pending,
+	io::{ErrorKind, Write},
+	path::{Path, PathBuf},
+	process,
+	sync::{Arc, Mutex},
+};
+use tokio::runtime::Runtime;
+
+/// A request that never finishes being handled while the worker is stalling, standing in for one that was in progress during a crash
+const STALL: u64 = 0;
+
+#[derive(Clone, Default)]
+struct Handled {
+	values: Arc<Mutex<Vec<u64>>>,
+	is_stalling: bool,
+}
+
+impl Handled {
+	fn stalling() -> Handled {
+		Handled {
+			is_stalling: true,
+			..Default::default()
+		}
+	}
+
+	fn values(&self) -> Vec<u64> {
+		self.values.lock().unwrap().clone()
+	}
+}
+
+/// Records every request that it handles
+struct RecordingWorker {
+	handled: Handled,
+}
+
+impl Worker for RecordingWorker {
+	type Context = Handled;
+	type Request = u64;
+	type Response = u64;
+	type Id = u32;
+
+	async fn create(_: &u32, handled: Handled, _: &Mailbox<Self>) -> Self {
+		RecordingWorker { handled }
+	}
+
+	async fn handle(&mut self, request: u64, _: &Mailbox<Self>) -> u64 {
+		if request == STALL && self.handled.is_stalling {
+			pending::<()>().await;
+		}
+
+		self.handled.values.lock().unwrap().push(request);
+
+		request
+	}
+
+	async fn destroy(self) {}
+}
+
+fn journal_path(name: &str) -> PathBuf {
+	let path = std::env::temp_dir().join(format!("async_worker-{name}-{}.jsonl", process::id()));
+	let _ = fs::remove_file(&path);
+
+	path
+}
+
+/// Run `run` against a journaled queue on a runtime of it's own. The runtime is shut down afterwards, which stops the workers wherever they
+/// are, just like a crash would, but waits for the journal to finish writing.
+fn run_journaled<T>(journal: FileJournal, handled: &Handled, run: impl AsyncFnOnce(&Queue<RecordingWorker>) -> T) -> T {
+	let runtime = Runtime::new().unwrap();
+
+	runtime.block_on(async {
+		let queue = QueueBuilder::default()
+			.build_journaled::<RecordingWorker>(handled.clone(), journal)
+			.await
+			.unwrap();
+
+		run(&queue).await
+	})
+}
+
+#[test]
+fn unacknowledged_requests_are_replayed_after_a_restart() {
+	let path = journal_path("replay");
+	let before_crash = Handled::stalling();
+
+	run_journaled(FileJournal::new(&path), &before_crash, async |queue| {
+		queue.enqueue(&1, 1).await.unwrap();
+		assert_eq!(queue.poll(&1).await.unwrap(), 1);
+
+		// a worker can't be polled while it is stalled, so the other worker is what shows that the queue has moved on
+		queue.enqueue(&1, STALL).await.unwrap();
+		queue.enqueue(&1, 2).await.unwrap();
+		queue.enqueue(&2, 3).await.unwrap();
+		assert_eq!(queue.poll(&2).await.unwrap(), 3);
+	});
+
+	assert_eq!(before_crash.values(), [1, 3]);
+
+	let after_restart = Handled::default();
+
+	// only the stalled request and the one behind it were never handled
+	run_journaled(FileJournal::new(&path), &after_restart, async |queue| {
+		assert_eq!(queue.poll(&1).await.unwrap(), STALL);
+		assert_eq!(queue.poll(&1).await.unwrap(), 2);
+	});
+
+	assert_eq!(after_restart.values(), [STALL, 2]);
+	fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn acknowledged_requests_are_not_replayed() {
+	let path = journal_path("acknowledge");
+	let handled = Handled::default();
+
+	run_journaled(FileJournal::new(&path), &handled, async |queue| {
+		queue.enqueue(&1, 1).await.unwrap();
+		queue.enqueue(&1, 2).await.unwrap();
+
+		assert_eq!(queue.poll(&1).await.unwrap(), 1);
+		assert_eq!(queue.poll(&1).await.unwrap(), 2);
+	});
+
+	let after_restart = Handled::default();
+
+	run_journaled(FileJournal::new(&path), &after_restart, async |queue| {
+		queue.enqueue(&1, 3).await.unwrap();
+		assert_eq!(queue.poll(&1).await.unwrap(), 3);
+	});
+
+	assert_eq!(after_restart.values(), [3]);
+	fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn journal_is_compacted_once_enough_requests_are_acknowledged() {
+	let path = journal_path("compact");
+	let handled = Handled::stalling();
+
+	run_journaled(FileJournal::new(&path).compact_after(3), &handled, async |queue| {
+		queue.enqueue(&1, STALL).await.unwrap();
+
+		for value in 1..=3 {
+			queue.enqueue(&2, value).await.unwrap();
+			assert_eq!(queue.poll(&2).await.unwrap(), value);
+		}
+	});
+
+	// only the stalled request is left, and the acknowledged ones have been compacted away
+	let content = fs::read_to_string(&path).unwrap();
+	assert_eq!(content.lines().count(), 1, "expected the journal to be compacted, but it contains:\n{content}");
+
+	fs::remove_file(&path).unwrap();
+}
+
+/// Append `content` to the journal at `path`, as a crash in the middle of a write would
+fn append_torn_record(path: &Path, content: &str) {
+	OpenOptions::new().append(true).open(path).unwrap().write_all(content.as_bytes()).unwrap();
+}
+
+#[test]
+fn torn_record_at_the_end_is_ignored_and_later_records_are_still_readable() {
+	let path = journal_path("torn-end");
+
+	run_journaled(FileJournal::new(&path), &Handled::stalling(), async |queue| {
+		queue.enqueue(&1, STALL).await.unwrap();
+		queue.enqueue(&1, 2).await.unwrap();
+	});
+
+	append_torn_record(&path, r#"{"type":"enqueue","sequence":2,"id":1,"requ"#);
+
+	let after_restart = Handled::default();
+
+	run_journaled(FileJournal::new(&path), &after_restart, async |queue| {
+		assert_eq!(queue.poll(&1).await.unwrap(), STALL);
+		assert_eq!(queue.poll(&1).await.unwrap(), 2);
+
+		queue.enqueue(&1, 3).await.unwrap();
+		assert_eq!(queue.poll(&1).await.unwrap(), 3);
+	});
+
+	assert_eq!(after_restart.values(), [STALL, 2, 3]);
+
+	// the records appended after the torn one must not have been made unreadable by it
+	let after_second_restart = Handled::default();
+	run_journaled(FileJournal::new(&path), &after_second_restart, async |_| {});
+
+	assert!(after_second_restart.values().is_empty());
+	fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn torn_record_in_the_middle_is_reported_as_corrupt() {
+	let path = journal_path("torn-middle");
+
+	run_journaled(FileJournal::new(&path), &Handled::stalling(), async |queue| {
+		queue.enqueue(&1, STALL).await.unwrap();
+	});
+
+	append_torn_record(&path, "{\"type\":\"enq\n");
+	append_torn_record(&path, "{\"type\":\"acknowledge\",\"sequences\":[0]}\n");
+
+	let runtime = Runtime::new().unwrap();
+	let build_res = runtime.block_on(QueueBuilder::default().build_journaled::<RecordingWorker>(Handled::default(), FileJournal::new(&path)));
+
+	assert!(matches!(build_res, Err(error) if error.kind() == ErrorKind::InvalidData));
+	fs::remove_file(&path).unwrap();
+}
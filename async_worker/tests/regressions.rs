@@ -0,0 +1,1033 @@
+use async_worker::{
+	testing::{advance, settle, FakeHandle, FakeRequest, FakeWorker, Lifecycle, Recorder},
+	Address, Backpressure, Concurrent, DropReason, Error, Local, LocalWorker, Mailbox, Queue, QueueBuilder, ShardedQueue, Worker, WorkerOverflow,
+};
+use std::{
+	cell::Cell,
+	rc::Rc,
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Arc,
+	},
+	thread,
+	time::Duration,
+};
+use tokio::time::Instant;
+use tokio_stream::StreamExt;
+
+const LONG: Duration = Duration::from_secs(60);
+
+fn build(recorder: &Recorder<u32>) -> Arc<Queue<FakeWorker>> {
+	Arc::new(QueueBuilder::default().build::<FakeWorker>(recorder.clone()))
+}
+
+fn build_with_handle(recorder: &Recorder<u32>) -> Queue<FakeWorker, FakeHandle<u64>> {
+	QueueBuilder::default().build_with_handle::<FakeWorker, FakeHandle<u64>>(recorder.clone())
+}
+
+#[tokio::test(start_paused = true)]
+async fn poll_is_ceeded_by_a_newer_poll() {
+	let recorder = Recorder::new();
+	let queue = build(&recorder);
+
+	queue.enqueue(&1, FakeRequest::Echo(1)).await.unwrap();
+	assert_eq!(queue.poll(&1).await.unwrap(), 1);
+
+	let first = tokio::spawn({
+		let queue = queue.clone();
+		async move { queue.poll(&1).await }
+	});
+	settle().await;
+
+	let second = tokio::spawn({
+		let queue = queue.clone();
+		async move { queue.poll(&1).await }
+	});
+	settle().await;
+
+	assert!(matches!(first.await.unwrap(), Err(Error::Ceeded)));
+
+	queue.enqueue(&1, FakeRequest::Echo(2)).await.unwrap();
+	assert_eq!(second.await.unwrap().unwrap(), 2);
+}
+
+#[tokio::test(start_paused = true)]
+async fn poll_is_ceeded_while_a_handle_is_registered() {
+	let recorder = Recorder::new();
+	let queue = build_with_handle(&recorder);
+
+	queue.enqueue(&1, FakeRequest::Echo(1)).await.unwrap();
+	queue.register_handle(&1, FakeHandle::new()).unwrap();
+
+	assert!(matches!(queue.poll(&1).await, Err(Error::Ceeded)));
+	assert!(matches!(queue.poll_many(&1).await, Err(Error::Ceeded)));
+}
+
+#[tokio::test(start_paused = true)]
+async fn handle_is_ceeded_by_a_newer_handle() {
+	let recorder = Recorder::new();
+	let queue = build_with_handle(&recorder);
+	let first = FakeHandle::new();
+	let second = FakeHandle::new();
+
+	queue.enqueue(&1, FakeRequest::Echo(1)).await.unwrap();
+	queue.register_handle(&1, first.clone()).unwrap();
+	settle().await;
+
+	queue.register_handle(&1, second.clone()).unwrap();
+	settle().await;
+
+	assert_eq!(first.drop_reason(), Some(DropReason::Ceeded));
+	assert_eq!(second.drop_reason(), None);
+}
+
+#[tokio::test(start_paused = true)]
+async fn registered_handle_receives_waiting_and_new_responses() {
+	let recorder = Recorder::new();
+	let queue = build_with_handle(&recorder);
+	let handle = FakeHandle::new();
+
+	queue.enqueue(&1, FakeRequest::Echo(1)).await.unwrap();
+	queue.enqueue(&1, FakeRequest::Echo(2)).await.unwrap();
+	settle().await;
+
+	queue.register_handle(&1, handle.clone()).unwrap();
+	queue.enqueue(&1, FakeRequest::Echo(3)).await.unwrap();
+	settle().await;
+
+	assert_eq!(handle.take_responses(), [1, 2, 3]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn closed_handle_falls_back_to_polling() {
+	let recorder = Recorder::new();
+	let queue = build_with_handle(&recorder);
+	let handle = FakeHandle::new();
+
+	queue.enqueue(&1, FakeRequest::Echo(1)).await.unwrap();
+	queue.register_handle(&1, handle.clone()).unwrap();
+	settle().await;
+
+	handle.close();
+	queue.enqueue(&1, FakeRequest::Echo(2)).await.unwrap();
+	settle().await;
+
+	assert_eq!(handle.take_responses(), [1]);
+	assert_eq!(handle.drop_reason(), Some(DropReason::HandleClosed));
+	assert_eq!(queue.poll(&1).await.unwrap(), 2);
+}
+
+#[tokio::test(start_paused = true)]
+async fn failed_response_is_sent_again_before_the_next_one() {
+	let recorder = Recorder::new();
+	let queue = build_with_handle(&recorder);
+	let handle = FakeHandle::new();
+
+	queue.enqueue(&1, FakeRequest::Echo(1)).await.unwrap();
+	queue.register_handle(&1, handle.clone()).unwrap();
+	settle().await;
+
+	handle.fail_next(1);
+	queue.enqueue(&1, FakeRequest::Echo(2)).await.unwrap();
+	settle().await;
+
+	assert_eq!(handle.take_responses(), [1]);
+
+	queue.enqueue(&1, FakeRequest::Echo(3)).await.unwrap();
+	settle().await;
+
+	assert_eq!(handle.take_responses(), [2, 3]);
+	assert_eq!(handle.drop_reason(), None);
+}
+
+#[tokio::test(start_paused = true)]
+async fn register_handle_requires_a_worker() {
+	let recorder = Recorder::new();
+	let queue = build_with_handle(&recorder);
+
+	assert!(matches!(queue.register_handle(&1, FakeHandle::new()), Err(Error::NoWorker)));
+}
+
+#[tokio::test(start_paused = true)]
+async fn poll_requires_a_worker() {
+	let recorder = Recorder::new();
+	let queue = build(&recorder);
+
+	assert!(matches!(queue.poll(&1).await, Err(Error::NoWorker)));
+	assert!(matches!(queue.poll_many(&1).await, Err(Error::NoWorker)));
+}
+
+#[tokio::test(start_paused = true)]
+async fn poll_is_interrupted_when_the_worker_is_terminated() {
+	let recorder = Recorder::new();
+	let queue = build(&recorder);
+
+	queue.enqueue(&1, FakeRequest::Echo(1)).await.unwrap();
+	assert_eq!(queue.poll(&1).await.unwrap(), 1);
+
+	let poll = tokio::spawn({
+		let queue = queue.clone();
+		async move { queue.poll(&1).await }
+	});
+	settle().await;
+
+	queue.terminate(&1);
+	settle().await;
+
+	assert!(matches!(poll.await.unwrap(), Err(Error::WorkerTerminated)));
+	recorder.assert_events(&[Lifecycle::Created(1), Lifecycle::Destroyed(1)]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn worker_is_terminated_after_inactivity() {
+	let recorder = Recorder::new();
+	let queue = QueueBuilder::default()
+		.terminate_worker_after(Duration::from_secs(10))
+		.build_with_handle::<FakeWorker, FakeHandle<u64>>(recorder.clone());
+	let handle = FakeHandle::new();
+
+	queue.enqueue(&1, FakeRequest::Echo(1)).await.unwrap();
+	queue.register_handle(&1, handle.clone()).unwrap();
+	settle().await;
+
+	advance(Duration::from_secs(9)).await;
+	assert!(recorder.is_alive(&1));
+
+	advance(Duration::from_secs(2)).await;
+	assert!(!recorder.is_alive(&1));
+	assert_eq!(handle.drop_reason(), Some(DropReason::WorkerTerminated));
+	assert!(matches!(queue.poll(&1).await, Err(Error::NoWorker)));
+}
+
+#[tokio::test(start_paused = true)]
+async fn busy_worker_is_not_terminated_for_inactivity() {
+	let recorder = Recorder::new();
+	let queue = QueueBuilder::default()
+		.terminate_worker_after(Duration::from_secs(10))
+		.build::<FakeWorker>(recorder.clone());
+
+	queue.enqueue(&1, FakeRequest::Sleep(Duration::from_secs(30), 1)).await.unwrap();
+	settle().await;
+
+	advance(Duration::from_secs(30)).await;
+	assert!(recorder.is_alive(&1));
+	assert_eq!(queue.poll(&1).await.unwrap(), 1);
+}
+
+#[tokio::test(start_paused = true)]
+async fn enqueue_is_rejected_when_the_worker_is_at_capacity() {
+	let recorder = Recorder::new();
+	let queue = QueueBuilder::default()
+		.max_length(1)
+		.backpressure(Backpressure::Reject)
+		.build::<FakeWorker>(recorder.clone());
+
+	// the first request is picked up by the worker, and the second fills it's queue
+	queue.enqueue(&1, FakeRequest::Sleep(LONG, 1)).await.unwrap();
+	settle().await;
+	queue.enqueue(&1, FakeRequest::Echo(2)).await.unwrap();
+
+	assert!(matches!(queue.enqueue(&1, FakeRequest::Echo(3)).await, Err(Error::WorkerAtCapacity)));
+	assert!(matches!(queue.poll(&1).await, Err(Error::WorkerAtCapacity)));
+}
+
+#[tokio::test(start_paused = true)]
+async fn enqueue_waits_for_the_worker_to_make_room() {
+	let recorder = Recorder::new();
+	let queue = Arc::new(
+		QueueBuilder::default()
+			.max_length(1)
+			.backpressure(Backpressure::Wait(Duration::from_secs(20)))
+			.build::<FakeWorker>(recorder.clone()),
+	);
+
+	queue.enqueue(&1, FakeRequest::Sleep(Duration::from_secs(10), 1)).await.unwrap();
+	settle().await;
+	queue.enqueue(&1, FakeRequest::Echo(2)).await.unwrap();
+
+	let waiting = tokio::spawn({
+		let queue = queue.clone();
+		async move { queue.enqueue(&1, FakeRequest::Echo(3)).await }
+	});
+
+	advance(Duration::from_secs(9)).await;
+	assert!(!waiting.is_finished());
+
+	// the worker finishes the first request and picks up the second, which makes room for the third
+	advance(Duration::from_secs(1)).await;
+	assert!(waiting.await.unwrap().is_ok());
+	assert_eq!(queue.poll_many(&1).await.unwrap(), [1, 2, 3]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn enqueue_gives_up_waiting_for_room_after_the_timeout() {
+	let recorder = Recorder::new();
+	let queue = Arc::new(
+		QueueBuilder::default()
+			.max_length(1)
+			.backpressure(Backpressure::Wait(Duration::from_secs(5)))
+			.build::<FakeWorker>(recorder.clone()),
+	);
+
+	queue.enqueue(&1, FakeRequest::Sleep(LONG, 1)).await.unwrap();
+	settle().await;
+	queue.enqueue(&1, FakeRequest::Echo(2)).await.unwrap();
+
+	let waiting = tokio::spawn({
+		let queue = queue.clone();
+		async move { queue.enqueue(&1, FakeRequest::Echo(3)).await }
+	});
+
+	advance(Duration::from_secs(6)).await;
+	assert!(matches!(waiting.await.unwrap(), Err(Error::WorkerAtCapacity)));
+}
+
+#[tokio::test(start_paused = true)]
+async fn oldest_pending_request_is_dropped_to_make_room() {
+	let recorder = Recorder::new();
+	let queue = QueueBuilder::default()
+		.max_length(2)
+		.backpressure(Backpressure::DropOldest)
+		.build::<FakeWorker>(recorder.clone());
+
+	// the request that is being handled is never dropped, only the ones that are still pending
+	queue.enqueue(&1, FakeRequest::Sleep(Duration::from_secs(10), 1)).await.unwrap();
+	settle().await;
+	queue.enqueue(&1, FakeRequest::Echo(2)).await.unwrap();
+	queue.enqueue(&1, FakeRequest::Echo(3)).await.unwrap();
+	queue.enqueue(&1, FakeRequest::Echo(4)).await.unwrap();
+
+	advance(Duration::from_secs(10)).await;
+	assert_eq!(queue.poll_many(&1).await.unwrap(), [1, 3, 4]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn pending_requests_are_coalesced_when_the_worker_is_at_capacity() {
+	let recorder = Recorder::new();
+	let queue = QueueBuilder::default()
+		.max_length(1)
+		.coalesce(|pending: FakeRequest, new: FakeRequest| match (pending, new) {
+			(FakeRequest::Echo(pending), FakeRequest::Echo(new)) => FakeRequest::Echo(pending + new),
+			(_, new) => new,
+		})
+		.build::<FakeWorker>(recorder.clone());
+
+	queue.enqueue(&1, FakeRequest::Sleep(Duration::from_secs(10), 1)).await.unwrap();
+	settle().await;
+	queue.enqueue(&1, FakeRequest::Echo(2)).await.unwrap();
+	queue.enqueue(&1, FakeRequest::Echo(3)).await.unwrap();
+	queue.enqueue(&1, FakeRequest::Echo(4)).await.unwrap();
+
+	advance(Duration::from_secs(10)).await;
+	assert_eq!(queue.poll_many(&1).await.unwrap(), [1, 9]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn new_workers_are_rejected_when_the_queue_is_full() {
+	let recorder = Recorder::new();
+	let queue = QueueBuilder::default()
+		.max_workers(1, WorkerOverflow::Reject)
+		.build::<FakeWorker>(recorder.clone());
+
+	queue.enqueue(&1, FakeRequest::Echo(1)).await.unwrap();
+
+	assert!(matches!(queue.enqueue(&2, FakeRequest::Echo(2)).await, Err(Error::TooManyWorkers)));
+	assert!(queue.enqueue(&1, FakeRequest::Echo(3)).await.is_ok());
+}
+
+#[tokio::test(start_paused = true)]
+async fn workers_that_timed_out_do_not_count_against_max_workers() {
+	let recorder = Recorder::new();
+	let queue = QueueBuilder::default()
+		.max_workers(1, WorkerOverflow::Reject)
+		.terminate_worker_after(Duration::from_secs(10))
+		.build::<FakeWorker>(recorder.clone());
+
+	queue.enqueue(&1, FakeRequest::Echo(1)).await.unwrap();
+	settle().await;
+
+	advance(Duration::from_secs(11)).await;
+	assert!(!recorder.is_alive(&1));
+
+	assert!(queue.enqueue(&2, FakeRequest::Echo(2)).await.is_ok());
+}
+
+#[tokio::test(start_paused = true)]
+async fn least_recently_active_worker_is_evicted_when_the_queue_is_full() {
+	let recorder = Recorder::new();
+	let queue = QueueBuilder::default()
+		.max_workers(2, WorkerOverflow::EvictLeastRecentlyActive)
+		.build::<FakeWorker>(recorder.clone());
+
+	queue.enqueue(&1, FakeRequest::Echo(1)).await.unwrap();
+	advance(Duration::from_secs(1)).await;
+	queue.enqueue(&2, FakeRequest::Echo(2)).await.unwrap();
+	advance(Duration::from_secs(1)).await;
+	queue.enqueue(&1, FakeRequest::Echo(3)).await.unwrap();
+	advance(Duration::from_secs(1)).await;
+
+	queue.enqueue(&3, FakeRequest::Echo(4)).await.unwrap();
+	settle().await;
+
+	assert!(recorder.is_alive(&1));
+	assert!(!recorder.is_alive(&2));
+	assert!(recorder.is_alive(&3));
+	recorder.assert_well_ordered();
+}
+
+#[tokio::test(start_paused = true)]
+async fn evicted_worker_is_told_before_it_is_destroyed() {
+	let recorder = Recorder::new();
+	let queue = QueueBuilder::default()
+		.max_workers(1, WorkerOverflow::EvictLeastRecentlyActive)
+		.build::<FakeWorker>(recorder.clone());
+
+	queue.enqueue(&1, FakeRequest::Echo(1)).await.unwrap();
+	settle().await;
+
+	queue.enqueue(&2, FakeRequest::Echo(2)).await.unwrap();
+	settle().await;
+
+	queue.terminate(&2);
+	settle().await;
+
+	// only eviction calls `Worker::evicted`, not a plain termination
+	recorder.assert_events(&[
+		Lifecycle::Created(1),
+		Lifecycle::Evicted(1),
+		Lifecycle::Destroyed(1),
+		Lifecycle::Created(2),
+		Lifecycle::Destroyed(2),
+	]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn terminated_worker_is_destroyed_before_it_is_recreated() {
+	let recorder = Recorder::new();
+	let queue = build(&recorder);
+
+	queue.enqueue(&1, FakeRequest::Echo(1)).await.unwrap();
+	settle().await;
+
+	queue.terminate(&1);
+	settle().await;
+
+	queue.enqueue(&1, FakeRequest::Echo(2)).await.unwrap();
+	settle().await;
+
+	assert_eq!(queue.poll(&1).await.unwrap(), 2);
+	recorder.assert_events(&[Lifecycle::Created(1), Lifecycle::Destroyed(1), Lifecycle::Created(1)]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn delayed_request_is_delivered_at_the_deadline() {
+	let recorder = Recorder::new();
+	let queue = build_with_handle(&recorder);
+	let handle = FakeHandle::new();
+
+	queue.enqueue(&1, FakeRequest::Echo(1)).await.unwrap();
+	queue.register_handle(&1, handle.clone()).unwrap();
+	settle().await;
+	assert_eq!(handle.take_responses(), [1]);
+
+	queue.enqueue_after(&1, FakeRequest::Echo(2), Duration::from_secs(10)).unwrap();
+	queue.enqueue_at(&1, FakeRequest::Echo(3), Instant::now() + Duration::from_secs(20)).unwrap();
+
+	advance(Duration::from_secs(9)).await;
+	assert!(handle.take_responses().is_empty());
+
+	advance(Duration::from_secs(1)).await;
+	assert_eq!(handle.take_responses(), [2]);
+
+	advance(Duration::from_secs(9)).await;
+	assert!(handle.take_responses().is_empty());
+
+	advance(Duration::from_secs(1)).await;
+	assert_eq!(handle.take_responses(), [3]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn delayed_requests_require_a_worker() {
+	let recorder = Recorder::new();
+	let queue = build(&recorder);
+
+	assert!(matches!(queue.enqueue_after(&1, FakeRequest::Echo(1), LONG), Err(Error::NoWorker)));
+	assert!(matches!(
+		queue.enqueue_at(&1, FakeRequest::Echo(1), Instant::now() + LONG),
+		Err(Error::NoWorker)
+	));
+	assert!(matches!(queue.register_interval(&1, LONG, || FakeRequest::Echo(1)), Err(Error::NoWorker)));
+}
+
+#[tokio::test(start_paused = true)]
+async fn interval_delivers_a_request_every_period() {
+	let recorder = Recorder::new();
+	let queue = build_with_handle(&recorder);
+	let handle = FakeHandle::new();
+	let mut count = 0;
+
+	queue.enqueue(&1, FakeRequest::Echo(0)).await.unwrap();
+	queue.register_handle(&1, handle.clone()).unwrap();
+	queue
+		.register_interval(&1, Duration::from_secs(10), move || {
+			count += 1;
+			FakeRequest::Echo(count)
+		})
+		.unwrap();
+	settle().await;
+	assert_eq!(handle.take_responses(), [0]);
+
+	advance(Duration::from_secs(9)).await;
+	assert!(handle.take_responses().is_empty());
+
+	for expected in 1..=3 {
+		advance(Duration::from_secs(1)).await;
+		assert_eq!(handle.take_responses(), [expected]);
+
+		advance(Duration::from_secs(9)).await;
+		assert!(handle.take_responses().is_empty());
+	}
+}
+
+#[tokio::test(start_paused = true)]
+async fn interval_is_cancelled_when_the_worker_terminates() {
+	let recorder = Recorder::new();
+	let queue = build(&recorder);
+	let count = Arc::new(AtomicU64::new(0));
+
+	queue.enqueue(&1, FakeRequest::Echo(0)).await.unwrap();
+	queue
+		.register_interval(&1, Duration::from_secs(10), {
+			let count = count.clone();
+			move || FakeRequest::Echo(count.fetch_add(1, Ordering::SeqCst))
+		})
+		.unwrap();
+	settle().await;
+
+	advance(Duration::from_secs(10)).await;
+	assert_eq!(count.load(Ordering::SeqCst), 1);
+
+	queue.terminate(&1);
+	settle().await;
+	assert!(!recorder.is_alive(&1));
+
+	advance(Duration::from_secs(30)).await;
+	assert_eq!(count.load(Ordering::SeqCst), 1);
+}
+
+/// A worker that sends requests to it's peers, and to the `FakeWorker`s of another queue, through it's mailbox.
+struct RelayWorker {
+	other_queue: Address<FakeWorker>,
+}
+
+#[derive(Debug)]
+enum RelayRequest {
+	Echo(u64),
+	/// Wait for the given duration, and then respond with the given value
+	Sleep(Duration, u64),
+	/// Enqueue an `Echo` for a peer, which is created if it doesn't exist
+	EnqueueForPeer(u32, u64),
+	/// Schedule an `Echo` for a peer, which must already exist
+	ScheduleForPeer(u32, u64),
+	/// Ask a peer to `Echo`, and respond with it's reply
+	AskPeer(u32, u64),
+	/// Ask a worker of the other queue to handle a request, and respond with it's reply
+	AskOtherQueue(u32, FakeRequest),
+	Reply(RelayResponse),
+}
+
+#[derive(Debug, PartialEq)]
+enum RelayResponse {
+	Value(u64),
+	Sent,
+	Failed(String),
+}
+
+impl RelayResponse {
+	fn from_result(result: Result<RelayResponse, Error>) -> RelayResponse {
+		result.unwrap_or_else(|error| RelayResponse::Failed(format!("{error:?}")))
+	}
+}
+
+impl Worker for RelayWorker {
+	type Context = Address<FakeWorker>;
+	type Request = RelayRequest;
+	type Response = RelayResponse;
+	type Id = u32;
+
+	async fn create(_: &Self::Id, other_queue: Self::Context, _: &Mailbox<Self>) -> Self {
+		RelayWorker { other_queue }
+	}
+
+	async fn handle(&mut self, request: Self::Request, mailbox: &Mailbox<Self>) -> Self::Response {
+		match request {
+			RelayRequest::Echo(value) => RelayResponse::Value(value),
+			RelayRequest::Sleep(duration, value) => {
+				tokio::time::sleep(duration).await;
+
+				RelayResponse::Value(value)
+			}
+			RelayRequest::EnqueueForPeer(peer, value) => {
+				RelayResponse::from_result(mailbox.enqueue(&peer, RelayRequest::Echo(value)).await.map(|_| RelayResponse::Sent))
+			}
+			RelayRequest::ScheduleForPeer(peer, value) => RelayResponse::from_result(
+				mailbox
+					.address()
+					.enqueue_after(&peer, RelayRequest::Echo(value), Duration::ZERO)
+					.map(|_| RelayResponse::Sent),
+			),
+			RelayRequest::AskPeer(peer, value) => {
+				mailbox.ask(mailbox.address(), &peer, RelayRequest::Echo(value), |response| {
+					RelayRequest::Reply(RelayResponse::from_result(response))
+				});
+
+				RelayResponse::Sent
+			}
+			RelayRequest::AskOtherQueue(id, request) => {
+				mailbox.ask(&self.other_queue, &id, request, |response| {
+					RelayRequest::Reply(RelayResponse::from_result(response.map(RelayResponse::Value)))
+				});
+
+				RelayResponse::Sent
+			}
+			RelayRequest::Reply(response) => response,
+		}
+	}
+
+	async fn destroy(self) {}
+}
+
+fn build_relay(recorder: &Recorder<u32>) -> (Arc<Queue<FakeWorker>>, Queue<RelayWorker>) {
+	build_relay_with(recorder, QueueBuilder::default())
+}
+
+fn build_relay_with(recorder: &Recorder<u32>, builder: QueueBuilder) -> (Arc<Queue<FakeWorker>>, Queue<RelayWorker>) {
+	let other_queue = build(recorder);
+	let relay_queue = builder.build::<RelayWorker>(other_queue.address());
+
+	(other_queue, relay_queue)
+}
+
+#[tokio::test(start_paused = true)]
+async fn worker_enqueues_for_a_peer() {
+	let recorder = Recorder::new();
+	let (_other_queue, queue) = build_relay(&recorder);
+
+	queue.enqueue(&1, RelayRequest::EnqueueForPeer(2, 7)).await.unwrap();
+	settle().await;
+
+	assert_eq!(queue.poll(&1).await.unwrap(), RelayResponse::Sent);
+	assert_eq!(queue.poll(&2).await.unwrap(), RelayResponse::Value(7));
+}
+
+#[tokio::test(start_paused = true)]
+async fn worker_asks_a_peer_and_receives_the_reply() {
+	let recorder = Recorder::new();
+	let (_other_queue, queue) = build_relay(&recorder);
+
+	queue.enqueue(&1, RelayRequest::AskPeer(2, 7)).await.unwrap();
+	settle().await;
+
+	assert_eq!(queue.poll_many(&1).await.unwrap(), [RelayResponse::Sent, RelayResponse::Value(7)]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn worker_asks_a_worker_of_another_queue_and_receives_the_reply() {
+	let recorder = Recorder::new();
+	let (_other_queue, queue) = build_relay(&recorder);
+
+	queue.enqueue(&1, RelayRequest::AskOtherQueue(3, FakeRequest::Echo(7))).await.unwrap();
+	settle().await;
+
+	assert_eq!(queue.poll_many(&1).await.unwrap(), [RelayResponse::Sent, RelayResponse::Value(7)]);
+	recorder.assert_events(&[Lifecycle::Created(3)]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn reply_waits_for_room_when_the_asking_worker_is_at_capacity() {
+	let recorder = Recorder::new();
+	let (_other_queue, queue) = build_relay_with(&recorder, QueueBuilder::default().max_length(1).backpressure(Backpressure::Wait(LONG)));
+
+	queue
+		.enqueue(&1, RelayRequest::AskOtherQueue(3, FakeRequest::Sleep(Duration::from_secs(5), 7)))
+		.await
+		.unwrap();
+	settle().await;
+
+	// keep the asking worker busy with a full queue for when the reply arrives
+	queue.enqueue(&1, RelayRequest::Sleep(Duration::from_secs(10), 1)).await.unwrap();
+	settle().await;
+	queue.enqueue(&1, RelayRequest::Echo(2)).await.unwrap();
+
+	advance(Duration::from_secs(5)).await;
+	advance(Duration::from_secs(5)).await;
+
+	assert_eq!(
+		queue.poll_many(&1).await.unwrap(),
+		[RelayResponse::Sent, RelayResponse::Value(1), RelayResponse::Value(2), RelayResponse::Value(7)]
+	);
+}
+
+#[tokio::test(start_paused = true)]
+async fn asking_a_queue_that_was_dropped_replies_with_an_error() {
+	let recorder = Recorder::new();
+	let (other_queue, queue) = build_relay(&recorder);
+
+	drop(other_queue);
+	queue.enqueue(&1, RelayRequest::AskOtherQueue(3, FakeRequest::Echo(7))).await.unwrap();
+	settle().await;
+
+	assert_eq!(
+		queue.poll_many(&1).await.unwrap(),
+		[RelayResponse::Sent, RelayResponse::Failed(format!("{:?}", Error::NoWorker))]
+	);
+}
+
+#[tokio::test(start_paused = true)]
+async fn scheduling_for_a_terminated_peer_fails() {
+	let recorder = Recorder::new();
+	let (_other_queue, queue) = build_relay(&recorder);
+
+	queue.enqueue(&2, RelayRequest::Echo(1)).await.unwrap();
+	settle().await;
+	queue.terminate(&2);
+	settle().await;
+
+	queue.enqueue(&1, RelayRequest::ScheduleForPeer(2, 7)).await.unwrap();
+	settle().await;
+
+	assert_eq!(queue.poll(&1).await.unwrap(), RelayResponse::Failed(format!("{:?}", Error::NoWorker)));
+	assert!(matches!(queue.poll(&2).await, Err(Error::NoWorker)));
+}
+
+#[tokio::test(start_paused = true)]
+async fn address_does_not_keep_the_queue_alive() {
+	let recorder = Recorder::new();
+	let queue = build(&recorder);
+	let address = queue.address();
+
+	drop(queue);
+
+	assert!(matches!(address.enqueue(&1, FakeRequest::Echo(1)).await, Err(Error::NoWorker)));
+}
+
+/// A request that makes a `CountingLocalWorker` panic
+const PANIC: u64 = u64::MAX;
+
+/// Counts it's requests in an `Rc`, which makes it `!Send`
+struct CountingLocalWorker {
+	id: u32,
+	count: Rc<Cell<u64>>,
+	recorder: Recorder<u32>,
+}
+
+impl LocalWorker for CountingLocalWorker {
+	type Context = Recorder<u32>;
+	type Request = u64;
+	type Response = (u64, String);
+	type Id = u32;
+
+	async fn create(id: &u32, recorder: Recorder<u32>, _mailbox: &Mailbox<Local<Self>>) -> Self {
+		recorder.record(Lifecycle::Created(*id));
+
+		CountingLocalWorker {
+			id: *id,
+			count: Rc::new(Cell::new(0)),
+			recorder,
+		}
+	}
+
+	async fn handle(&mut self, amount: u64, _mailbox: &Mailbox<Local<Self>>) -> (u64, String) {
+		let count = self.count.clone();
+
+		if amount == PANIC {
+			panic!("asked to panic");
+		}
+
+		// hold the `Rc` across an await point, which a `Send` worker couldn't do
+		tokio::task::yield_now().await;
+		count.set(count.get() + amount);
+
+		(count.get(), thread::current().name().unwrap_or_default().to_string())
+	}
+
+	async fn destroy(self) {
+		self.recorder.record(Lifecycle::Destroyed(self.id));
+	}
+}
+
+#[tokio::test]
+async fn local_worker_handles_requests_on_a_local_thread() {
+	let recorder = Recorder::new();
+	let queue = QueueBuilder::default().local_threads(2).build_local::<CountingLocalWorker>(recorder.clone());
+
+	let (first, first_thread) = queue.request(&1, 1).await.unwrap().unwrap();
+	let (second, second_thread) = queue.request(&1, 2).await.unwrap().unwrap();
+
+	assert_eq!((first, second), (1, 3));
+	assert!(first_thread.starts_with("async_worker-local-"));
+	assert_eq!(first_thread, second_thread);
+
+	queue.enqueue(&1, 4).await.unwrap();
+	assert_eq!(queue.poll(&1).await.unwrap().unwrap().0, 7);
+}
+
+#[tokio::test]
+async fn local_worker_is_destroyed_when_terminated() {
+	let recorder = Recorder::new();
+	let queue = QueueBuilder::default().local_threads(2).build_local::<CountingLocalWorker>(recorder.clone());
+
+	assert_eq!(queue.request(&1, 5).await.unwrap().unwrap().0, 5);
+
+	queue.terminate(&1);
+
+	// the new worker starts counting from scratch
+	assert_eq!(queue.request(&1, 1).await.unwrap().unwrap().0, 1);
+	recorder.assert_events(&[Lifecycle::Created(1), Lifecycle::Destroyed(1), Lifecycle::Created(1)]);
+}
+
+#[tokio::test]
+async fn local_worker_that_panics_gives_an_error_instead_of_a_response() {
+	let recorder = Recorder::new();
+	let queue = QueueBuilder::default().local_threads(1).build_local::<CountingLocalWorker>(recorder.clone());
+
+	assert!(matches!(queue.request(&1, PANIC).await, Ok(Err(Error::LocalWorkerStopped))));
+
+	// the worker is gone, but the thread that it was on keeps driving other workers
+	assert!(matches!(queue.request(&1, 1).await, Ok(Err(Error::LocalWorkerStopped))));
+	assert_eq!(queue.request(&2, 1).await.unwrap().unwrap().0, 1);
+}
+
+#[tokio::test(start_paused = true)]
+async fn subscriber_receives_responses() {
+	let recorder = Recorder::new();
+	let queue = build(&recorder);
+
+	queue.enqueue(&1, FakeRequest::Echo(1)).await.unwrap();
+	assert_eq!(queue.poll(&1).await.unwrap(), 1);
+
+	let mut responses = queue.subscribe(&1).unwrap();
+	queue.enqueue(&1, FakeRequest::Echo(2)).await.unwrap();
+	queue.enqueue(&1, FakeRequest::Echo(3)).await.unwrap();
+
+	assert_eq!(responses.next().await, Some(2));
+	assert_eq!(responses.next().await, Some(3));
+	assert!(matches!(queue.poll(&1).await, Err(Error::Ceeded)));
+
+	queue.terminate(&1);
+	assert_eq!(responses.next().await, None);
+}
+
+#[tokio::test(start_paused = true)]
+async fn dropping_a_subscriber_falls_back_to_polling() {
+	let recorder = Recorder::new();
+	let queue = build(&recorder);
+
+	queue.enqueue(&1, FakeRequest::Echo(1)).await.unwrap();
+	let mut responses = queue.subscribe(&1).unwrap();
+	assert_eq!(responses.next().await, Some(1));
+
+	drop(responses);
+
+	// the worker only notices that the subscriber is gone once it has a response for it, which is then kept for polling
+	queue.enqueue(&1, FakeRequest::Echo(2)).await.unwrap();
+	settle().await;
+
+	assert_eq!(queue.poll(&1).await.unwrap(), 2);
+}
+
+#[tokio::test(start_paused = true)]
+async fn subscriber_and_registered_handle_ceed_to_each_other() {
+	let recorder = Recorder::new();
+	let queue = build_with_handle(&recorder);
+	let first = FakeHandle::new();
+	let second = FakeHandle::new();
+
+	queue.enqueue(&1, FakeRequest::Echo(1)).await.unwrap();
+	queue.register_handle(&1, first.clone()).unwrap();
+	settle().await;
+
+	let mut responses = queue.subscribe(&1).unwrap();
+	queue.enqueue(&1, FakeRequest::Echo(2)).await.unwrap();
+
+	assert_eq!(responses.next().await, Some(2));
+	assert_eq!(first.take_responses(), [1]);
+	assert_eq!(first.drop_reason(), Some(DropReason::Ceeded));
+
+	queue.register_handle(&1, second.clone()).unwrap();
+	queue.enqueue(&1, FakeRequest::Echo(3)).await.unwrap();
+	settle().await;
+
+	assert_eq!(responses.next().await, None);
+	assert_eq!(second.take_responses(), [3]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn subscribe_requires_a_worker() {
+	let recorder = Recorder::new();
+	let queue = build(&recorder);
+
+	assert!(matches!(queue.subscribe(&1), Err(Error::NoWorker)));
+}
+
+const SHARD_COUNT: usize = 4;
+
+/// Build a sharded queue that only allows one worker per shard, so that the ids which are accepted each live on a different shard
+fn build_one_worker_per_shard(recorder: &Recorder<u32>) -> ShardedQueue<FakeWorker> {
+	QueueBuilder::default()
+		.shards(SHARD_COUNT)
+		.max_workers(SHARD_COUNT, WorkerOverflow::Reject)
+		.build_sharded::<FakeWorker>(recorder.clone())
+}
+
+#[tokio::test(start_paused = true)]
+async fn same_id_is_always_routed_to_the_same_shard() {
+	let recorder = Recorder::new();
+	let queue = build_one_worker_per_shard(&recorder);
+
+	queue.enqueue(&1, FakeRequest::Echo(1)).await.unwrap();
+
+	// if any of these were routed to a different shard, a second worker would be created for the id
+	for value in 2..=16 {
+		queue.enqueue(&1, FakeRequest::Echo(value)).await.unwrap();
+		assert_eq!(queue.poll(&1).await.unwrap(), value - 1);
+	}
+
+	assert_eq!(queue.poll(&1).await.unwrap(), 16);
+	recorder.assert_events(&[Lifecycle::Created(1)]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn responses_come_back_from_every_shard() {
+	let recorder = Recorder::new();
+	let queue = build_one_worker_per_shard(&recorder);
+	let mut accepted = Vec::new();
+
+	for id in 0..64 {
+		match queue.enqueue(&id, FakeRequest::Echo(id as u64)).await {
+			Ok(_) => accepted.push(id),
+			Err(error) => assert!(matches!(error, Error::TooManyWorkers)),
+		}
+	}
+
+	// every shard has room for exactly one worker
+	assert_eq!(accepted.len(), SHARD_COUNT);
+
+	for id in accepted {
+		assert_eq!(queue.poll(&id).await.unwrap(), id as u64);
+	}
+}
+
+#[tokio::test(start_paused = true)]
+async fn pending_request_is_skipped_when_cancelled() {
+	let recorder = Recorder::new();
+	let queue = build(&recorder);
+
+	queue.enqueue(&1, FakeRequest::Sleep(LONG, 1)).await.unwrap();
+	let ticket = queue.enqueue(&1, FakeRequest::Echo(2)).await.unwrap();
+	queue.enqueue(&1, FakeRequest::Echo(3)).await.unwrap();
+	settle().await;
+
+	ticket.cancel();
+	advance(LONG).await;
+
+	assert_eq!(queue.poll_many(&1).await.unwrap(), [1, 3]);
+
+	// `Worker::cancelled` is only called for requests that were being handled
+	recorder.assert_events(&[Lifecycle::Created(1)]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn running_request_is_dropped_when_cancelled() {
+	let recorder = Recorder::new();
+	let queue = build(&recorder);
+
+	let ticket = queue.enqueue(&1, FakeRequest::Sleep(LONG, 1)).await.unwrap();
+	settle().await;
+
+	ticket.cancel();
+	settle().await;
+
+	// the worker moves on without waiting for the cancelled request to finish sleeping
+	queue.enqueue(&1, FakeRequest::Echo(2)).await.unwrap();
+	settle().await;
+
+	assert_eq!(queue.poll_many(&1).await.unwrap(), [2]);
+	recorder.assert_events(&[Lifecycle::Created(1), Lifecycle::Cancelled(1)]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn cancelling_a_handled_request_does_nothing() {
+	let recorder = Recorder::new();
+	let queue = build(&recorder);
+
+	let ticket = queue.enqueue(&1, FakeRequest::Echo(1)).await.unwrap();
+	settle().await;
+
+	ticket.cancel();
+	settle().await;
+
+	assert!(ticket.is_cancelled());
+	assert_eq!(queue.poll(&1).await.unwrap(), 1);
+	recorder.assert_events(&[Lifecycle::Created(1)]);
+}
+
+fn build_concurrent(recorder: &Recorder<u32>) -> Queue<Concurrent<FakeWorker>, FakeHandle<u64>> {
+	QueueBuilder::default().build_with_handle::<Concurrent<FakeWorker>, FakeHandle<u64>>(recorder.clone())
+}
+
+#[tokio::test(start_paused = true)]
+async fn concurrent_requests_with_the_same_key_run_in_order() {
+	let recorder = Recorder::new();
+	let queue = build_concurrent(&recorder);
+	let handle = FakeHandle::new();
+
+	queue.enqueue(&1, FakeRequest::Keyed(1, Duration::from_secs(10), 1)).await.unwrap();
+	queue.enqueue(&1, FakeRequest::Keyed(1, Duration::from_secs(1), 2)).await.unwrap();
+	queue.register_handle(&1, handle.clone()).unwrap();
+	settle().await;
+
+	// the second request doesn't start until the first has completed, even though it is quicker
+	advance(Duration::from_secs(1)).await;
+	assert!(handle.take_responses().is_empty());
+
+	advance(Duration::from_secs(9)).await;
+	assert_eq!(handle.take_responses(), [1]);
+
+	advance(Duration::from_secs(1)).await;
+	assert_eq!(handle.take_responses(), [2]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn concurrent_requests_with_different_keys_overlap() {
+	let recorder = Recorder::new();
+	let queue = build_concurrent(&recorder);
+	let handle = FakeHandle::new();
+
+	queue.enqueue(&1, FakeRequest::Keyed(1, Duration::from_secs(10), 1)).await.unwrap();
+	queue.enqueue(&1, FakeRequest::Keyed(2, Duration::from_secs(1), 2)).await.unwrap();
+	queue.register_handle(&1, handle.clone()).unwrap();
+	settle().await;
+
+	advance(Duration::from_secs(1)).await;
+	assert_eq!(handle.take_responses(), [2]);
+
+	advance(Duration::from_secs(9)).await;
+	assert_eq!(handle.take_responses(), [1]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn concurrent_worker_is_destroyed_once_its_requests_complete() {
+	let recorder = Recorder::new();
+	let queue = build_concurrent(&recorder);
+
+	queue.enqueue(&1, FakeRequest::Keyed(1, Duration::from_secs(10), 1)).await.unwrap();
+	settle().await;
+
+	queue.terminate(&1);
+	advance(Duration::from_secs(1)).await;
+	recorder.assert_events(&[Lifecycle::Created(1)]);
+
+	advance(Duration::from_secs(9)).await;
+	recorder.assert_events(&[Lifecycle::Created(1), Lifecycle::Destroyed(1)]);
+}
@@ -0,0 +1,63 @@
+use std::{
+	pin::pin,
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Arc,
+	},
+};
+use tokio::sync::Notify;
+
+struct TicketState {
+	is_cancelled: AtomicBool,
+	cancelled: Notify,
+}
+
+/// Returned by `Queue::enqueue`, and can be used to cancel the enqueued request.
+///
+/// If the request is still pending when it is cancelled, it will be skipped without ever being handled. If it is already being handled, the
+/// `Worker::handle` future is dropped at it's next await point, and `Worker::cancelled` is called. Either way, no response is produced. Cancelling
+/// a request that has already been handled does nothing.
+///
+/// When a request is merged into a pending one by `QueueBuilder::coalesce`, the merged request takes on the ticket of the newer request.
+#[derive(Clone)]
+pub struct Ticket {
+	state: Arc<TicketState>,
+}
+
+impl Ticket {
+	pub(crate) fn new() -> Ticket {
+		Ticket {
+			state: Arc::new(TicketState {
+				is_cancelled: AtomicBool::new(false),
+				cancelled: Notify::new(),
+			}),
+		}
+	}
+
+	/// Cancel the request that this ticket was issued for.
+	pub fn cancel(&self) {
+		self.state.is_cancelled.store(true, Ordering::Release);
+		self.state.cancelled.notify_waiters();
+	}
+
+	/// Returns true if `Ticket::cancel` has been called on this ticket or any of it's clones.
+	pub fn is_cancelled(&self) -> bool {
+		self.state.is_cancelled.load(Ordering::Acquire)
+	}
+
+	/// Waits for the ticket to be cancelled.
+	///
+	/// This function is cancel safe.
+	pub(crate) async fn cancelled(&self) {
+		loop {
+			let mut cancelled = pin!(self.state.cancelled.notified());
+			cancelled.as_mut().enable();
+
+			if self.is_cancelled() {
+				return;
+			}
+
+			cancelled.await;
+		}
+	}
+}
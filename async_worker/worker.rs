@@ -1,12 +1,21 @@
-use log::{debug, info};
+use log::{debug, error, info};
 use std::{collections::VecDeque, fmt::Debug, future::Future, hash::Hash, time::Duration};
 use tokio::{
 	select,
 	sync::{mpsc, oneshot},
-	time::sleep,
+	task::JoinSet,
+	time::{sleep_until, Instant},
 };
 
-use crate::handle::{recv_from_handle, DropReason, SendResult, WorkerHandle};
+use crate::{
+	activity::Activity,
+	address::{Address, BoxFuture, Mailbox},
+	channel::Receiver,
+	handle::{recv_from_handle, AttachedHandle, ChannelHandle, DropReason, SendResult, WorkerHandle},
+	journal::Receipt,
+	schedule::Timers,
+	ticket::Ticket,
+};
 
 pub enum InternalPollResponse<T: Sized> {
 	Ok(T),
@@ -24,32 +33,89 @@ pub enum TaskMessage<Request: Sized, Response: Sized, Handle: WorkerHandle<Reque
 	RegisterHandle {
 		handle: Handle,
 	},
+	Subscribe {
+		sender: mpsc::Sender<Response>,
+	},
 	Enqueue {
 		request: Request,
+		ticket: Option<Ticket>,
+		receipt: Option<Receipt>,
+	},
+	Request {
+		request: Request,
+		responder: oneshot::Sender<Response>,
+	},
+	Schedule {
+		request: Request,
+		at: Instant,
+	},
+	RegisterInterval {
+		period: Duration,
+		make_request: Box<dyn FnMut() -> Request + Send>,
+	},
+	/// Produced by the worker's own task when a request that was started via `Worker::handle_concurrently` finishes. `response` is `None` if
+	/// the request was cancelled.
+	Completed {
+		response: Option<Response>,
+		responder: Option<oneshot::Sender<Response>>,
 	},
 }
 
 pub struct SpawnMessage<W: Worker, Handle: WorkerHandle<W::Request, W::Response>> {
 	pub id: W::Id,
 	pub context: W::Context,
-	pub message_receiver: mpsc::Receiver<TaskMessage<W::Request, W::Response, Handle>>,
-	pub initial_request: W::Request,
+	pub message_receiver: Receiver<TaskMessage<W::Request, W::Response, Handle>>,
+	pub address: Address<W>,
+	/// The worker's place in the queue's order of activity, which the queue evicts the least recently active worker from when it needs to make
+	/// room under `QueueBuilder::max_workers`
+	pub activity: Activity<W::Id>,
 }
 
 pub trait Worker
 where
-	Self: Sized + Send,
+	Self: Sized + Send + 'static,
 {
-	type Context: 'static + Send + Sized + Clone;
+	type Context: 'static + Send + Sync + Sized + Clone;
 	type Request: 'static + Send + Sized;
 	type Response: 'static + Send + Sized;
-	type Id: 'static + Hash + PartialOrd + Eq + Clone + Send + Debug;
+	type Id: 'static + Hash + PartialOrd + Eq + Clone + Send + Sync + Debug;
+
+	/// Creates a new worker, which will be referenced to by the queue as `id`. `context` is a clone of the context that was given to the queue when it was built.
+	/// `mailbox` can be used to send requests to this worker, it's peers, and the workers of other queues.
+	fn create(id: &Self::Id, context: Self::Context, mailbox: &Mailbox<Self>) -> impl Future<Output = Self> + Send;
+
+	/// Handle a new response. The output will be able to be attained by the queue via a `WorkerHandle` or polling, or directly by the requester if the
+	/// request was sent via `Queue::request`.
+	fn handle(&mut self, request: Self::Request, mailbox: &Mailbox<Self>) -> impl Future<Output = Self::Response> + Send;
 
-	/// Creates a new worker, which will be referenced to by the queue as `id`. `context` is a clone of the context that was given to the queue when it was built
-	fn create(id: &Self::Id, context: Self::Context) -> impl Future<Output = Self> + Send;
+	/// Called after a request was cancelled via it's `Ticket` while it was being handled, and the `Worker::handle` future was dropped. Because the
+	/// request could have been stopped at any await point, this is the place to restore any state that it left half-updated.
+	///
+	/// Requests that are cancelled before being handled are skipped without calling this.
+	fn cancelled(&mut self, mailbox: &Mailbox<Self>) -> impl Future<Output = ()> + Send {
+		let _ = mailbox;
 
-	/// Handle a new response. The output will be able to be attained by the queue via a `WorkerHandle` or polling.
-	fn handle(&mut self, request: Self::Request) -> impl Future<Output = Self::Response> + Send;
+		async {}
+	}
+
+	/// Start handling a request without holding up the requests that come after it. If a future is returned, it is run alongside the worker's
+	/// other requests and it's output is treated just like that of `Worker::handle`. If the request is given back, it is handled by
+	/// `Worker::handle` as usual.
+	///
+	/// Workers that handle one request at a time don't need to implement this. See `ConcurrentWorker` for workers that want to.
+	fn handle_concurrently(&mut self, request: Self::Request, mailbox: &Mailbox<Self>) -> std::result::Result<BoxFuture<Self::Response>, Self::Request> {
+		let _ = mailbox;
+
+		Err(request)
+	}
+
+	/// Called before `Worker::destroy` when this worker is evicted to make room for a new one under `QueueBuilder::max_workers`. The worker will be
+	/// created from scratch the next time a request comes in for it's id, so this is the place to snapshot any state that should outlive it.
+	fn evicted(&mut self, mailbox: &Mailbox<Self>) -> impl Future<Output = ()> + Send {
+		let _ = mailbox;
+
+		async {}
+	}
 
 	/// Called just before this worker is dropped, but after the worker handle (if present) was dropped and any ongoing polls were closed with an `Error::WorkerTerminated`.
 	fn destroy(self) -> impl Future<Output = ()> + Send;
@@ -58,39 +124,65 @@ where
 pub async fn drive_workers<W, H>(worker_inactivity_timeout: Duration, mut spawn_receiver: mpsc::Receiver<SpawnMessage<W, H>>)
 where
 	W: Worker,
-	H: WorkerHandle<W::Request, W::Response> + Send + Sync + 'static,
+	H: WorkerHandle<W::Request, W::Response> + Send + 'static,
 {
 	loop {
 		let SpawnMessage {
 			id,
 			context,
 			mut message_receiver,
-			initial_request,
+			address,
+			mut activity,
 		} = match spawn_receiver.recv().await {
 			Some(message) => message,
 			None => break,
 		};
 
 		tokio::spawn(async move {
-			let mut worker = W::create(&id, context).await;
+			let mailbox = Mailbox::new(id.clone(), address);
+			let mut worker = W::create(&id, context, &mailbox).await;
 			let mut response_list = VecDeque::<W::Response>::new();
-			let mut stashed_handle = Option::<H>::None;
+			let mut stashed_handle = Option::<AttachedHandle<W::Request, W::Response, H>>::None;
 			let mut single_response_sender = Option::<ResponseSender<W::Response>>::None;
-
-			response_list.push_back(worker.handle(initial_request).await);
+			let mut timers = Timers::<W::Request>::new();
+			let mut last_activity = Instant::now();
+			let mut in_flight = JoinSet::<(Option<W::Response>, Option<oneshot::Sender<W::Response>>)>::new();
+			let mut is_terminating = false;
 
 			loop {
-				let message = select! {
-					message = message_receiver.recv() => match message {
-						Some(message) => message,
+				// a terminated worker still finishes the requests that it is concurrently handling
+				if is_terminating && in_flight.is_empty() {
+					break;
+				}
+
+				// requests that are delivered by timers are not considered activity, otherwise an interval would keep it's worker alive forever
+				let (message, is_activity) = select! {
+					message = message_receiver.recv(), if !is_terminating => match message {
+						Some(message) => (message, true),
 						None => {
 							debug!("worker {id:?} was manually terminated");
 
-							break
+							is_terminating = true;
+							continue
 						},
 					},
-					message = recv_from_handle::<W, H>(stashed_handle.as_mut()) => match message {
-						Some(request) => TaskMessage::Enqueue { request },
+					Some(result) = in_flight.join_next(), if !in_flight.is_empty() => match result {
+						Ok((response, responder)) => (TaskMessage::Completed { response, responder }, true),
+						Err(error) => {
+							error!("a request for worker {id:?} failed to complete: {error}");
+
+							continue
+						}
+					},
+					message = recv_from_handle::<W, _>(stashed_handle.as_mut()) => match message {
+						Some(request) => (
+							TaskMessage::Enqueue {
+								request,
+								ticket: None,
+								receipt: None,
+							},
+							true,
+						),
 						None => {
 							debug!("worker {id:?} just had it's handle close");
 
@@ -100,16 +192,29 @@ where
 							continue
 						}
 					},
-					_ = sleep(worker_inactivity_timeout) => {
+					request = timers.next() => (
+						TaskMessage::Enqueue {
+							request,
+							ticket: None,
+							receipt: None,
+						},
+						false,
+					),
+					_ = sleep_until(last_activity + worker_inactivity_timeout), if in_flight.is_empty() => {
 						debug!("task {id:?} was terminated due to an inactivity timeout of {}s", worker_inactivity_timeout.as_secs());
 
 						break
 					},
 				};
 
+				// the queue's view of activity is updated before the message is handled so that a worker that is busy is never seen as idle
+				if is_activity {
+					activity.touch();
+				}
+
 				match message {
 					TaskMessage::Poll { responder } => {
-						if let None = stashed_handle {
+						if stashed_handle.is_none() {
 							match response_list.pop_front() {
 								Some(waiting_response) => {
 									if let Err(rejected) = responder.send(InternalPollResponse::Ok(waiting_response)) {
@@ -132,7 +237,7 @@ where
 						}
 					}
 					TaskMessage::PollMany { responder } => {
-						if let None = stashed_handle {
+						if stashed_handle.is_none() {
 							let waiting_responses = response_list.drain(..).collect::<Vec<_>>();
 
 							if !waiting_responses.is_empty() {
@@ -152,61 +257,85 @@ where
 							let _ = responder.send(InternalPollResponse::Ceeded);
 						}
 					}
-					TaskMessage::RegisterHandle { mut handle } => {
-						let mut did_close = false;
-
-						loop {
-							let waiting_message = match response_list.pop_front() {
-								Some(response) => response,
-								None => break,
-							};
-
-							match handle.send(waiting_message).await {
-								SendResult::Sent => (),
-								SendResult::Closed(rejected) => {
-									did_close = true;
-									response_list.push_front(rejected);
+					TaskMessage::RegisterHandle { handle } => {
+						attach_handle(AttachedHandle::Registered(handle), &mut stashed_handle, &mut response_list).await;
+					}
+					TaskMessage::Subscribe { sender } => {
+						let handle = AttachedHandle::Subscription(ChannelHandle::new(sender));
+
+						attach_handle(handle, &mut stashed_handle, &mut response_list).await;
+					}
+					TaskMessage::Enqueue { request, ticket, mut receipt } => {
+						let response = match ticket {
+							Some(ticket) if ticket.is_cancelled() => {
+								debug!("skipping a request for worker {id:?} that was cancelled before it was handled");
+
+								None
+							}
+							ticket => match worker.handle_concurrently(request, &mailbox) {
+								Ok(future) => {
+									// the receipt is acknowledged once the request completes
+									in_flight.spawn(complete_concurrently(future, ticket, receipt.take(), None));
+
+									None
 								}
-								SendResult::Failed(rejected) => response_list.push_front(rejected),
-							};
+								Err(request) => match ticket {
+									Some(ticket) => {
+										let response = select! {
+											biased;
+
+											response = worker.handle(request, &mailbox) => Some(response),
+											_ = ticket.cancelled() => None,
+										};
+
+										if response.is_none() {
+											debug!("a request for worker {id:?} was cancelled while being handled");
+											worker.cancelled(&mailbox).await;
+										}
+
+										response
+									}
+									None => Some(worker.handle(request, &mailbox).await),
+								},
+							},
+						};
+
+						if let Some(receipt) = receipt {
+							receipt.acknowledge();
 						}
 
-						if !did_close {
-							if let Some(mut old_handle) = stashed_handle.replace(handle) {
-								old_handle.will_drop(DropReason::Ceeded).await;
-							}
+						if let Some(response) = response {
+							deliver_response(response, &mut stashed_handle, &mut single_response_sender, &mut response_list).await;
 						}
 					}
-					TaskMessage::Enqueue { request } => {
-						let mut response = worker.handle(request).await;
-
-						if let Some(handle) = &mut stashed_handle {
-							loop {
-								match handle.send(response).await {
-									SendResult::Sent => break,
-									SendResult::Closed(rejected) => {
-										handle.will_drop(DropReason::HandleClosed).await;
-										stashed_handle = None;
-
-										response_list.push_back(rejected);
-										break;
-									}
-									SendResult::Failed(rejected) => response = rejected,
-								};
+					TaskMessage::Request { request, responder } => match worker.handle_concurrently(request, &mailbox) {
+						Ok(future) => {
+							in_flight.spawn(complete_concurrently(future, None, None, Some(responder)));
+						}
+						Err(request) => {
+							if responder.send(worker.handle(request, &mailbox).await).is_err() {
+								debug!("requester for worker {id:?} stopped waiting before the response was ready");
 							}
-						} else if let Some(sender) = single_response_sender.take() {
-							if let Err(rejected) = sender.send_new(InternalPollResponse::Ok(response)) {
-								response_list.push_back(match rejected {
-									InternalPollResponse::Ok(rejected) => rejected,
-									InternalPollResponse::Ceeded => unreachable!(),
-									InternalPollResponse::WorkerTerminated => unreachable!(),
-								});
-								single_response_sender = None;
+						}
+					},
+					TaskMessage::Schedule { request, at } => timers.insert_once(at, request),
+					TaskMessage::RegisterInterval { period, make_request } => timers.insert_interval(period, make_request),
+					TaskMessage::Completed { response, responder } => match (response, responder) {
+						(None, _) => {
+							debug!("a request for worker {id:?} was cancelled while being handled");
+							worker.cancelled(&mailbox).await;
+						}
+						(Some(response), Some(responder)) => {
+							if responder.send(response).is_err() {
+								debug!("requester for worker {id:?} stopped waiting before the response was ready");
 							}
-						} else {
-							response_list.push_back(response);
 						}
-					}
+						(Some(response), None) => deliver_response(response, &mut stashed_handle, &mut single_response_sender, &mut response_list).await,
+					},
+				}
+
+				if is_activity {
+					last_activity = Instant::now();
 				}
 			}
 
@@ -221,11 +350,103 @@ where
 				let _ = responder.send_new(InternalPollResponse::WorkerTerminated);
 			}
 
+			if activity.is_evicted() {
+				worker.evicted(&mailbox).await;
+			}
+
 			worker.destroy().await;
 		});
 	}
 }
 
+/// Run a request that was started via `Worker::handle_concurrently` to completion, unless it's ticket is cancelled first.
+async fn complete_concurrently<Response>(
+	future: BoxFuture<Response>,
+	ticket: Option<Ticket>,
+	receipt: Option<Receipt>,
+	responder: Option<oneshot::Sender<Response>>,
+) -> (Option<Response>, Option<oneshot::Sender<Response>>) {
+	let response = match ticket {
+		Some(ticket) => select! {
+			biased;
+
+			response = future => Some(response),
+			_ = ticket.cancelled() => None,
+		},
+		None => Some(future.await),
+	};
+
+	if let Some(receipt) = receipt {
+		receipt.acknowledge();
+	}
+
+	(response, responder)
+}
+
+/// Send the responses that are waiting to be polled to `handle`, and then make it the worker's handle in place of the previous one, unless it
+/// closed while they were being sent.
+async fn attach_handle<Request, Response, H: WorkerHandle<Request, Response>>(
+	mut handle: H,
+	stashed_handle: &mut Option<H>,
+	response_list: &mut VecDeque<Response>,
+) {
+	if send_waiting_responses(&mut handle, response_list).await {
+		if let Some(mut old_handle) = stashed_handle.replace(handle) {
+			old_handle.will_drop(DropReason::Ceeded).await;
+		}
+	}
+}
+
+/// Send the responses in `response_list` to `handle` in order, returning false if the handle closed. Once the handle fails to take a response,
+/// it and the ones after it are kept in `response_list` so that they are retried, in order, before the next response is sent.
+async fn send_waiting_responses<Request, Response, H: WorkerHandle<Request, Response>>(handle: &mut H, response_list: &mut VecDeque<Response>) -> bool {
+	while let Some(response) = response_list.pop_front() {
+		match handle.send(response).await {
+			SendResult::Sent => (),
+			SendResult::Closed(rejected) => {
+				response_list.push_front(rejected);
+
+				return false;
+			}
+			SendResult::Failed(rejected) => {
+				response_list.push_front(rejected);
+
+				break;
+			}
+		}
+	}
+
+	true
+}
+
+/// Send a response to the worker's handle if there is one, otherwise to the waiting poll, otherwise keep it until the next poll. If the handle
+/// closes, the responses that it didn't take are kept for polling.
+async fn deliver_response<Response, H: WorkerHandle<Request, Response>, Request>(
+	response: Response,
+	stashed_handle: &mut Option<H>,
+	single_response_sender: &mut Option<ResponseSender<Response>>,
+	response_list: &mut VecDeque<Response>,
+) {
+	if let Some(handle) = stashed_handle {
+		response_list.push_back(response);
+
+		if !send_waiting_responses(handle, response_list).await {
+			handle.will_drop(DropReason::HandleClosed).await;
+			*stashed_handle = None;
+		}
+	} else if let Some(sender) = single_response_sender.take() {
+		if let Err(rejected) = sender.send_new(InternalPollResponse::Ok(response)) {
+			response_list.push_back(match rejected {
+				InternalPollResponse::Ok(rejected) => rejected,
+				InternalPollResponse::Ceeded => unreachable!(),
+				InternalPollResponse::WorkerTerminated => unreachable!(),
+			});
+		}
+	} else {
+		response_list.push_back(response);
+	}
+}
+
 enum ResponseSender<Response> {
 	Single(oneshot::Sender<InternalPollResponse<Response>>),
 	Many(oneshot::Sender<InternalPollResponse<Vec<Response>>>),
@@ -0,0 +1,227 @@
+use anyhow::{anyhow, Context, Result};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use log::info;
+use serde::Serialize;
+use serde_json::{from_slice, ser::PrettyFormatter, Serializer};
+use sha2::{Digest, Sha256};
+use std::{
+	collections::BTreeMap,
+	io::ErrorKind,
+	path::{Component, Path},
+};
+use tokio::fs::{canonicalize, read, write};
+use url::Url;
+use walkdir::WalkDir;
+
+use crate::{
+	asset_loader::RawAsset,
+	diagnostic::{Diagnostic, DiagnosticList},
+};
+
+#[derive(Debug, Clone, Copy)]
+pub struct IndexAssetsParams<'a> {
+	/// The directory that is scanned for assets. Must be inside of the index's directory, because local paths are relative to the index.
+	pub directory: &'a Path,
+	pub index_path: &'a Path,
+	/// Globs, relative to `directory`, that a file must match to be indexed. Everything is indexed if there are none.
+	pub include: &'a [String],
+	/// Globs, relative to `directory`, of files that are not indexed, even if they are included
+	pub exclude: &'a [String],
+	/// Prepended to each file's path relative to `directory` to get it's web path
+	pub web_prefix: &'a str,
+	/// Don't write the index, but report every difference between it and the directory as an error
+	pub check: bool,
+}
+
+/// A difference between an asset index and the directory that it indexes
+#[derive(Debug)]
+enum Drift {
+	Added(RawAsset),
+	Changed(RawAsset),
+	Removed(RawAsset),
+}
+
+/// Write an index of every file in a directory, or check that an existing one is up to date. Entries that are not in the directory, such as
+/// remote assets, are left alone, and the order of existing entries is kept, with new ones added to the end.
+pub async fn index_assets(params: IndexAssetsParams<'_>, diagnostic_list: &mut DiagnosticList) -> Result<()> {
+	let index_directory = match params.index_path.parent() {
+		Some(parent) if parent != Path::new("") => parent,
+		_ => Path::new("."),
+	};
+	let index_directory = canonicalize(index_directory)
+		.await
+		.with_context(|| format!("failed to find the directory of the asset index at {:?}", params.index_path))?;
+	let directory = canonicalize(params.directory)
+		.await
+		.with_context(|| format!("failed to find the asset directory at {:?}", params.directory))?;
+	let directory_local_path = get_relative_path(&directory, &index_directory).ok_or(anyhow!(
+		"the asset directory at {:?} must be inside of {index_directory:?}, the directory of the asset index, because the index's local paths \
+		 are relative to it",
+		params.directory
+	))?;
+
+	let existing = match read(params.index_path).await {
+		Ok(bytes) => from_slice::<Vec<RawAsset>>(&bytes).with_context(|| format!("failed to parse the asset index at {:?}", params.index_path))?,
+		Err(error) if error.kind() == ErrorKind::NotFound => Vec::new(),
+		Err(error) => return Err(error).with_context(|| format!("failed to read the asset index at {:?}", params.index_path)),
+	};
+	let index_file_name = params
+		.index_path
+		.file_name()
+		.ok_or(anyhow!("{:?} is not a valid path for an asset index", params.index_path))?;
+	let mut scanned = scan(params, &directory, &directory_local_path, &index_directory.join(index_file_name)).await?;
+
+	let mut assets = Vec::new();
+	let mut drifts = Vec::new();
+
+	for asset in existing {
+		// entries outside of the directory, or that are urls, were not put in the index by scanning it
+		let is_scanned =
+			Url::parse(&asset.local_path).is_err() && (directory_local_path.is_empty() || asset.local_path.starts_with(&format!("{directory_local_path}/")));
+
+		if !is_scanned {
+			assets.push(asset);
+			continue;
+		}
+
+		match scanned.remove(&asset.local_path) {
+			Some(scanned_asset) => {
+				if scanned_asset != asset {
+					drifts.push(Drift::Changed(scanned_asset.clone()));
+				}
+
+				assets.push(scanned_asset);
+			}
+			None => drifts.push(Drift::Removed(asset)),
+		}
+	}
+
+	for (_, asset) in scanned {
+		drifts.push(Drift::Added(asset.clone()));
+		assets.push(asset);
+	}
+
+	if params.check {
+		for drift in &drifts {
+			diagnostic_list.add(
+				match drift {
+					Drift::Added(asset) => Diagnostic::start("Asset ").inline_code(&asset.local_path).text(" is not in the index"),
+					Drift::Changed(asset) => Diagnostic::start("The index entry for ")
+						.inline_code(&asset.local_path)
+						.text(" does not match it's contents or web path"),
+					Drift::Removed(asset) => Diagnostic::start("Asset ")
+						.inline_code(&asset.local_path)
+						.text(" is in the index, but does not exist"),
+				}
+				.shift()
+				.text(params.index_path.display())
+				.hint("run `objection assets index` without `--check` to update the index")
+				.build(),
+			);
+		}
+
+		diagnostic_list.flush("verify the asset index")?;
+		info!("{:?} is up to date", params.index_path);
+
+		return Ok(());
+	}
+
+	if drifts.is_empty() {
+		info!("{:?} is up to date", params.index_path);
+
+		return Ok(());
+	}
+
+	// existing indexes are indented with tabs, so that is kept, to avoid rewriting every line
+	let mut json = Vec::new();
+	assets.serialize(&mut Serializer::with_formatter(&mut json, PrettyFormatter::with_indent(b"\t")))?;
+	json.push(b'\n');
+
+	write(params.index_path, json)
+		.await
+		.with_context(|| format!("failed to write the asset index at {:?}", params.index_path))?;
+
+	info!(
+		"Updated {:?}: {} added, {} changed, {} removed",
+		params.index_path,
+		drifts.iter().filter(|drift| matches!(drift, Drift::Added(_))).count(),
+		drifts.iter().filter(|drift| matches!(drift, Drift::Changed(_))).count(),
+		drifts.iter().filter(|drift| matches!(drift, Drift::Removed(_))).count()
+	);
+
+	Ok(())
+}
+
+/// Hash every included file in `directory`, keyed by local path
+async fn scan(params: IndexAssetsParams<'_>, directory: &Path, directory_local_path: &str, index_path: &Path) -> Result<BTreeMap<String, RawAsset>> {
+	let include = build_glob_set(params.include)?;
+	let exclude = build_glob_set(params.exclude)?;
+	let mut assets = BTreeMap::new();
+
+	for entry in WalkDir::new(directory).sort_by_file_name() {
+		let entry = entry.with_context(|| format!("failed to scan {directory:?}"))?;
+
+		if !entry.file_type().is_file() || entry.path() == index_path {
+			continue;
+		}
+
+		let relative_path = get_relative_path(entry.path(), directory).ok_or(anyhow!("{:?} is not inside of {directory:?}", entry.path()))?;
+
+		if (!params.include.is_empty() && !include.is_match(&relative_path)) || exclude.is_match(&relative_path) {
+			continue;
+		}
+
+		let content = read(entry.path()).await.with_context(|| format!("failed to read {:?}", entry.path()))?;
+		let mut hasher = Sha256::new();
+		hasher.update(&content);
+
+		let local_path = join_path(directory_local_path, &relative_path);
+		let asset = RawAsset {
+			local_path: local_path.clone(),
+			web_path: join_path(params.web_prefix.trim_matches('/'), &relative_path),
+			sha256: hex::encode(hasher.finalize()),
+		};
+
+		assets.insert(local_path, asset);
+	}
+
+	Ok(assets)
+}
+
+fn build_glob_set(globs: &[String]) -> Result<GlobSet> {
+	let mut builder = GlobSetBuilder::new();
+
+	for glob in globs {
+		// like in a .gitignore, `*` does not match across directories, but `**` does
+		builder.add(
+			GlobBuilder::new(glob)
+				.literal_separator(true)
+				.build()
+				.with_context(|| format!("'{glob}' is not a valid glob"))?,
+		);
+	}
+
+	Ok(builder.build()?)
+}
+
+/// Get the path of `path` relative to `base`, joined with `/` on every platform, or `None` if `path` is not inside of `base`
+fn get_relative_path(path: &Path, base: &Path) -> Option<String> {
+	let components = path
+		.strip_prefix(base)
+		.ok()?
+		.components()
+		.map(|component| match component {
+			Component::Normal(name) => name.to_str(),
+			_ => None,
+		})
+		.collect::<Option<Vec<_>>>()?;
+
+	Some(components.join("/"))
+}
+
+fn join_path(prefix: &str, path: &str) -> String {
+	match prefix.is_empty() {
+		true => path.to_string(),
+		false => format!("{prefix}/{path}"),
+	}
+}
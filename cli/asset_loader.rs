@@ -1,23 +1,28 @@
-use anyhow::{anyhow, bail, Context, Result};
+use anyhow::{bail, Context, Result};
 use serde::{Deserialize, Serialize};
-use serde_json::{from_str, from_value, Value};
+use serde_json::{from_slice, from_value, Value};
 use sha2::{Digest, Sha256};
 use std::collections::{HashMap, HashSet};
-use tokio::fs::read_to_string;
 use url::Url;
 
 use crate::{
+	asset_pipeline::AssetPipeline,
 	diagnostic::{Diagnostic, DiagnosticList},
-	module_loader::InfoGraph,
+	lockfile::Lockfile,
+	module_cache::ModuleCache,
+	module_loader::ModuleOptions,
 	writer::Writer,
 };
 
-#[derive(Debug, Serialize, Deserialize)]
+/// An entry of an asset index, as it is written
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-struct RawAsset {
-	sha256: String,
-	local_path: String,
-	web_path: String,
+pub struct RawAsset {
+	/// Relative to the index
+	pub local_path: String,
+	pub web_path: String,
+	/// Hex encoded
+	pub sha256: String,
 }
 
 #[derive(Debug)]
@@ -53,6 +58,8 @@ pub struct AssetsLoader {
 	indexes: Vec<Url>,
 	web_paths: HashSet<String>,
 	assets: Vec<Asset>,
+	/// The cache that remote assets are read through, so that they respect `--frozen` and `--vendor-dir`. Set by `load`
+	module_cache: Option<ModuleCache>,
 }
 
 #[derive(Debug, Default)]
@@ -66,9 +73,13 @@ impl AssetsLoader {
 		self.indexes.push(url.into());
 	}
 
-	pub async fn load(&mut self, diagnostic_list: &mut DiagnosticList) -> Result<()> {
+	/// Load every registered asset index. Remote indexes are fetched and verified against the lockfile just like the runtime's modules.
+	pub async fn load(&mut self, diagnostic_list: &mut DiagnosticList, module_options: ModuleOptions<'_>) -> Result<()> {
+		let mut lockfile = Lockfile::load(module_options.lockfile_path, module_options.frozen).await?;
+		self.module_cache = Some(module_options.get_module_cache());
+
 		for index_url in &self.indexes {
-			let mut assets = match load_index(&index_url).await {
+			let mut assets = match load_index(&index_url, module_options, &mut lockfile).await {
 				Ok(assets) => assets,
 				Err(error) => {
 					println!("{}", error);
@@ -96,6 +107,8 @@ impl AssetsLoader {
 			}
 		}
 
+		lockfile.save().await?;
+
 		Ok(())
 	}
 
@@ -128,17 +141,48 @@ impl AssetsLoader {
 				}
 			}
 
-			let download_res = writer.download_file(&path, &asset.url).await;
+			let content = match self.read_asset(&asset.url).await {
+				Ok(content) => content,
+				Err(error) => {
+					diagnostic_list.add_error(error.context(format!("Failed to download {}", asset.url)));
+					continue;
+				}
+			};
+
+			let mut hasher = Sha256::new();
+			hasher.update(&content);
 
-			let downloaded_hash = match download_res {
-				Ok(hash) => hash,
+			if hasher.finalize().as_slice() != asset.sha256 {
+				diagnostic_list.add(
+					Diagnostic::start("After being download, the expected hash in the asset index does not match the actual hash of the file")
+						.shift()
+						.text(&asset.url)
+						.build(),
+				);
+				continue;
+			}
+
+			writer.write_file(&path, content).await?;
+		}
+
+		Ok(())
+	}
+
+	/// Write every asset to `pipeline` at a fingerprinted version of it's web path, verifying each against the hash in it's index
+	pub async fn write_fingerprinted(&self, pipeline: &mut AssetPipeline<'_>, diagnostic_list: &mut DiagnosticList) -> Result<()> {
+		for asset in &self.assets {
+			let content = match self.read_asset(&asset.url).await {
+				Ok(content) => content,
 				Err(error) => {
 					diagnostic_list.add_error(error.context(format!("Failed to download {}", asset.url)));
 					continue;
 				}
 			};
 
-			if downloaded_hash != asset.sha256 {
+			let mut hasher = Sha256::new();
+			hasher.update(&content);
+
+			if hasher.finalize().as_slice() != asset.sha256 {
 				diagnostic_list.add(
 					Diagnostic::start("After being download, the expected hash in the asset index does not match the actual hash of the file")
 						.shift()
@@ -147,11 +191,23 @@ impl AssetsLoader {
 				);
 				continue;
 			}
+
+			pipeline.write_fingerprinted(&asset.web_path, content).await?;
 		}
 
 		Ok(())
 	}
 
+	/// Read the content of an asset. Assets are read through the module cache, and are verified against the hashes in their index by the
+	/// caller, so an index that is in the lockfile locks it's assets as well.
+	async fn read_asset(&self, url: &Url) -> Result<Vec<u8>> {
+		let Some(module_cache) = &self.module_cache else {
+			bail!("assets can't be read before their indexes are loaded");
+		};
+
+		module_cache.read(url).await
+	}
+
 	pub async fn download(self, cache_writer: &Writer, diagnostic_list: &mut DiagnosticList) -> Result<AccessibleAssets> {
 		self.write(
 			cache_writer,
@@ -207,21 +263,11 @@ fn normalize_web_path(path: &str) -> String {
 	}
 }
 
-async fn load_index(url: &Url) -> Result<Vec<Asset>> {
-	let mut graph = InfoGraph::load(url).await?;
-	let module = graph.modules.drain(..).nth(0).ok_or(anyhow!(
-		"Expected there to be a single module (probably caused by a regression in `deno fmt`) when getting the graph for asset index"
-	))?;
-
-	if let Some(error) = module.error {
-		return Err(anyhow!(error));
-	}
+async fn load_index(url: &Url, options: ModuleOptions<'_>, lockfile: &mut Lockfile) -> Result<Vec<Asset>> {
+	let json = options.get_module_cache().read(url).await?;
+	lockfile.verify(url, &json)?;
 
-	let local = module
-		.local
-		.ok_or_else(|| anyhow!("Expected a local file because there was no error. This is probably caused by a regression in `deno info`"))?;
-	let json = read_to_string(&local).await.with_context(|| format!("failed to read file at {local:?}"))?;
-	let value = from_str::<Value>(&json).context("Index is not valid json")?;
+	let value = from_slice::<Value>(&json).context("Index is not valid json")?;
 	let mut value_array = match value {
 		Value::Array(inner) => inner,
 		_ => bail!("Asset index should be a json file containing an array"),
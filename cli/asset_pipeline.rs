@@ -0,0 +1,124 @@
+use anyhow::{Context, Result};
+use brotli::CompressorWriter;
+use flate2::{write::GzEncoder, Compression};
+use serde_json::{from_slice, to_string, to_string_pretty};
+use sha2::{Digest, Sha256};
+use std::{
+	collections::{BTreeMap, HashSet},
+	io::{ErrorKind, Write},
+	path::Path,
+};
+use tokio::fs::read;
+
+use crate::writer::Writer;
+
+/// Where the manifest of fingerprinted paths is written, relative to the output directory
+pub const MANIFEST_PATH: &str = "asset-manifest.json";
+
+/// The number of hex characters of a file's hash that are put in it's fingerprinted path
+const FINGERPRINT_LENGTH: usize = 16;
+
+/// Writes the files of a production build. Files are fingerprinted, meaning that the hash of their content is put in their path, so that
+/// they can be cached forever, and each file gets precompressed `.gz` and `.br` siblings for servers to send to clients that accept them.
+///
+/// The manifest maps each original web path to it's fingerprinted path.
+pub struct AssetPipeline<'a> {
+	writer: &'a Writer,
+	manifest: BTreeMap<String, String>,
+}
+
+impl AssetPipeline<'_> {
+	pub fn new(writer: &Writer) -> AssetPipeline<'_> {
+		AssetPipeline {
+			writer,
+			manifest: BTreeMap::new(),
+		}
+	}
+
+	/// Write `content` to a fingerprinted version of `web_path`, returning the fingerprinted path
+	pub async fn write_fingerprinted(&mut self, web_path: &str, content: impl AsRef<[u8]>) -> Result<String> {
+		let fingerprinted_path = fingerprint_path(web_path, content.as_ref());
+
+		self.write(&fingerprinted_path, content).await?;
+		self.manifest.insert(web_path.to_string(), fingerprinted_path.clone());
+
+		Ok(fingerprinted_path)
+	}
+
+	/// Write `content` to `web_path` without fingerprinting it. This is for files that must be found at a known path, such as `index.html`.
+	pub async fn write(&self, web_path: &str, content: impl AsRef<[u8]>) -> Result<()> {
+		let content = content.as_ref();
+		let path = web_path.trim_start_matches('/');
+
+		self.writer.write_file(path, content).await?;
+
+		// already compressed formats, like images, usually don't get any smaller, in which case there is no point to a sibling
+		let gzipped = gzip(content).with_context(|| format!("failed to gzip {web_path}"))?;
+		if gzipped.len() < content.len() {
+			self.writer.write_file(format!("{path}.gz"), gzipped).await?;
+		}
+
+		let brotli = brotli(content).with_context(|| format!("failed to compress {web_path} with brotli"))?;
+		if brotli.len() < content.len() {
+			self.writer.write_file(format!("{path}.br"), brotli).await?;
+		}
+
+		Ok(())
+	}
+
+	/// Write a fingerprinted copy of the manifest for clients to look up fingerprinted paths with, returning it's path. Because it is
+	/// fingerprinted, it can be cached forever, unlike the page that references it. Files that are written after this won't be in it.
+	pub async fn write_client_manifest(&mut self) -> Result<String> {
+		let content = to_string(&self.manifest)?;
+
+		self.write_fingerprinted(&format!("/{MANIFEST_PATH}"), content).await
+	}
+
+	/// Write the manifest
+	pub async fn finish(self) -> Result<()> {
+		self.writer.write_file(MANIFEST_PATH, format!("{}\n", to_string_pretty(&self.manifest)?)).await
+	}
+}
+
+/// Get the fingerprinted paths in the manifest of the production build in `directory`. If there is no manifest, the build wasn't a production
+/// build, so nothing is fingerprinted.
+pub async fn load_fingerprinted_paths(directory: &Path) -> Result<HashSet<String>> {
+	let path = directory.join(MANIFEST_PATH);
+
+	let manifest = match read(&path).await {
+		Ok(bytes) => from_slice::<BTreeMap<String, String>>(&bytes).with_context(|| format!("failed to parse the asset manifest at {path:?}"))?,
+		Err(error) if error.kind() == ErrorKind::NotFound => return Ok(HashSet::new()),
+		Err(error) => return Err(error).with_context(|| format!("failed to read the asset manifest at {path:?}")),
+	};
+
+	Ok(manifest.into_values().collect())
+}
+
+/// Put the hash of `content` in `web_path`, before the extension. For example, `/icons/add.svg` becomes `/icons/add.0123456789abcdef.svg`.
+fn fingerprint_path(web_path: &str, content: &[u8]) -> String {
+	let mut hasher = Sha256::new();
+	hasher.update(content);
+	let hash = hex::encode(hasher.finalize());
+	let hash = &hash[..FINGERPRINT_LENGTH];
+
+	let (directory, name) = web_path.rsplit_once('/').unwrap_or(("", web_path));
+
+	match name.rsplit_once('.') {
+		Some((stem, extension)) if !stem.is_empty() => format!("{directory}/{stem}.{hash}.{extension}"),
+		_ => format!("{directory}/{name}.{hash}"),
+	}
+}
+
+fn gzip(content: &[u8]) -> Result<Vec<u8>> {
+	let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+	encoder.write_all(content)?;
+
+	Ok(encoder.finish()?)
+}
+
+fn brotli(content: &[u8]) -> Result<Vec<u8>> {
+	let mut writer = CompressorWriter::new(Vec::new(), 4096, 11, 22);
+	writer.write_all(content)?;
+
+	Ok(writer.into_inner())
+}
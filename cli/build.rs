@@ -1,71 +1,97 @@
-use anyhow::{bail, Context, Result};
+use anyhow::{Context, Result};
 use deno_graph::source::MemoryLoader;
-use log::{error, info};
+use log::info;
+use std::path::PathBuf;
 use url::Url;
 
 use crate::{
 	asset_loader::AssetsLoader,
-	bundle::{BundleParams, Bundler},
+	bundle::{BundleOutput, BundleParams, Bundler, SourceMapKind},
 	collect::Collection,
-	diagnostic::DiagnosticList,
+	diagnostic::{Diagnostic, DiagnosticList, MessageFormat},
 	engine::Engine,
 	inspect::Inspector,
-	module_loader::load_modules,
+	module_loader::{load_modules, ModuleOptions},
 };
 
 #[derive(Debug, Clone, Copy)]
 pub struct BuildOptions<'a> {
 	pub runtime: &'a Url,
-	pub bundler: &'a Url,
 	pub engine_url: &'a Url,
 	pub engine: Engine,
+	pub message_format: MessageFormat,
+	pub module_options: ModuleOptions<'a>,
+	pub source_map: SourceMapKind,
 }
 
 pub struct Build {
 	pub client_bundle: String,
+	/// Only set if the source map is external
+	pub client_source_map: Option<String>,
 	pub bindings: String,
 	pub assets_loader: AssetsLoader,
+	/// The paths of the runtime's modules that are local files, which are the ones that can change during development
+	pub local_modules: Vec<PathBuf>,
 }
 
-pub async fn build(diagnostic_list: &mut DiagnosticList, options: BuildOptions<'_>) -> Result<Build> {
+/// A runtime that has been loaded, mounted, and validated, but not yet bundled.
+pub struct Checked {
+	pub bundler: Bundler,
+	pub collection: Collection,
+	pub local_modules: Vec<PathBuf>,
+}
+
+/// Load, mount, and validate the runtime, reporting any problems to `diagnostic_list`. This is everything that `build` does before bundling.
+pub async fn check(diagnostic_list: &mut DiagnosticList, runtime: &Url, engine: Engine, module_options: ModuleOptions<'_>) -> Result<Checked> {
 	let mut memory_loader = MemoryLoader::default();
 	let mut bundler = Bundler::default();
 	let mut collection = Collection::default();
 
-	load_modules(options.runtime, &mut memory_loader, &mut bundler).await?;
+	let local_modules = load_modules(runtime, module_options, &mut memory_loader, &mut bundler, diagnostic_list).await?;
 	info!("Loaded runtime");
 
-	collection.collect(&options.runtime, &memory_loader).await?;
+	collection.collect(runtime, &memory_loader).await?;
 	collection.check_components();
 
-	let errors = collection.get_errors();
-	let error_count = errors.len();
-
-	for error in errors {
-		error!("{:?}", error);
+	for warning in collection.take_warnings() {
+		diagnostic_list.add_warning(warning);
 	}
 
-	if error_count > 0 {
-		bail!(
-			"could not mount runtime due to {} previous error{}",
-			error_count,
-			if error_count == 1 { "" } else { "s" }
-		);
+	for error in collection.get_errors() {
+		diagnostic_list.add(Diagnostic::from_error(error));
 	}
 
+	diagnostic_list.flush("mount runtime")?;
 	info!("Mounted runtime");
 
-	let inspector = Inspector::new(&collection);
+	let inspector = Inspector::new(&collection, engine);
 	inspector.inspect(diagnostic_list);
 
 	diagnostic_list.flush("validate runtime")?;
 	info!("Validated runtime");
 
-	let client_bundle = bundler
+	Ok(Checked {
+		bundler,
+		collection,
+		local_modules,
+	})
+}
+
+pub async fn build(diagnostic_list: &mut DiagnosticList, options: BuildOptions<'_>) -> Result<Build> {
+	let Checked {
+		bundler,
+		collection,
+		local_modules,
+	} = check(diagnostic_list, options.runtime, options.engine, options.module_options).await?;
+
+	let BundleOutput {
+		code: client_bundle,
+		source_map: client_source_map,
+	} = bundler
 		.bundle(BundleParams {
-			bundler_url: options.bundler,
 			runtime_url: options.runtime,
 			collection: &collection,
+			source_map: options.source_map,
 		})
 		.await?;
 	info!("Bundled runtime");
@@ -73,13 +99,18 @@ pub async fn build(diagnostic_list: &mut DiagnosticList, options: BuildOptions<'
 	let bindings = options.engine.get_bindings(&collection)?;
 
 	let mut assets_loader = collection.finish();
-	assets_loader.load(diagnostic_list).await.context("Failed to load assets")?;
+	assets_loader
+		.load(diagnostic_list, options.module_options)
+		.await
+		.context("Failed to load assets")?;
 	diagnostic_list.flush("load assets")?;
 	info!("Loaded assets");
 
 	Ok(Build {
 		client_bundle,
+		client_source_map,
 		bindings,
 		assets_loader,
+		local_modules,
 	})
 }
@@ -1,27 +1,69 @@
 use aho_corasick::AhoCorasick;
-use anyhow::{bail, Result};
-use serde::Serialize;
-use serde_json::to_string;
-use std::{collections::HashMap, env, fmt::Write, path::PathBuf, process::Stdio};
-use tokio::{io::AsyncWriteExt, process::Command};
+use anyhow::{anyhow, Context, Error, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use clap::ValueEnum;
+use deno_ast::{
+	get_syntax,
+	swc::{
+		ast::{KeyValueProp, Module},
+		bundler::{Bundler as SwcBundler, Config, Hook, Load, ModuleData, ModuleRecord, ModuleType, Resolve},
+		codegen::{text_writer::JsWriter, Config as CodegenConfig, Emitter},
+		common::{comments::SingleThreadedComments, sync::Lrc, FileName, FilePathMapping, Globals, SourceMap as SwcSourceMap, Span, GLOBALS},
+		loader::resolve::Resolution,
+		parser::{lexer::Lexer, Parser, StringInput},
+	},
+	MediaType, ES_VERSION,
+};
+use sourcemap::{SourceMap, SourceMapBuilder};
+use std::{
+	collections::HashMap,
+	fmt::Write,
+	fs::{read, read_to_string},
+	path::PathBuf,
+};
+use tokio::task::spawn_blocking;
 use url::Url;
 
 use crate::collect::Collection;
 
 const RUNTIME_ENTRY: &str = include_str!("runtime_entry.js");
 
-#[derive(Debug, Serialize, Default)]
+/// The specifier of the generated entry module, which imports the runtime
+const ENTRY_SPECIFIER: &str = "entry://default";
+
+#[derive(Debug, Default)]
 struct BundleManifest {
 	resolutions: HashMap<Url, HashMap<String, Url>>,
 	source_files: HashMap<Url, PathBuf>,
+	source_maps: HashMap<Url, PathBuf>,
+}
+
+/// Where the source map of a bundle is put
+#[derive(Debug, ValueEnum, Clone, Copy, Default, PartialEq)]
+pub enum SourceMapKind {
+	/// Appended to the bundle as a data url
+	#[default]
+	Inline,
+	/// Returned alongside the bundle, to be written to it's own file
+	External,
+	/// Not generated
+	None,
 }
 
 pub struct BundleParams<'a> {
-	pub bundler_url: &'a Url,
 	pub runtime_url: &'a Url,
 	pub collection: &'a Collection,
+	pub source_map: SourceMapKind,
 }
 
+pub struct BundleOutput {
+	pub code: String,
+	/// Only set if the source map kind was `External`. The code doesn't reference it, because where it will be written isn't known yet, so
+	/// a `sourceMappingURL` comment should be appended once it is.
+	pub source_map: Option<String>,
+}
+
+/// Links the runtime's modules into a single script. Each module must be registered, along with it's dependencies, before bundling.
 #[derive(Debug, Default)]
 pub struct Bundler {
 	manifest: BundleManifest,
@@ -38,11 +80,18 @@ impl Bundler {
 		}
 	}
 
+	/// Register the javascript for `source`. Typescript and jsx must already be transpiled.
 	pub fn register_source_file(&mut self, source: impl Into<Url>, file: impl Into<PathBuf>) {
 		self.manifest.source_files.insert(source.into(), file.into());
 	}
 
-	pub async fn bundle(self, params: BundleParams<'_>) -> Result<String> {
+	/// Register a source map for the javascript of `source`, if it was transpiled, so that the bundle's source map points to the original
+	/// source rather than the transpiled javascript.
+	pub fn register_source_map(&mut self, source: impl Into<Url>, file: impl Into<PathBuf>) {
+		self.manifest.source_maps.insert(source.into(), file.into());
+	}
+
+	pub async fn bundle(self, params: BundleParams<'_>) -> Result<BundleOutput> {
 		let imports = {
 			let mut js = String::new();
 
@@ -61,49 +110,216 @@ impl Bundler {
 			let mut js = String::new();
 
 			for (name, info) in params.collection.get_component_info() {
-				write!(
+				writeln!(
 					js,
-					"\tif (component.type === '{}') return {{ func: {}, params: component.def }}\n",
+					"\tif (component.type === '{}') return {{ func: {}, params: component.def }}",
 					name, &info.render_name
 				)?;
 			}
 
-			write!(js, "\tthrow new Error('Unknown component type: ' + component.type)\n")?;
+			writeln!(js, "\tthrow new Error('Unknown component type: ' + component.type)")?;
 
 			js
 		};
 
 		let entry = AhoCorasick::new(&["\"IMPORTS\"", "\"COMPONENT_CASES\""])?.replace_all(RUNTIME_ENTRY, &[imports, component_cases]);
 
-		self.run_bundle_command(params.bundler_url, entry).await
+		let source_map = params.source_map;
+
+		// linking reads every module from disk and is cpu heavy, so it would otherwise hold up the runtime, including the dev server
+		spawn_blocking(move || self.link(entry, source_map))
+			.await
+			.context("failed to wait for the bundle to be linked")?
 	}
 
-	async fn run_bundle_command(self, bundler_url: &Url, entry_code: impl Into<String>) -> Result<String> {
-		let mut command = Command::new("deno");
+	/// Link the entry module and everything that it imports into a single module, and print it
+	fn link(self, entry_code: String, source_map: SourceMapKind) -> Result<BundleOutput> {
+		let globals = Globals::new();
 
-		command
-			.arg("run")
-			.arg("--allow-read")
-			.arg(bundler_url.as_str())
-			.stdin(Stdio::piped())
-			.stdout(Stdio::piped())
-			.stderr(Stdio::inherit())
-			.env_clear()
-			.env("PATH", env::var("PATH").unwrap());
+		GLOBALS.set(&globals, || {
+			let entry_url = Url::parse(ENTRY_SPECIFIER)?;
+			let source_map_builder = Lrc::new(SwcSourceMap::new(FilePathMapping::empty()));
+			let loader = ManifestLoader {
+				source_map: source_map_builder.clone(),
+				manifest: &self.manifest,
+				entry_code,
+			};
+			let resolver = ManifestResolver { manifest: &self.manifest };
+			let config = Config {
+				module: ModuleType::Es,
+				..Default::default()
+			};
 
-		let mut process = command.spawn()?;
-		let json = to_string(&(entry_code.into(), self.manifest))?;
+			let mut bundler = SwcBundler::new(&globals, source_map_builder.clone(), loader, resolver, config, Box::new(NoImportMetaHook));
+			let mut bundles = bundler
+				.bundle(HashMap::from([(String::from("bundle"), FileName::Url(entry_url))]))
+				.context("failed to link the runtime's modules")?;
 
-		let mut stdin = process.stdin.take().unwrap();
-		stdin.write_all(json.as_bytes()).await?;
-		drop(stdin);
+			let bundle = bundles.pop().ok_or(anyhow!("expected the bundler to output a bundle"))?;
+			let mut code = Vec::new();
+			let mut mappings = Vec::new();
+
+			Emitter {
+				cfg: CodegenConfig::default(),
+				cm: source_map_builder.clone(),
+				comments: None,
+				wr: JsWriter::new(source_map_builder.clone(), "\n", &mut code, Some(&mut mappings)),
+			}
+			.emit_module(&bundle.module)
+			.context("failed to print the bundle")?;
+
+			let mut code = String::from_utf8(code).context("the printed bundle was not valid utf8")?;
+
+			if source_map == SourceMapKind::None {
+				return Ok(BundleOutput { code, source_map: None });
+			}
 
-		let output = process.wait_with_output().await?;
+			let mut map = Vec::new();
+			source_map_builder
+				.build_source_map(&mappings)
+				.to_writer(&mut map)
+				.context("failed to write the bundle's source map")?;
+			let map = self.chain_source_maps(&map)?;
 
-		if !output.status.success() {
-			bail!("Failed to bundle runtime")
+			Ok(match source_map {
+				SourceMapKind::Inline => {
+					write!(code, "\n//# sourceMappingURL=data:application/json;base64,{}", STANDARD.encode(&map))?;
+
+					BundleOutput { code, source_map: None }
+				}
+				_ => BundleOutput {
+					code,
+					source_map: Some(String::from_utf8(map).context("the source map was not valid utf8")?),
+				},
+			})
+		})
+	}
+
+	/// The bundle's source map points into the javascript that was linked, which for transpiled modules is not the original source. This
+	/// maps each of those tokens through the module's own source map, so that the result points into the original source.
+	fn chain_source_maps(&self, bundle_map: &[u8]) -> Result<Vec<u8>> {
+		let bundle_map = SourceMap::from_slice(bundle_map).context("failed to parse the bundle's source map")?;
+		let mut module_maps = HashMap::new();
+
+		for (specifier, path) in &self.manifest.source_maps {
+			let content = read(path).with_context(|| format!("failed to read {path:?}, the source map for {specifier}"))?;
+			let map = SourceMap::from_slice(&content).with_context(|| format!("failed to parse {path:?}, the source map for {specifier}"))?;
+
+			module_maps.insert(specifier.as_str(), map);
 		}
 
-		Ok(String::from_utf8(output.stdout)?)
+		let mut builder = SourceMapBuilder::new(None);
+
+		for token in bundle_map.tokens() {
+			let (original, original_map) = match token.get_source().and_then(|source| module_maps.get(source)) {
+				Some(module_map) => match module_map.lookup_token(token.get_src_line(), token.get_src_col()) {
+					Some(original) => (original, module_map),
+					None => continue,
+				},
+				None => (token, &bundle_map),
+			};
+
+			let raw = builder.add(
+				token.get_dst_line(),
+				token.get_dst_col(),
+				original.get_src_line(),
+				original.get_src_col(),
+				original.get_source(),
+				original.get_name().or(token.get_name()),
+			);
+
+			if original.has_source() && !builder.has_source_contents(raw.src_id) {
+				builder.set_source_contents(raw.src_id, original_map.get_source_contents(original.get_src_id()));
+			}
+		}
+
+		let mut map = Vec::new();
+		builder
+			.into_sourcemap()
+			.to_writer(&mut map)
+			.context("failed to write the bundle's chained source map")?;
+
+		Ok(map)
+	}
+}
+
+/// Loads modules from the source files in the manifest
+struct ManifestLoader<'a> {
+	source_map: Lrc<SwcSourceMap>,
+	manifest: &'a BundleManifest,
+	entry_code: String,
+}
+
+impl Load for ManifestLoader<'_> {
+	fn load(&self, file_name: &FileName) -> Result<ModuleData, Error> {
+		let FileName::Url(specifier) = file_name else {
+			return Err(anyhow!("expected every module to be identified by a url, but found {file_name}"));
+		};
+
+		let code = match specifier.as_str() {
+			ENTRY_SPECIFIER => self.entry_code.clone(),
+			_ => {
+				let path = self
+					.manifest
+					.source_files
+					.get(specifier)
+					.ok_or(anyhow!("Encountered {specifier}, for which no source file was provided"))?;
+
+				read_to_string(path).with_context(|| format!("failed to read {path:?}, the source file for {specifier}"))?
+			}
+		};
+
+		let file = self.source_map.new_source_file(file_name.clone(), code);
+		let comments = SingleThreadedComments::default();
+		let lexer = Lexer::new(get_syntax(MediaType::JavaScript), ES_VERSION, StringInput::from(&*file), Some(&comments));
+
+		let module: Module = Parser::new_from(lexer)
+			.parse_module()
+			.map_err(|error| anyhow!("failed to parse {specifier}: {}", error.kind().msg()))?;
+
+		Ok(ModuleData {
+			fm: file,
+			module,
+			helpers: Default::default(),
+		})
+	}
+}
+
+/// Resolves imports with the resolutions in the manifest
+struct ManifestResolver<'a> {
+	manifest: &'a BundleManifest,
+}
+
+impl Resolve for ManifestResolver<'_> {
+	fn resolve(&self, referrer: &FileName, specifier: &str) -> Result<Resolution, Error> {
+		let FileName::Url(referrer) = referrer else {
+			return Err(anyhow!("expected every module to be identified by a url, but found {referrer}"));
+		};
+
+		// all imports in the entry module are pre-resolved
+		let resolved = match referrer.as_str() {
+			ENTRY_SPECIFIER => Url::parse(specifier)?,
+			_ => self
+				.manifest
+				.resolutions
+				.get(referrer)
+				.and_then(|resolutions| resolutions.get(specifier))
+				.ok_or(anyhow!("Encountered \"{specifier}\" from {referrer}, for which no resolution was provided"))?
+				.clone(),
+		};
+
+		Ok(Resolution {
+			filename: FileName::Url(resolved),
+			slug: None,
+		})
+	}
+}
+
+/// The runtime doesn't use `import.meta`, so no properties are provided for it
+struct NoImportMetaHook;
+
+impl Hook for NoImportMetaHook {
+	fn get_import_meta_props(&self, _span: Span, _module_record: &ModuleRecord) -> Result<Vec<KeyValueProp>, Error> {
+		Ok(Vec::new())
 	}
 }
@@ -1,5 +1,5 @@
 use anyhow::{anyhow, Context, Error, Result};
-use deno_doc::{js_doc::JsDocTag, DocNodeKind, DocParser, DocParserOptions, Location};
+use deno_doc::{js_doc::JsDocTag, DocDiagnosticKind, DocNodeKind, DocParser, DocParserOptions, Location};
 use deno_graph::{source::MemoryLoader, BuildOptions, CapturingModuleAnalyzer, GraphKind, ModuleGraph};
 use log::{debug, trace};
 use std::collections::{HashMap, HashSet};
@@ -17,6 +17,7 @@ pub struct KindDefinition<'a> {
 	pub name: &'a str,
 	pub comment: Option<&'a str>,
 	pub kind: &'a Kind,
+	pub location: &'a Location,
 }
 
 #[derive(Debug)]
@@ -24,6 +25,7 @@ struct InternalKindDefinition {
 	pub comment: Option<String>,
 	pub kind: Kind,
 	pub dependencies: Vec<String>,
+	pub location: Location,
 }
 
 #[derive(Debug)]
@@ -44,6 +46,8 @@ pub struct Collection {
 	functions: HashSet<String>,
 	erroring_functions: HashMap<String, Error>,
 	other_diagnostics: Vec<Error>,
+	warnings: Vec<Diagnostic>,
+	module_graph_error: Option<Error>,
 }
 
 impl Collection {
@@ -63,7 +67,27 @@ impl Collection {
 			.await;
 
 		for diagnostic in diagnostics {
-			println!("{}", diagnostic);
+			let builder = Diagnostic::start(&diagnostic);
+
+			self.warnings.push(match &diagnostic.maybe_range {
+				Some(range) => builder.shift().location(range).build(),
+				None => builder.build(),
+			});
+		}
+
+		// a missing or broken module means that there is nothing sensible to document, so stop here and let the error be reported
+		if let Err(error) = graph.valid() {
+			let builder = Diagnostic::start("Failed to load the runtime's module graph: ").text(&error);
+
+			self.module_graph_error = Some(
+				match error.maybe_range() {
+					Some(range) => builder.shift().location(range).build(),
+					None => builder.build(),
+				}
+				.error(),
+			);
+
+			return Ok(());
 		}
 
 		let parser = DocParser::new(
@@ -77,8 +101,21 @@ impl Collection {
 
 		let nodes = parser.parse_with_reexports(runtime_url)?;
 
-		// TODO print these as they outline missing dependencies and missing return types
-		let _diagnostics = parser.take_diagnostics();
+		for diagnostic in parser.take_diagnostics() {
+			let (message, hint) = match &diagnostic.kind {
+				// every undocumented export would be reported, which is just noise for a runtime
+				DocDiagnosticKind::MissingJsDoc => continue,
+				DocDiagnosticKind::MissingExplicitType => ("Missing explicit type", "add a type annotation so that the type does not have to be inferred"),
+				DocDiagnosticKind::MissingReturnType => ("Missing return type", "add a return type annotation so that it does not have to be inferred"),
+				DocDiagnosticKind::PrivateTypeRef { .. } => (
+					"An exported item references a type that is not exported",
+					"export the referenced type, otherwise it cannot be included in the engine bindings",
+				),
+			};
+
+			self.warnings
+				.push(Diagnostic::start(message).shift().location(&diagnostic.location).hint(hint).build());
+		}
 
 		for node in &nodes {
 			self.consider_js_doc_tags(&node.name, &node.js_doc.tags, &node.location).with_context(|| {
@@ -90,18 +127,16 @@ impl Collection {
 		}
 
 		if let None = &self.event_key_type_name {
-			Diagnostic::start("No type was found for noting event keys")
+			self.warnings.push(Diagnostic::start("No type was found for noting event keys")
 				.shift()
 				.text("Runtime events will not be recognized without a @feature_event_key js doc type tag to notate them. Additionally, this type must be exported from the runtime.")
-				.build()
-				.print_warn();
+				.build());
 		}
 
 		if let None = &self.action_key_type_name {
-			Diagnostic::start("No type was found for noting action keys")
+			self.warnings.push(Diagnostic::start("No type was found for noting action keys")
 				.shift()
-				.text("Runtime action types will not be recognized without a @feature_action_key js doc tag to notate them. Additionally, this type must be exported from the runtime.").build()
-				.print_warn();
+				.text("Runtime action types will not be recognized without a @feature_action_key js doc tag to notate them. Additionally, this type must be exported from the runtime.").build());
 		}
 
 		for node in nodes {
@@ -111,26 +146,27 @@ impl Collection {
 				DocNodeKind::Function => {
 					self.functions.insert(name);
 				}
-				DocNodeKind::Class => Diagnostic::start("Classes are not a supported type of export and will be ignored")
-					.shift()
-					.location(&node.location)
-					.build()
-					.print_warn(),
-				DocNodeKind::Enum => {
-					Diagnostic::start("Enums are not a supported type of export and will be ignored. Use a keyed or string literal union instead")
+				DocNodeKind::Class => self.warnings.push(
+					Diagnostic::start("Classes are not a supported type of export and will be ignored")
 						.shift()
 						.location(&node.location)
-						.build()
-						.print_warn()
-				}
+						.build(),
+				),
+				DocNodeKind::Enum => self.warnings.push(
+					Diagnostic::start("Enums are not a supported type of export and will be ignored")
+						.shift()
+						.location(&node.location)
+						.hint("use a keyed or string literal union instead")
+						.build(),
+				),
 				DocNodeKind::Import => (), // TODO we should figure out how to handle the "import item as anotherItem" cases
-				DocNodeKind::ModuleDoc => {
-					Diagnostic::start("Module docs are ignored. To document a specific component, place the doc comment on that component's interface")
+				DocNodeKind::ModuleDoc => self.warnings.push(
+					Diagnostic::start("Module docs are ignored")
 						.shift()
 						.location(&node.location)
-						.build()
-						.print_warn()
-				}
+						.hint("to document a specific component, place the doc comment on that component's interface")
+						.build(),
+				),
 				DocNodeKind::Interface => {
 					let conversion = convert_interface(ConvertInterfaceParams {
 						interface: node.interface_def.as_ref().ok_or(anyhow!("Bad deno_doc output: expected interface def."))?,
@@ -148,6 +184,7 @@ impl Collection {
 									comment: node.js_doc.doc.clone(),
 									kind,
 									dependencies,
+									location: node.location.clone(),
 								},
 							);
 						}
@@ -165,11 +202,12 @@ impl Collection {
 						}
 					};
 				}
-				DocNodeKind::Namespace => Diagnostic::start("Namespaces are not supported and will be ignored")
-					.shift()
-					.location(&node.location)
-					.build()
-					.print_warn(),
+				DocNodeKind::Namespace => self.warnings.push(
+					Diagnostic::start("Namespaces are not supported and will be ignored")
+						.shift()
+						.location(&node.location)
+						.build(),
+				),
 				DocNodeKind::TypeAlias => {
 					let type_alias = node
 						.type_alias_def
@@ -202,6 +240,7 @@ impl Collection {
 										comment: node.js_doc.doc.clone(),
 										kind,
 										dependencies,
+										location: node.location.clone(),
 									},
 								);
 							}
@@ -221,14 +260,13 @@ impl Collection {
 						};
 					}
 				}
-				DocNodeKind::Variable => Diagnostic::start(
-					"Exported variables are not supported and will be ignored. If you want to export a component render \
-					function, `export function` instead",
-				)
-				.shift()
-				.location(&node.location)
-				.build()
-				.print_error(),
+				DocNodeKind::Variable => self.warnings.push(
+					Diagnostic::start("Exported variables are not supported and will be ignored")
+						.shift()
+						.location(&node.location)
+						.hint("if you want to export a component render function, `export function` instead")
+						.build(),
+				),
 			}
 		}
 
@@ -242,6 +280,11 @@ impl Collection {
 	pub fn check_components(&mut self) {
 		// TODO all of this should be in `Inspect`
 
+		// nothing was collected, so every check would fail for reasons that are not the actual problem
+		if self.module_graph_error.is_some() {
+			return;
+		}
+
 		let components = self.get_component_info().iter().map(|(name, _)| *name).collect::<Vec<_>>();
 		let unreachable_names = self.get_unrelated_names(components).iter().map(|name| name.to_string()).collect::<Vec<_>>();
 
@@ -394,12 +437,18 @@ impl Collection {
 		}
 	}
 
+	/// Take the warnings that were encountered while collecting. These don't prevent the runtime from being mounted.
+	pub fn take_warnings(&mut self) -> Vec<Diagnostic> {
+		std::mem::take(&mut self.warnings)
+	}
+
 	pub fn get_errors(&self) -> Vec<&Error> {
 		let kind_errors = self
 			.erroring_kinds
 			.values()
 			.chain(self.erroring_functions.values())
 			.chain(self.other_diagnostics.iter())
+			.chain(self.module_graph_error.iter())
 			.collect::<Vec<_>>();
 
 		kind_errors
@@ -413,6 +462,7 @@ impl Collection {
 				name,
 				comment: def.comment.as_deref(),
 				kind: &def.kind,
+				location: &def.location,
 			})
 			.collect::<Vec<_>>();
 
@@ -26,6 +26,8 @@ pub struct EnumProperty {
 	pub comment: Option<String>,
 	pub name: String,
 	pub kind: Kind,
+	/// The location of the variant's `type` field
+	pub location: Location,
 }
 
 #[derive(Debug)]
@@ -34,6 +36,7 @@ pub struct ObjectProperty {
 	pub name: String,
 	pub kind: Kind,
 	pub is_optional: bool,
+	pub location: Location,
 }
 
 #[derive(Debug)]
@@ -63,22 +66,21 @@ pub fn convert_interface(params: ConvertInterfaceParams<'_>) -> Result<Conversio
 	let mut properties = Vec::new();
 
 	if !interface.extends.is_empty() {
-		return Diagnostic::start("Interface extensions are not supported, instead specify all properties in the interface body")
+		return Diagnostic::start("Interface extensions are not supported")
 			.shift()
 			.location(location)
+			.hint("specify all properties in the interface body instead")
 			.build()
 			.err();
 	}
 
 	if !interface.methods.is_empty() {
-		return Diagnostic::start(
-			"Methods are not supported in exported interfaces. If this is releated to private client-only functionality, consider \
-			inlining the methods in the render function",
-		)
-		.shift()
-		.location(location)
-		.build()
-		.err();
+		return Diagnostic::start("Methods are not supported in exported interfaces")
+			.shift()
+			.location(location)
+			.hint("if this is related to private client-only functionality, consider inlining the methods in the render function")
+			.build()
+			.err();
 	}
 
 	for property_def in &interface.properties {
@@ -115,6 +117,7 @@ pub fn convert_interface(params: ConvertInterfaceParams<'_>) -> Result<Conversio
 			name: property_def.name.to_string(),
 			kind: conversion.kind,
 			is_optional: property_def.optional,
+			location: property_def.location.clone(),
 		})
 	}
 
@@ -178,10 +181,22 @@ pub fn convert_ts_type(params: ConvertTsTypeParams<'_>) -> Result<Conversion> {
 		}
 
 		if keyword == "any" {
-			return Diagnostic::start("Use 'unknown' instead of 'any'").shift().location(location).build().err();
+			return Diagnostic::start("The ")
+				.inline_code("any")
+				.text(" type is not supported")
+				.shift()
+				.location(location)
+				.hint("use `unknown` instead")
+				.build()
+				.err();
 		}
 
-		return Diagnostic::start("Unknown keyword '{keyword}'").shift().location(location).build().err();
+		return Diagnostic::start("Unknown keyword ")
+			.inline_code(keyword)
+			.shift()
+			.location(location)
+			.build()
+			.err();
 	}
 
 	if let Some(type_ref) = &params.ts_type.type_ref {
@@ -328,6 +343,7 @@ pub fn convert_ts_type(params: ConvertTsTypeParams<'_>) -> Result<Conversion> {
 			} else if let Some(type_literal) = &ts_type.type_literal {
 				let mut comment = None;
 				let mut name = None;
+				let mut name_location = None;
 				let mut definition_kind = None;
 
 				for property in &type_literal.properties {
@@ -346,7 +362,8 @@ pub fn convert_ts_type(params: ConvertTsTypeParams<'_>) -> Result<Conversion> {
 
 						if let Some(literal) = &type_type.literal {
 							if let Some(string) = &literal.string {
-								name = Some(string.clone())
+								name = Some(string.clone());
+								name_location = Some(property.location.clone());
 							} else {
 								return Diagnostic::start("The type of the ")
 									.inline_code("type")
@@ -418,7 +435,12 @@ pub fn convert_ts_type(params: ConvertTsTypeParams<'_>) -> Result<Conversion> {
 						.error(),
 				)?;
 
-				keyed_variants.push(EnumProperty { comment, name, kind });
+				keyed_variants.push(EnumProperty {
+					comment,
+					name,
+					kind,
+					location: name_location.unwrap_or_else(|| location.clone()),
+				});
 			} else {
 				return Diagnostic::start("Unsupported enum type in variant ")
 					.text(variant_number)
@@ -433,14 +455,12 @@ pub fn convert_ts_type(params: ConvertTsTypeParams<'_>) -> Result<Conversion> {
 		}
 
 		if !string_variants.is_empty() && !keyed_variants.is_empty() {
-			return Diagnostic::start(
-				"Found a union with both string and keyed object variants. This is not allowed.\
-				The entire union must be made up of either string literals or keyed objects",
-			)
-			.shift()
-			.location(location)
-			.build()
-			.err();
+			return Diagnostic::start("Found a union with both string and keyed object variants. This is not allowed.")
+				.shift()
+				.location(location)
+				.hint("the entire union must be made up of either string literals or keyed objects")
+				.build()
+				.err();
 		}
 
 		return Ok(if !string_variants.is_empty() {
@@ -457,9 +477,10 @@ pub fn convert_ts_type(params: ConvertTsTypeParams<'_>) -> Result<Conversion> {
 	}
 
 	if let Some(_) = &ts_type.type_literal {
-		return Diagnostic::start("Object literals are not supported for types. Use an interface instead.")
+		return Diagnostic::start("Object literals are not supported for types")
 			.shift()
 			.location(location)
+			.hint("use an interface instead")
 			.build()
 			.err();
 	}
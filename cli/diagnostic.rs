@@ -1,33 +1,122 @@
 use anstyle::Style;
 use anyhow::{Error, Result};
+use clap::ValueEnum;
 use colored::Colorize;
 use deno_doc::Location;
+use deno_graph::Range;
 use log::{error, warn};
-use std::fmt::{Display, Write};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::{
+	collections::HashMap,
+	fmt::{Debug, Display, Write},
+	sync::Arc,
+};
+use url::Url;
+
+#[derive(Debug, ValueEnum, Clone, Copy, Default)]
+pub enum MessageFormat {
+	/// Colored, human readable diagnostics, written to stderr
+	#[default]
+	Human,
+	/// One JSON object per diagnostic, including the error that stopped the command if there was one, written to stdout. Each object has a `severity`, `message`, `file`, `line`, `column`, and `hints`.
+	/// `line` and `column` are both 1-based. The location fields are null if the diagnostic does not point to a particular place, and are
+	/// not repeated in the `message`.
+	Json,
+}
+
+impl Display for MessageFormat {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{}", self.to_possible_value().unwrap().get_name())
+	}
+}
+
+impl MessageFormat {
+	/// Report an error that stopped the command before it could be added to a `DiagnosticList`, so that it is still reported in this format.
+	pub fn print_error(self, error: &Error) {
+		match self {
+			MessageFormat::Human => error!("{:?}", error),
+			MessageFormat::Json => println!("{}", Diagnostic::from_error(error).to_json(Severity::Error)),
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+	Error,
+	Warning,
+}
 
 pub struct DiagnosticList {
-	diagnostics: Vec<Diagnostic>,
+	format: MessageFormat,
+	diagnostics: Vec<(Severity, Diagnostic)>,
+	/// The source code of every loaded module, keyed by specifier, so that diagnostics can show the code that they point to
+	sources: HashMap<String, Arc<str>>,
 }
 
 impl DiagnosticList {
-	pub fn new() -> DiagnosticList {
-		DiagnosticList { diagnostics: Vec::new() }
+	pub fn new(format: MessageFormat) -> DiagnosticList {
+		DiagnosticList {
+			format,
+			diagnostics: Vec::new(),
+			sources: HashMap::new(),
+		}
+	}
+
+	/// Make the source of a module available to the code frames of this list's diagnostics. Should be called with the same source that is
+	/// given to the `MemoryLoader`.
+	pub fn register_source(&mut self, specifier: &Url, source: Arc<str>) {
+		self.sources.insert(specifier.to_string(), source);
 	}
 
 	pub fn add(&mut self, diagnostic: Diagnostic) {
-		self.diagnostics.push(diagnostic)
+		self.push(Severity::Error, diagnostic)
+	}
+
+	pub fn add_warning(&mut self, diagnostic: Diagnostic) {
+		self.push(Severity::Warning, diagnostic)
+	}
+
+	/// Add a diagnostic, unless an identical one has already been added. The same problem is often reported more than once, such as when a
+	/// module is re-exported.
+	fn push(&mut self, severity: Severity, diagnostic: Diagnostic) {
+		let rendered = diagnostic.to_string();
+
+		if !self
+			.diagnostics
+			.iter()
+			.any(|(other_severity, other)| *other_severity == severity && other.to_string() == rendered)
+		{
+			self.diagnostics.push((severity, diagnostic))
+		}
 	}
 
 	pub fn add_error(&mut self, error: Error) {
-		self.diagnostics.push(Diagnostic::from_error(error))
+		self.add(Diagnostic::from_error(&error))
 	}
 
+	/// Report all diagnostics, failing if any of them were errors.
 	pub fn flush(&mut self, operation: impl Display) -> Result<()> {
-		let error_count = self.diagnostics.len();
+		let mut error_count = 0;
+
+		for (severity, mut diagnostic) in self.diagnostics.drain(..) {
+			if severity == Severity::Error {
+				error_count += 1;
+			}
 
-		for mut diagnostic in self.diagnostics.drain(..) {
-			diagnostic.0.push('\n');
-			diagnostic.print_error();
+			match self.format {
+				MessageFormat::Human => {
+					diagnostic.frame = diagnostic.location.as_ref().and_then(|location| render_frame(location, &self.sources));
+					diagnostic.text.push('\n');
+
+					match severity {
+						Severity::Error => diagnostic.print_error(),
+						Severity::Warning => diagnostic.print_warn(),
+					}
+				}
+				MessageFormat::Json => println!("{}", diagnostic.to_json(severity)),
+			}
 		}
 
 		if error_count > 0 {
@@ -45,10 +134,58 @@ impl DiagnosticList {
 	}
 }
 
-pub struct Diagnostic(String);
+/// A place in a source file. Both `line` and `column` are 1-based, as editors expect.
+#[derive(Debug, Clone)]
+pub struct DiagnosticLocation {
+	file: String,
+	line: usize,
+	column: usize,
+}
+
+impl From<&Location> for DiagnosticLocation {
+	fn from(location: &Location) -> Self {
+		// deno_doc locations have one-based lines, but zero-based columns
+		DiagnosticLocation {
+			file: location.filename.to_string(),
+			line: location.line,
+			column: location.col + 1,
+		}
+	}
+}
+
+impl From<&Range> for DiagnosticLocation {
+	fn from(range: &Range) -> Self {
+		// deno_graph positions are entirely zero-based
+		DiagnosticLocation {
+			file: range.specifier.to_string(),
+			line: range.start.line + 1,
+			column: range.start.character + 1,
+		}
+	}
+}
+
+#[derive(Clone)]
+pub struct Diagnostic {
+	text: String,
+	location: Option<DiagnosticLocation>,
+	/// The part of `text` that points to `location`, which is left out of the json format's message, because it has fields for the location
+	location_text: Option<std::ops::Range<usize>>,
+	frame: Option<String>,
+	hints: Vec<String>,
+}
 
 impl Diagnostic {
-	pub fn from_error(error: Error) -> Diagnostic {
+	/// Create a diagnostic from an error. If the error was built from a diagnostic, that diagnostic's location is kept.
+	pub fn from_error(error: &Error) -> Diagnostic {
+		let diagnostic = error.downcast_ref::<Diagnostic>();
+
+		// the error is nothing more than the diagnostic, so it can be used as is
+		if let (Some(diagnostic), 1) = (diagnostic, error.chain().count()) {
+			return diagnostic.clone();
+		}
+
+		let location = diagnostic.and_then(|diagnostic| diagnostic.location.clone());
+
 		let mut string = String::new();
 		let _ = write!(&mut string, "{:?}", error);
 
@@ -60,15 +197,29 @@ impl Diagnostic {
 			string = new_string;
 		}
 
-		Diagnostic(string)
+		Diagnostic {
+			text: string,
+			location,
+			location_text: None,
+			frame: None,
+			hints: Vec::new(),
+		}
 	}
 
 	pub fn start(initial_message: impl Display) -> DiagnosticBuilder {
-		DiagnosticBuilder::new(Diagnostic(String::new())).text(initial_message)
+		DiagnosticBuilder::new(Diagnostic {
+			text: String::new(),
+			location: None,
+			location_text: None,
+			frame: None,
+			hints: Vec::new(),
+		})
+		.text(initial_message)
 	}
 
+	/// Convert into an error, which can later be turned back into a diagnostic via `Diagnostic::from_error` without losing it's location.
 	pub fn error(self) -> Error {
-		Error::msg(self.0)
+		Error::msg(self)
 	}
 
 	pub fn err<T>(self) -> Result<T> {
@@ -80,51 +231,168 @@ impl Diagnostic {
 	}
 
 	pub fn print_warn(self) {
-		warn!("{}", self.0)
+		warn!("{self}")
+	}
+
+	fn to_json(&self, severity: Severity) -> Value {
+		let message = match &self.location_text {
+			Some(range) => format!("{}{}", &self.text[..range.start], &self.text[range.end..]),
+			None => self.text.clone(),
+		};
+
+		json!({
+			"severity": severity,
+			"message": strip_styles(&message).trim(),
+			"file": self.location.as_ref().map(|location| &location.file),
+			"line": self.location.as_ref().map(|location| location.line),
+			"column": self.location.as_ref().map(|location| location.column),
+			"hints": self.hints.iter().map(|hint| strip_styles(hint)).collect::<Vec<_>>(),
+		})
+	}
+}
+
+impl Debug for Diagnostic {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{self}")
 	}
 }
 
 impl Display for Diagnostic {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-		write!(f, "{}", self.0)
+		write!(f, "{}", self.text)?;
+
+		if let Some(frame) = &self.frame {
+			write!(f, "\n{frame}")?;
+		}
+
+		for hint in &self.hints {
+			write!(f, "\n   {} {hint}", "= hint:".bold().blue())?;
+		}
+
+		Ok(())
+	}
+}
+
+/// Render the lines around `location`, with the code at it's column underlined. Returns `None` if the source is not in `sources`.
+fn render_frame(location: &DiagnosticLocation, sources: &HashMap<String, Arc<str>>) -> Option<String> {
+	let lines = sources.get(&location.file)?.lines().collect::<Vec<_>>();
+	let line_index = location.line.checked_sub(1).filter(|index| *index < lines.len())?;
+	let column_index = location.column.saturating_sub(1);
+
+	let first_index = line_index.saturating_sub(FRAME_CONTEXT_LINES);
+	let last_index = (line_index + FRAME_CONTEXT_LINES).min(lines.len() - 1);
+	let gutter_width = (last_index + 1).to_string().len();
+	let gutter = |number: &str| format!("{number:>gutter_width$} |").bold().blue().to_string();
+
+	let mut frame = gutter("");
+
+	for (index, line) in lines.iter().enumerate().take(last_index + 1).skip(first_index) {
+		write!(&mut frame, "\n{} {line}", gutter(&(index + 1).to_string())).unwrap();
+
+		if index == line_index {
+			// keep any tabs in the indentation so that the underline lines up with the code
+			let indent = line
+				.chars()
+				.take(column_index)
+				.map(|char| if char == '\t' { '\t' } else { ' ' })
+				.collect::<String>();
+			let length = line
+				.chars()
+				.skip(column_index)
+				.take_while(|char| char.is_alphanumeric() || *char == '_' || *char == '$')
+				.count()
+				.max(1);
+
+			write!(&mut frame, "\n{} {indent}{}", gutter(""), "^".repeat(length).bold().red()).unwrap();
+		}
 	}
+
+	Some(frame)
+}
+
+/// Remove the ansi escape sequences that are used to style diagnostics.
+fn strip_styles(text: &str) -> String {
+	let mut stripped = String::with_capacity(text.len());
+	let mut chars = text.chars();
+
+	while let Some(char) = chars.next() {
+		if char == '\x1b' {
+			// skip the control sequence, which is terminated by a character in the range `@` to `~`
+			if chars.next() == Some('[') {
+				for char in chars.by_ref() {
+					if ('@'..='~').contains(&char) {
+						break;
+					}
+				}
+			}
+		} else {
+			stripped.push(char);
+		}
+	}
+
+	stripped
 }
 
 const FORE_STYLE: Style = Style::new().bold();
 
+/// The number of lines above and below a diagnostic's line that are included in it's code frame
+const FRAME_CONTEXT_LINES: usize = 1;
+
 pub struct DiagnosticBuilder {
 	diagnostic: Diagnostic,
-	did_shift: bool,
+	/// Where the text that was written by the last call to `shift` starts and ends
+	shift_text: Option<std::ops::Range<usize>>,
 }
 
 impl DiagnosticBuilder {
 	pub fn new(mut diagnostic: Diagnostic) -> DiagnosticBuilder {
-		write!(&mut diagnostic.0, "{FORE_STYLE}").unwrap();
+		write!(&mut diagnostic.text, "{FORE_STYLE}").unwrap();
 
-		DiagnosticBuilder { diagnostic, did_shift: false }
+		DiagnosticBuilder { diagnostic, shift_text: None }
 	}
 
 	pub fn inline_code(mut self, code: impl Display) -> DiagnosticBuilder {
-		write!(&mut self.diagnostic.0, "`{code}`").unwrap();
+		write!(&mut self.diagnostic.text, "`{code}`").unwrap();
 
 		self
 	}
 
 	pub fn shift(mut self) -> DiagnosticBuilder {
-		write!(&mut self.diagnostic.0, "{FORE_STYLE:#}\n  {}", "--> ".bold().blue()).unwrap();
-		self.did_shift = true;
+		let start = self.diagnostic.text.len();
+		write!(&mut self.diagnostic.text, "{FORE_STYLE:#}\n  {}", "--> ".bold().blue()).unwrap();
+		self.shift_text = Some(start..self.diagnostic.text.len());
 
 		self
 	}
 
 	pub fn text(mut self, text: impl Display) -> DiagnosticBuilder {
-		write!(&mut self.diagnostic.0, "{text}").unwrap();
+		write!(&mut self.diagnostic.text, "{text}").unwrap();
+
+		self
+	}
+
+	pub fn location(mut self, location: impl Into<DiagnosticLocation>) -> DiagnosticBuilder {
+		let location = location.into();
+		let start = self.diagnostic.text.len();
+		write!(&mut self.diagnostic.text, "{}:{}:{}", &location.file, &location.line, &location.column).unwrap();
+
+		if self.diagnostic.location.is_none() {
+			self.diagnostic.location = Some(location);
+
+			// a location that directly follows a shift is only a pointer to the location, so it can be left out of messages that have their own
+			// fields for the location, along with the shift
+			self.diagnostic.location_text = match &self.shift_text {
+				Some(shift_text) if shift_text.end == start => Some(shift_text.start..self.diagnostic.text.len()),
+				_ => None,
+			};
+		}
 
 		self
 	}
 
-	pub fn location(mut self, location: &Location) -> DiagnosticBuilder {
-		write!(&mut self.diagnostic.0, "{}:{}:{}", &location.filename, &location.line, &location.col).unwrap();
+	/// Add a suggestion for how to fix the problem, which is shown below the code frame.
+	pub fn hint(mut self, hint: impl Display) -> DiagnosticBuilder {
+		self.diagnostic.hints.push(hint.to_string());
 
 		self
 	}
@@ -140,7 +408,7 @@ impl DiagnosticBuilder {
 			if first {
 				first = false;
 			} else {
-				write!(&mut self.diagnostic.0, ", ").unwrap();
+				write!(&mut self.diagnostic.text, ", ").unwrap();
 			}
 
 			self = func(self, item)
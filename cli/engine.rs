@@ -4,12 +4,17 @@ use anyhow::Result;
 use clap::ValueEnum;
 use log::info;
 
-use crate::{collect::Collection, gen_rust::RustGen};
+use crate::{collect::Collection, gen_json_schema::SchemaGen, gen_python::PyGen, gen_rust::RustGen, gen_typescript::TsGen};
 
 #[derive(Default, Debug, ValueEnum, Clone, Copy)]
 pub enum Engine {
 	#[default]
 	Rust,
+	#[value(name = "typescript")]
+	TypeScript,
+	Python,
+	/// Not an engine, but a JSON Schema document that describes the components, so that they can be used from any other language
+	JsonSchema,
 }
 
 impl Display for Engine {
@@ -31,6 +36,27 @@ impl Engine {
 
 				Ok(output)
 			}
+			Self::TypeScript => {
+				let mut gen = TsGen::new(collection)?;
+				gen.gen()?;
+				info!("Generated typescript engine bindings");
+
+				Ok(gen.get_output())
+			}
+			Self::Python => {
+				let mut gen = PyGen::new(collection)?;
+				gen.gen()?;
+				info!("Generated python engine bindings");
+
+				Ok(gen.get_output())
+			}
+			Self::JsonSchema => {
+				let mut gen = SchemaGen::new(collection)?;
+				gen.gen();
+				info!("Generated json schema");
+
+				Ok(gen.get_output())
+			}
 		}
 	}
 }
@@ -0,0 +1,200 @@
+use anyhow::{bail, Context, Result};
+use axum::{
+	body::Body,
+	extract::Request,
+	http::{header, HeaderMap, StatusCode, Uri},
+	response::{IntoResponse, Response},
+	routing::any,
+	Router,
+};
+use hyper::upgrade::OnUpgrade;
+use hyper_util::rt::TokioIo;
+use log::{debug, warn};
+use reqwest::{redirect::Policy, Client};
+use tokio::{io::copy_bidirectional, try_join};
+use url::Url;
+
+/// Headers that only apply to a single connection, and so are never forwarded. `connection` and `upgrade` are the exception for upgrade
+/// requests, because they are what asks the engine to upgrade.
+const HOP_BY_HOP_HEADERS: &[header::HeaderName] = &[
+	header::CONNECTION,
+	header::PROXY_AUTHENTICATE,
+	header::PROXY_AUTHORIZATION,
+	header::TE,
+	header::TRAILER,
+	header::TRANSFER_ENCODING,
+	header::UPGRADE,
+];
+
+/// Forwards every request under `path` to the engine, so that the client can reach the engine on the same origin that it was served from.
+/// This means that engines don't need to allow cross-origin requests, and that cookies set by the engine behave as they would in production.
+///
+/// Request and response bodies are streamed through, and upgrade requests (such as for websockets) are tunneled to the engine once both
+/// sides have upgraded.
+#[derive(Debug, Clone)]
+pub struct EngineProxy {
+	path: String,
+	engine_url: Url,
+	client: Client,
+}
+
+impl EngineProxy {
+	pub fn new(path: &str, engine_url: &Url) -> Result<EngineProxy> {
+		let path = path.trim_end_matches('/');
+
+		if path.is_empty() {
+			bail!("cannot proxy the engine at `/`, because that is where the client is served");
+		}
+
+		if !path.starts_with('/') {
+			bail!("expected the engine proxy path to start with a `/`, but found `{path}`");
+		}
+
+		if path.contains(['*', ':', '?', '#']) {
+			bail!("expected the engine proxy path to be a plain path, but found `{path}`");
+		}
+
+		// websockets start out as http requests, so they are proxied as such
+		let mut engine_url = engine_url.clone();
+		let scheme = match engine_url.scheme() {
+			"http" | "ws" => "http",
+			"https" | "wss" => "https",
+			scheme => bail!("cannot proxy to an engine url with a '{scheme}' scheme"),
+		};
+
+		if engine_url.set_scheme(scheme).is_err() {
+			bail!("failed to convert {engine_url} into an http url");
+		}
+
+		let client = Client::builder()
+			.redirect(Policy::none())
+			.build()
+			.context("failed to create the engine proxy client")?;
+
+		Ok(EngineProxy {
+			path: path.to_string(),
+			engine_url,
+			client,
+		})
+	}
+
+	/// The path that the engine is proxied at. Never has a trailing slash.
+	pub fn path(&self) -> &str {
+		&self.path
+	}
+
+	/// Add the proxy routes to `router`
+	pub fn route<S: Clone + Send + Sync + 'static>(&self, router: Router<S>) -> Router<S> {
+		let handler = {
+			let proxy = self.clone();
+
+			move |request: Request| {
+				let proxy = proxy.clone();
+
+				async move { proxy.forward(request).await }
+			}
+		};
+
+		router
+			.route(&self.path, any(handler.clone()))
+			.route(&format!("{}/*rest", self.path), any(handler))
+	}
+
+	async fn forward(&self, mut request: Request) -> Response {
+		let target_url = self.get_target_url(request.uri());
+		let client_upgrade = match request.headers().contains_key(header::UPGRADE) {
+			true => Some(hyper::upgrade::on(&mut request)),
+			false => None,
+		};
+
+		let (parts, body) = request.into_parts();
+		let mut headers = parts.headers;
+		headers.remove(header::HOST);
+		remove_hop_by_hop_headers(&mut headers, client_upgrade.is_some());
+
+		let mut engine_request = self.client.request(parts.method, target_url.clone()).headers(headers);
+
+		// the body of an upgrade request is whatever is sent after the upgrade, so it is tunneled instead
+		if client_upgrade.is_none() {
+			engine_request = engine_request.body(reqwest::Body::wrap_stream(body.into_data_stream()));
+		}
+
+		debug!("proxying {} to {target_url}", parts.uri);
+
+		let engine_response = match engine_request.send().await {
+			Ok(response) => response,
+			Err(error) => {
+				warn!("Failed to proxy a request to the engine at {target_url}: {error}");
+
+				return (StatusCode::BAD_GATEWAY, format!("failed to reach the engine at {}", self.engine_url)).into_response();
+			}
+		};
+
+		let status = engine_response.status();
+		let mut headers = engine_response.headers().clone();
+
+		let body = match (status, client_upgrade) {
+			(StatusCode::SWITCHING_PROTOCOLS, Some(client_upgrade)) => {
+				tokio::spawn(async move {
+					if let Err(error) = tunnel(client_upgrade, engine_response).await {
+						warn!("{error:?}");
+					}
+				});
+
+				Body::empty()
+			}
+			_ => {
+				remove_hop_by_hop_headers(&mut headers, false);
+
+				Body::from_stream(engine_response.bytes_stream())
+			}
+		};
+
+		let mut response = Response::new(body);
+		*response.status_mut() = status;
+		*response.headers_mut() = headers;
+
+		response
+	}
+
+	fn get_target_url(&self, uri: &Uri) -> Url {
+		let rest = uri.path().strip_prefix(&self.path).unwrap_or_default();
+		let mut url = self.engine_url.clone();
+
+		url.set_path(&format!("{}{rest}", self.engine_url.path().trim_end_matches('/')));
+		url.set_query(uri.query());
+
+		url
+	}
+}
+
+fn remove_hop_by_hop_headers(headers: &mut HeaderMap, is_upgrade: bool) {
+	for name in HOP_BY_HOP_HEADERS {
+		if is_upgrade && (name == header::CONNECTION || name == header::UPGRADE) {
+			continue;
+		}
+
+		headers.remove(name);
+	}
+}
+
+/// Wait for both the client and engine connections to be upgraded, and then copy between them until one closes.
+async fn tunnel(client_upgrade: OnUpgrade, engine_response: reqwest::Response) -> Result<()> {
+	let (client, mut engine) = try_join!(
+		async { client_upgrade.await.context("failed to upgrade the client connection to the engine proxy") },
+		async {
+			engine_response
+				.upgrade()
+				.await
+				.context("failed to upgrade the proxied connection to the engine")
+		},
+	)?;
+
+	let (sent, received) = copy_bidirectional(&mut TokioIo::new(client), &mut engine)
+		.await
+		.context("proxied connection to the engine failed")?;
+
+	debug!("closed proxied connection to the engine after sending {sent} bytes and receiving {received} bytes");
+
+	Ok(())
+}
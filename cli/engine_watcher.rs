@@ -0,0 +1,161 @@
+use log::debug;
+use reqwest::Client;
+use serde_json::{from_slice, json, Value};
+use std::time::Duration;
+use tokio::{net::TcpStream, sync::mpsc, time::sleep, time::timeout};
+use url::Url;
+
+/// How often the engine is checked
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How long a single check may take before the engine is considered to be offline
+const CHECK_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy)]
+pub enum EngineState {
+	Disconnected,
+	Reconnected,
+	Connected,
+}
+
+/// Watches an engine for restarts.
+///
+/// Engines are sent a heartbeat request (`{ "heartbeat": true }`), which engines built on the objection bindings answer with an id that is
+/// unique to their process, so a restart is noticed even if it is too quick to ever see the engine offline. If the engine doesn't answer
+/// with an id, such as when it is a websocket url, the watcher falls back to checking that something is accepting connections on the
+/// engine's port, which can only notice restarts that are slow enough to be seen offline.
+pub struct EngineWatcher(mpsc::Receiver<EngineState>);
+
+impl EngineWatcher {
+	pub fn new(engine_url: Url) -> EngineWatcher {
+		let (sender, receiver) = mpsc::channel(1);
+
+		tokio::spawn(drive_status_detection(sender, engine_url));
+
+		EngineWatcher(receiver)
+	}
+
+	pub async fn next_change(&mut self) -> Option<EngineState> {
+		self.0.recv().await
+	}
+}
+
+async fn drive_status_detection(sender: mpsc::Sender<EngineState>, engine_url: Url) {
+	let client = Client::new();
+	let mut maybe_old_status = None::<EngineStatus>;
+
+	loop {
+		let new_status = EngineStatus::get(&client, &engine_url).await.with_known_instance_id(maybe_old_status.as_ref());
+
+		let change = match (&maybe_old_status, &new_status) {
+			(None, EngineStatus::Offline) => None,
+			(None, EngineStatus::Online { .. }) => Some(EngineState::Connected),
+			(Some(EngineStatus::Online { .. }), EngineStatus::Offline) => Some(EngineState::Disconnected),
+			(Some(old_status), new_status) if new_status.did_restart(old_status) => {
+				debug!("engine appears to have restarted; last status: {old_status:?}; this status: {new_status:?}");
+
+				Some(EngineState::Reconnected)
+			}
+			_ => None,
+		};
+
+		// stay offline until the engine is first seen, so that coming online is reported as `Connected`
+		if maybe_old_status.is_some() || new_status.is_online() {
+			maybe_old_status = Some(new_status);
+		}
+
+		if let Some(change) = change {
+			if let Err(_) = sender.send(change).await {
+				break;
+			}
+		}
+
+		sleep(POLL_INTERVAL).await;
+	}
+}
+
+#[derive(Debug, PartialEq)]
+enum EngineStatus {
+	Offline,
+	/// `instance_id` is `None` if the engine didn't answer the heartbeat, but is accepting connections
+	Online {
+		instance_id: Option<String>,
+	},
+}
+
+impl EngineStatus {
+	async fn get(client: &Client, engine_url: &Url) -> EngineStatus {
+		if let Some(instance_id) = get_instance_id(client, engine_url).await {
+			return EngineStatus::Online {
+				instance_id: Some(instance_id),
+			};
+		}
+
+		if is_accepting_connections(engine_url).await {
+			EngineStatus::Online { instance_id: None }
+		} else {
+			EngineStatus::Offline
+		}
+	}
+
+	/// A heartbeat that timed out while the engine is still accepting connections says nothing about whether it restarted, so the last known
+	/// instance id is kept, to compare the next heartbeat's id against.
+	fn with_known_instance_id(self, old_status: Option<&EngineStatus>) -> EngineStatus {
+		match (self, old_status) {
+			(
+				EngineStatus::Online { instance_id: None },
+				Some(EngineStatus::Online {
+					instance_id: Some(old_instance_id),
+				}),
+			) => EngineStatus::Online {
+				instance_id: Some(old_instance_id.clone()),
+			},
+			(new_status, _) => new_status,
+		}
+	}
+
+	fn is_online(&self) -> bool {
+		matches!(self, EngineStatus::Online { .. })
+	}
+
+	fn did_restart(&self, old_status: &EngineStatus) -> bool {
+		match (old_status, self) {
+			(EngineStatus::Offline, EngineStatus::Online { .. }) => true,
+			(
+				EngineStatus::Online {
+					instance_id: Some(old_instance_id),
+				},
+				EngineStatus::Online {
+					instance_id: Some(new_instance_id),
+				},
+			) => old_instance_id != new_instance_id,
+			_ => false,
+		}
+	}
+}
+
+/// Send a heartbeat request to the engine, returning it's instance id if it answered with one.
+async fn get_instance_id(client: &Client, engine_url: &Url) -> Option<String> {
+	if !matches!(engine_url.scheme(), "http" | "https") {
+		return None;
+	}
+
+	let request = client
+		.post(engine_url.clone())
+		.header("content-type", "application/json")
+		.body(json!({ "heartbeat": true }).to_string())
+		.timeout(CHECK_TIMEOUT);
+
+	let bytes = request.send().await.ok()?.bytes().await.ok()?;
+	let response = from_slice::<Value>(&bytes).ok()?;
+
+	response.get("instanceId")?.as_str().map(|id| id.to_string())
+}
+
+async fn is_accepting_connections(engine_url: &Url) -> bool {
+	let (Some(host), Some(port)) = (engine_url.host_str(), engine_url.port_or_known_default()) else {
+		return false;
+	};
+
+	matches!(timeout(CHECK_TIMEOUT, TcpStream::connect((host, port))).await, Ok(Ok(_)))
+}
@@ -0,0 +1,174 @@
+use anyhow::{anyhow, Result};
+use log::debug;
+use serde_json::{json, Map, Value};
+
+use crate::{
+	collect::Collection,
+	convert::{EnumProperty, Kind, ObjectProperty},
+};
+
+const SCHEMA_DIALECT: &str = "https://json-schema.org/draft/2020-12/schema";
+
+/// Describes the collected component model as a JSON Schema document. Every named kind is placed in `$defs`, and the document itself validates
+/// the component index, which is what the engine sends to the runtime.
+///
+/// `EventKey` and `ActionKey` kinds are validated as the keys that they are on the wire, and their payload schemas are attached under the
+/// `x-event-data` and `x-action-data` annotations.
+pub struct SchemaGen<'a> {
+	collection: &'a Collection,
+	index_name: &'a str,
+	defs: Map<String, Value>,
+}
+
+impl SchemaGen<'_> {
+	pub fn new<'a>(collection: &'a Collection) -> Result<SchemaGen<'a>> {
+		let index_name = collection.get_component_index_name().ok_or(anyhow!(
+			"No component index was found during json schema gen. This indicates a failure in the checking step"
+		))?;
+
+		Ok(SchemaGen {
+			collection,
+			index_name,
+			defs: Map::new(),
+		})
+	}
+
+	pub fn gen(&mut self) {
+		self.gen_index();
+
+		for def in self.collection.get_kinds() {
+			if def.name == self.index_name {
+				continue;
+			}
+
+			debug!("Generating {}", def.name);
+
+			let schema = with_annotations(self.gen_kind(def.kind), Some(def.name), def.comment);
+			self.defs.insert(def.name.to_string(), schema);
+		}
+	}
+
+	pub fn get_output(self) -> String {
+		let document = json!({
+			"$schema": SCHEMA_DIALECT,
+			"$ref": format!("#/$defs/{}", self.index_name),
+			"$defs": self.defs,
+		});
+
+		serde_json::to_string_pretty(&document).unwrap()
+	}
+
+	fn gen_index(&mut self) {
+		let variants = self
+			.collection
+			.get_component_info()
+			.into_iter()
+			.map(|(name, _)| tagged_variant(name, json!({ "$ref": format!("#/$defs/{name}") }), self.collection.get_comment(name)))
+			.collect::<Vec<_>>();
+
+		let schema = with_annotations(
+			json!({ "oneOf": variants }),
+			Some(self.index_name),
+			self.collection.get_comment(self.index_name),
+		);
+		self.defs.insert(self.index_name.to_string(), schema);
+	}
+
+	fn gen_kind(&self, kind: &Kind) -> Value {
+		match kind {
+			Kind::Dynamic => json!({}),
+			Kind::String => json!({ "type": "string" }),
+			Kind::Number => json!({ "type": "number" }),
+			Kind::Bool => json!({ "type": "boolean" }),
+			Kind::Null => json!({ "type": "null" }),
+			Kind::ActionKey { data_type } => key_schema("actionPath", "x-action-data", self.gen_kind(data_type)),
+			Kind::EventKey { data_type } => key_schema("eventPath", "x-event-data", self.gen_kind(data_type)),
+			Kind::Ref { name } => json!({ "$ref": format!("#/$defs/{name}") }),
+			Kind::List { of } => json!({ "type": "array", "items": self.gen_kind(of) }),
+			Kind::Tuple { items } => json!({
+				"type": "array",
+				"prefixItems": items.iter().map(|item| self.gen_kind(item)).collect::<Vec<_>>(),
+				"items": false,
+				"minItems": items.len(),
+			}),
+			Kind::StringEnum { variants } => json!({ "enum": variants }),
+			Kind::KeyedEnum { variants } => self.gen_keyed_enum(variants),
+			Kind::Object { properties } => self.gen_object(properties),
+		}
+	}
+
+	fn gen_keyed_enum(&self, variants: &[EnumProperty]) -> Value {
+		let variants = variants
+			.iter()
+			.map(|variant| tagged_variant(&variant.name, self.gen_kind(&variant.kind), variant.comment.as_deref()))
+			.collect::<Vec<_>>();
+
+		json!({ "oneOf": variants })
+	}
+
+	fn gen_object(&self, properties: &[ObjectProperty]) -> Value {
+		let mut property_schemas = Map::new();
+		let mut required = Vec::new();
+
+		for property in properties {
+			let mut schema = self.gen_kind(&property.kind);
+
+			// rust engines serialize missing optional properties as null
+			if property.is_optional {
+				schema = json!({ "anyOf": [schema, { "type": "null" }] });
+			} else {
+				required.push(property.name.as_str());
+			}
+
+			property_schemas.insert(property.name.clone(), with_annotations(schema, None, property.comment.as_deref()));
+		}
+
+		json!({
+			"type": "object",
+			"properties": property_schemas,
+			"required": required,
+		})
+	}
+}
+
+/// The schema of a `{ "type": name, "def": ... }` value, which is how keyed enums and the component index are serialized.
+fn tagged_variant(name: &str, def_schema: Value, comment: Option<&str>) -> Value {
+	let schema = json!({
+		"type": "object",
+		"properties": {
+			"type": { "const": name },
+			"def": def_schema,
+		},
+		"required": ["type", "def"],
+		"additionalProperties": false,
+	});
+
+	with_annotations(schema, None, comment)
+}
+
+/// The schema of an event or action key, with the schema of it's payload attached as an annotation.
+fn key_schema(path_property: &str, data_annotation: &str, data_schema: Value) -> Value {
+	json!({
+		"type": "object",
+		"properties": {
+			path_property: { "type": "array", "items": { "type": "string" } },
+			"debugSymbol": { "type": ["string", "null"] },
+		},
+		"required": [path_property],
+		data_annotation: data_schema,
+	})
+}
+
+fn with_annotations(mut schema: Value, title: Option<&str>, description: Option<&str>) -> Value {
+	if let Value::Object(map) = &mut schema {
+		if let Some(title) = title {
+			map.insert("title".into(), title.into());
+		}
+
+		if let Some(description) = description.map(str::trim).filter(|description| !description.is_empty()) {
+			map.insert("description".into(), description.into());
+		}
+	}
+
+	schema
+}
@@ -0,0 +1,414 @@
+use anyhow::{anyhow, Result};
+use inflector::Inflector;
+use log::debug;
+use std::{collections::HashSet, fmt::Write};
+
+use crate::{
+	collect::Collection,
+	convert::{EnumProperty, Kind, ObjectProperty},
+};
+
+const PRELUDE: &str = include_str!("python_prelude.py");
+
+const INDENT: &str = "    ";
+
+#[derive(Debug, Clone)]
+enum KindContext {
+	/// This is the type of a field
+	Type,
+	/// This is the type of a method argument
+	CallSignature,
+	/// The value representation of the kind. This is `existing_value_expression`, unless it needs to be converted into the field type.
+	Value { existing_value_expression: String },
+}
+
+pub struct PyGen<'a> {
+	collection: &'a Collection,
+	names_generated: HashSet<String>,
+	component_names: HashSet<&'a str>,
+	index_name: &'a str,
+	output: String,
+}
+
+impl PyGen<'_> {
+	pub fn new<'a>(collection: &'a Collection) -> Result<PyGen<'a>> {
+		let index_name = collection.get_component_index_name().ok_or(anyhow!(
+			"No component index was found during python code gen. This indicates a failure in the checking step"
+		))?;
+
+		Ok(PyGen {
+			collection,
+			index_name,
+			names_generated: HashSet::new(),
+			component_names: collection.get_component_info().into_iter().map(|(name, _)| name).collect(),
+			output: PRELUDE.replace("COMPONENT_INDEX", index_name),
+		})
+	}
+
+	pub fn gen(&mut self) -> Result<()> {
+		self.gen_index();
+
+		for def in self.collection.get_kinds() {
+			debug!("Generating {}", def.name);
+
+			match def.kind {
+				Kind::Dynamic
+				| Kind::String
+				| Kind::Number
+				| Kind::Bool
+				| Kind::Null
+				| Kind::ActionKey { .. }
+				| Kind::EventKey { .. }
+				| Kind::Ref { .. }
+				| Kind::List { .. }
+				| Kind::Tuple { .. } => {
+					if !self.has_item(def.name) {
+						let anon_item = self.gen_kind(def.name, None, def.kind, KindContext::Type)?;
+						let item = format!("{}{}: TypeAlias = {anon_item}\n", line_comment(def.comment, ""), def.name);
+
+						self.add_item(def.name, item);
+					}
+				}
+				Kind::StringEnum { .. } | Kind::KeyedEnum { .. } | Kind::Object { .. } => {
+					self.gen_kind(def.name, def.comment, def.kind, KindContext::Type)?;
+				}
+			};
+		}
+
+		Ok(())
+	}
+
+	pub fn get_output(self) -> String {
+		self.output
+	}
+
+	fn gen_index(&mut self) {
+		let mut variants = Vec::new();
+		let mut item = String::new();
+
+		for (name, _) in self.collection.get_component_info() {
+			let variant_name = format!("_{}{name}", self.index_name);
+
+			writeln!(
+				&mut item,
+				"{variant_name} = TypedDict(\"{variant_name}\", {{\"type\": Literal[\"{name}\"], \"def\": \"{name}\"}})"
+			)
+			.unwrap();
+			variants.push(variant_name);
+		}
+
+		write!(
+			&mut item,
+			"\n{}{}: TypeAlias = Union[{}]\n",
+			line_comment(self.collection.get_comment(self.index_name), ""),
+			self.index_name,
+			variants.join(", ")
+		)
+		.unwrap();
+
+		self.add_item(self.index_name, item);
+	}
+
+	fn gen_kind(&mut self, context_name: &str, comment: Option<&str>, kind: &Kind, context: KindContext) -> Result<String> {
+		let type_expression = match kind {
+			Kind::Dynamic => "Any".to_string(),
+			Kind::String => "str".to_string(),
+			Kind::Number => "float".to_string(),
+			Kind::Bool => "bool".to_string(),
+			Kind::Null => "None".to_string(),
+			Kind::ActionKey { data_type } => {
+				let inner = self.gen_kind(&format!("{context_name}ActionData"), None, data_type, KindContext::Type)?;
+
+				format!("ActionKey[{inner}]")
+			}
+			Kind::EventKey { data_type } => {
+				let inner = self.gen_kind(&format!("{context_name}EventData"), None, data_type, KindContext::Type)?;
+
+				format!("EventKey[{inner}]")
+			}
+			Kind::Ref { name } => {
+				if name == self.index_name {
+					match &context {
+						KindContext::Type => format!("\"{name}\""),
+						KindContext::CallSignature => format!("Union[\"{name}\", IntoComponentIndex]"),
+						KindContext::Value { existing_value_expression } => return Ok(format!("_into_component_index({existing_value_expression})")),
+					}
+				} else {
+					format!("\"{name}\"")
+				}
+			}
+			Kind::List { of } => {
+				let inner = self.gen_kind(&format!("{context_name}Item"), None, of, KindContext::Type)?;
+
+				format!("List[{inner}]")
+			}
+			Kind::Tuple { items } => {
+				let inner = items
+					.iter()
+					.enumerate()
+					.map(|(index, kind)| self.gen_kind(&format!("{context_name}Item{index}"), None, kind, KindContext::Type))
+					.collect::<Result<Vec<_>>>()?;
+
+				format!("Tuple[{}]", inner.join(", "))
+			}
+			Kind::StringEnum { variants } => {
+				if !self.has_item(context_name) {
+					let variants = variants.iter().map(|variant| format!("\"{variant}\"")).collect::<Vec<_>>().join(", ");
+					let item = format!("{}{context_name}: TypeAlias = Literal[{variants}]\n", line_comment(comment, ""));
+
+					self.add_item(context_name, item);
+				}
+
+				format!("\"{context_name}\"")
+			}
+			Kind::KeyedEnum { variants } => {
+				if !self.has_item(context_name) {
+					self.gen_keyed_enum(context_name, comment, variants)?;
+				}
+
+				format!("\"{context_name}\"")
+			}
+			Kind::Object { properties } => {
+				if !self.has_item(context_name) && self.index_name != context_name {
+					self.gen_dataclass(context_name, comment, properties)?;
+				}
+
+				format!("\"{context_name}\"")
+			}
+		};
+
+		Ok(match context {
+			KindContext::Type | KindContext::CallSignature => type_expression,
+			KindContext::Value { existing_value_expression } => existing_value_expression,
+		})
+	}
+
+	fn has_item(&self, name: &str) -> bool {
+		self.names_generated.contains(name)
+	}
+
+	fn add_item(&mut self, name: &str, item: String) {
+		self.names_generated.insert(name.to_string());
+		self.output.push_str("\n\n");
+		self.output.push_str(&item);
+	}
+
+	fn gen_keyed_enum(&mut self, context_name: &str, comment: Option<&str>, variants: &[EnumProperty]) -> Result<()> {
+		// claim the name before generating the variants, in case one of them refers back to this enum
+		self.names_generated.insert(context_name.to_string());
+
+		let mut variant_names = Vec::new();
+		let mut item = String::new();
+
+		for variant in variants {
+			let kind_type = self.gen_kind(
+				&get_keyed_enum_variant_context_name(context_name, &variant.name),
+				variant.comment.as_deref(),
+				&variant.kind,
+				KindContext::Type,
+			)?;
+
+			// variant names are prefixed with an underscore so that they can't clash with the names of generated kinds, which are pascal case
+			let variant_name = format!("_{context_name}{}", &variant.name);
+
+			writeln!(
+				&mut item,
+				"{variant_name} = TypedDict(\"{variant_name}\", {{\"type\": Literal[\"{}\"], \"def\": {kind_type}}})",
+				&variant.name
+			)
+			.unwrap();
+			variant_names.push(variant_name);
+		}
+
+		write!(
+			&mut item,
+			"\n{}{context_name}: TypeAlias = Union[{}]\n",
+			line_comment(comment, ""),
+			variant_names.join(", ")
+		)
+		.unwrap();
+		self.add_item(context_name, item);
+
+		Ok(())
+	}
+
+	fn gen_dataclass(&mut self, context_name: &str, comment: Option<&str>, properties: &[ObjectProperty]) -> Result<()> {
+		self.names_generated.insert(context_name.to_string());
+
+		let mut required_fields = String::new();
+		let mut optional_fields = String::new();
+		let mut conversions = String::new();
+		let mut methods = String::new();
+
+		for property in properties {
+			let (resolved_kind, _) = self.collection.resolve_kind(&property.kind);
+			let property_context_name = get_class_property_context_name(context_name, &property.name);
+			let snake_name = property.name.to_snake_case();
+
+			let kind_type = self.gen_kind(&property_context_name, property.comment.as_deref(), &property.kind, KindContext::Type)?;
+			let kind_call_signature = self.gen_kind(&property_context_name, property.comment.as_deref(), &property.kind, KindContext::CallSignature)?;
+			let kind_value = self.gen_kind(
+				&property_context_name,
+				property.comment.as_deref(),
+				&property.kind,
+				KindContext::Value {
+					existing_value_expression: snake_name.clone(),
+				},
+			)?;
+
+			// fields are snake case, but they must be serialized with their original camel case names
+			let metadata = (snake_name != property.name).then(|| format!("metadata={{\"json\": \"{}\"}}", &property.name));
+			let field = match (property.is_optional, metadata) {
+				(false, None) => format!("{snake_name}: {kind_type}"),
+				(false, Some(metadata)) => format!("{snake_name}: {kind_type} = field({metadata})"),
+				(true, None) => format!("{snake_name}: Optional[{kind_type}] = None"),
+				(true, Some(metadata)) => format!("{snake_name}: Optional[{kind_type}] = field(default=None, {metadata})"),
+			};
+
+			let fields = if property.is_optional { &mut optional_fields } else { &mut required_fields };
+			writeln!(fields, "{}{INDENT}{field}", line_comment(property.comment.as_deref(), INDENT)).unwrap();
+
+			let self_value = self.gen_kind(
+				&property_context_name,
+				None,
+				&property.kind,
+				KindContext::Value {
+					existing_value_expression: format!("self.{snake_name}"),
+				},
+			)?;
+
+			if self_value != format!("self.{snake_name}") {
+				writeln!(&mut conversions, "{INDENT}{INDENT}self.{snake_name} = {self_value}").unwrap();
+			}
+
+			// booleans are usually being turned on, so let the argument be left off
+			let argument = if let Kind::Bool = resolved_kind {
+				format!("{snake_name}: bool = True")
+			} else {
+				format!("{snake_name}: {kind_call_signature}")
+			};
+
+			write!(
+				&mut methods,
+				"\n{INDENT}def with_{snake_name}(self, {argument}) -> \"{context_name}\":\n{INDENT}{INDENT}self.{snake_name} = {kind_value}\n{INDENT}{INDENT}return self\n"
+			)
+			.unwrap();
+
+			if let Kind::List { of } = resolved_kind {
+				let singular_name = snake_name.to_singular();
+
+				if snake_name != singular_name {
+					let inner_call_signature = self.gen_kind(&property_context_name, None, of, KindContext::CallSignature)?;
+					let inner_value = self.gen_kind(
+						&property_context_name,
+						None,
+						of,
+						KindContext::Value {
+							existing_value_expression: singular_name.clone(),
+						},
+					)?;
+					let initializer = if property.is_optional {
+						format!("{INDENT}{INDENT}if self.{snake_name} is None:\n{INDENT}{INDENT}{INDENT}self.{snake_name} = []\n")
+					} else {
+						String::new()
+					};
+
+					write!(
+						&mut methods,
+						"\n{INDENT}def add_{singular_name}(self, {singular_name}: {inner_call_signature}) -> \"{context_name}\":\n{initializer}{INDENT}{INDENT}self.{snake_name}.append({inner_value})\n{INDENT}{INDENT}return self\n"
+					)
+					.unwrap();
+				}
+			}
+		}
+
+		if !conversions.is_empty() {
+			methods.insert_str(0, &format!("\n{INDENT}def __post_init__(self) -> None:\n{conversions}"));
+		}
+
+		if self.component_names.contains(context_name) {
+			write!(
+				&mut methods,
+				"\n{INDENT}def into_index(self) -> \"{index_name}\":\n{INDENT}{INDENT}return {{\"type\": \"{context_name}\", \"def\": self}}\n",
+				index_name = self.index_name
+			)
+			.unwrap();
+		}
+
+		let mut body = docstring(comment, INDENT);
+
+		for section in [required_fields, optional_fields] {
+			if !section.is_empty() {
+				if !body.is_empty() {
+					body.push('\n');
+				}
+
+				body.push_str(&section);
+			}
+		}
+
+		body.push_str(&methods);
+
+		if body.is_empty() {
+			body = format!("{INDENT}pass\n");
+		}
+
+		let item = format!("@dataclass\nclass {context_name}:\n{body}");
+		self.add_item(context_name, item);
+
+		Ok(())
+	}
+}
+
+fn get_class_property_context_name(class_context_name: &str, property_name: &str) -> String {
+	// all property names are camel case, but all property names must be pascal case
+	format!("{class_context_name}{}", property_name.to_pascal_case())
+}
+
+fn get_keyed_enum_variant_context_name(enum_context_name: &str, variant_name: &str) -> String {
+	// all variant names must be pascal case, so nothing to do here
+	format!("{enum_context_name}{variant_name}")
+}
+
+/// Render `comment` as `#` comments, with each line prefixed by `indent`. Renders nothing if there is no comment.
+fn line_comment(comment: Option<&str>, indent: &str) -> String {
+	match comment.map(str::trim) {
+		Some(comment) if !comment.is_empty() => comment
+			.lines()
+			.map(|line| {
+				if line.is_empty() {
+					format!("{indent}#\n")
+				} else {
+					format!("{indent}# {line}\n")
+				}
+			})
+			.collect(),
+		_ => String::new(),
+	}
+}
+
+/// Render `comment` as a docstring, with each line prefixed by `indent`. Renders nothing if there is no comment.
+fn docstring(comment: Option<&str>, indent: &str) -> String {
+	let comment = match comment.map(str::trim) {
+		Some(comment) if !comment.is_empty() => comment.replace('\\', "\\\\").replace("\"\"\"", "\\\"\\\"\\\""),
+		_ => return String::new(),
+	};
+
+	let mut output = format!("{indent}\"\"\"");
+
+	for (index, line) in comment.lines().enumerate() {
+		if index > 0 {
+			output.push('\n');
+
+			if !line.is_empty() {
+				output.push_str(indent);
+			}
+		}
+
+		output.push_str(line);
+	}
+
+	output.push_str("\"\"\"\n");
+
+	output
+}
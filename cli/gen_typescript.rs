@@ -0,0 +1,371 @@
+use anyhow::{anyhow, Result};
+use inflector::Inflector;
+use log::debug;
+use std::{collections::HashSet, fmt::Write};
+
+use crate::{
+	collect::Collection,
+	convert::{EnumProperty, Kind, ObjectProperty},
+};
+
+const PRELUDE: &str = include_str!("typescript_prelude.ts");
+
+/// The maximum number of required properties that a class constructor will take as separate arguments. Classes with more required properties
+/// than this take a single object instead.
+const CONSTRUCTOR_ARGUMENT_LIMIT: usize = 3;
+
+#[derive(Debug, Clone)]
+enum KindContext {
+	/// This is the type of a property
+	Type,
+	/// This is the type of a method or constructor argument
+	CallSignature,
+	/// The value representation of the kind. This is `existing_value_expression`, unless it needs to be converted into the property type.
+	Value { existing_value_expression: String },
+}
+
+pub struct TsGen<'a> {
+	collection: &'a Collection,
+	names_generated: HashSet<String>,
+	component_names: HashSet<&'a str>,
+	index_name: &'a str,
+	output: String,
+}
+
+impl TsGen<'_> {
+	pub fn new<'a>(collection: &'a Collection) -> Result<TsGen<'a>> {
+		let index_name = collection.get_component_index_name().ok_or(anyhow!(
+			"No component index was found during typescript code gen. This indicates a failure in the checking step"
+		))?;
+
+		Ok(TsGen {
+			collection,
+			index_name,
+			names_generated: HashSet::new(),
+			component_names: collection.get_component_info().into_iter().map(|(name, _)| name).collect(),
+			output: PRELUDE.replace("COMPONENT_INDEX", index_name),
+		})
+	}
+
+	pub fn gen(&mut self) -> Result<()> {
+		self.gen_index();
+
+		for def in self.collection.get_kinds() {
+			debug!("Generating {}", def.name);
+
+			match def.kind {
+				Kind::Dynamic
+				| Kind::String
+				| Kind::Number
+				| Kind::Bool
+				| Kind::Null
+				| Kind::ActionKey { .. }
+				| Kind::EventKey { .. }
+				| Kind::Ref { .. }
+				| Kind::List { .. }
+				| Kind::Tuple { .. } => {
+					if !self.has_item(def.name) {
+						let anon_item = self.gen_kind(def.name, None, def.kind, KindContext::Type)?;
+						let item = format!("{}export type {} = {anon_item}\n", doc_comment(def.comment, ""), def.name);
+
+						self.add_item(def.name, item);
+					}
+				}
+				Kind::StringEnum { .. } | Kind::KeyedEnum { .. } | Kind::Object { .. } => {
+					self.gen_kind(def.name, def.comment, def.kind, KindContext::Type)?;
+				}
+			};
+		}
+
+		Ok(())
+	}
+
+	pub fn get_output(self) -> String {
+		self.output
+	}
+
+	fn gen_index(&mut self) {
+		let mut variants = String::new();
+
+		for (name, _) in self.collection.get_component_info() {
+			write!(&mut variants, "\n\t| {{ type: '{name}'; def: {name} }}").unwrap();
+		}
+
+		let comment = doc_comment(self.collection.get_comment(self.index_name), "");
+		let item = format!("{comment}export type {} ={variants}\n", self.index_name);
+
+		self.add_item(self.index_name, item);
+	}
+
+	fn gen_kind(&mut self, context_name: &str, comment: Option<&str>, kind: &Kind, context: KindContext) -> Result<String> {
+		let type_expression = match kind {
+			Kind::Dynamic => "unknown".to_string(),
+			Kind::String => "string".to_string(),
+			Kind::Number => "number".to_string(),
+			Kind::Bool => "boolean".to_string(),
+			Kind::Null => "null".to_string(),
+			Kind::ActionKey { data_type } => {
+				let inner = self.gen_kind(&format!("{context_name}ActionData"), None, data_type, KindContext::Type)?;
+
+				format!("ActionKey<{inner}>")
+			}
+			Kind::EventKey { data_type } => {
+				let inner = self.gen_kind(&format!("{context_name}EventData"), None, data_type, KindContext::Type)?;
+
+				format!("EventKey<{inner}>")
+			}
+			Kind::Ref { name } => {
+				if name == self.index_name {
+					match &context {
+						KindContext::Type => name.clone(),
+						KindContext::CallSignature => format!("{name} | IntoComponentIndex"),
+						KindContext::Value { existing_value_expression } => return Ok(format!("intoComponentIndex({existing_value_expression})")),
+					}
+				} else {
+					name.clone()
+				}
+			}
+			Kind::List { of } => {
+				let inner = self.gen_kind(&format!("{context_name}Item"), None, of, KindContext::Type)?;
+
+				format!("Array<{inner}>")
+			}
+			Kind::Tuple { items } => {
+				let inner = items
+					.iter()
+					.enumerate()
+					.map(|(index, kind)| self.gen_kind(&format!("{context_name}Item{index}"), None, kind, KindContext::Type))
+					.collect::<Result<Vec<_>>>()?;
+
+				format!("[{}]", inner.join(", "))
+			}
+			Kind::StringEnum { variants } => {
+				if !self.has_item(context_name) {
+					let variants = variants.iter().map(|variant| format!("\n\t| '{variant}'")).collect::<String>();
+					let item = format!("{}export type {context_name} ={variants}\n", doc_comment(comment, ""));
+
+					self.add_item(context_name, item);
+				}
+
+				context_name.to_string()
+			}
+			Kind::KeyedEnum { variants } => {
+				if !self.has_item(context_name) {
+					self.gen_keyed_enum(context_name, comment, variants)?;
+				}
+
+				context_name.to_string()
+			}
+			Kind::Object { properties } => {
+				if !self.has_item(context_name) && self.index_name != context_name {
+					self.gen_class(context_name, comment, properties)?;
+				}
+
+				context_name.to_string()
+			}
+		};
+
+		Ok(match context {
+			KindContext::Type | KindContext::CallSignature => type_expression,
+			KindContext::Value { existing_value_expression } => existing_value_expression,
+		})
+	}
+
+	fn has_item(&self, name: &str) -> bool {
+		self.names_generated.contains(name)
+	}
+
+	fn add_item(&mut self, name: &str, item: String) {
+		self.names_generated.insert(name.to_string());
+		self.output.push('\n');
+		self.output.push_str(&item);
+	}
+
+	fn gen_keyed_enum(&mut self, context_name: &str, comment: Option<&str>, variants: &[EnumProperty]) -> Result<()> {
+		// claim the name before generating the variants, in case one of them refers back to this enum
+		self.names_generated.insert(context_name.to_string());
+
+		let mut variant_types = String::new();
+
+		for variant in variants {
+			let kind_type = self.gen_kind(
+				&get_keyed_enum_variant_context_name(context_name, &variant.name),
+				variant.comment.as_deref(),
+				&variant.kind,
+				KindContext::Type,
+			)?;
+
+			write!(&mut variant_types, "\n\t| {{ type: '{}'; def: {kind_type} }}", &variant.name).unwrap();
+		}
+
+		let item = format!("{}export type {context_name} ={variant_types}\n", doc_comment(comment, ""));
+		self.add_item(context_name, item);
+
+		Ok(())
+	}
+
+	fn gen_class(&mut self, context_name: &str, comment: Option<&str>, properties: &[ObjectProperty]) -> Result<()> {
+		self.names_generated.insert(context_name.to_string());
+
+		let mut fields = String::new();
+		let mut methods = String::new();
+		let constructor = self.gen_constructor(context_name, properties)?;
+
+		for property in properties {
+			let (resolved_kind, _) = self.collection.resolve_kind(&property.kind);
+			let property_context_name = get_class_property_context_name(context_name, &property.name);
+			let pascal_name = property.name.to_pascal_case();
+			let name = &property.name;
+
+			let kind_type = self.gen_kind(&property_context_name, property.comment.as_deref(), &property.kind, KindContext::Type)?;
+			let kind_call_signature = self.gen_kind(&property_context_name, property.comment.as_deref(), &property.kind, KindContext::CallSignature)?;
+			let kind_value = self.gen_kind(
+				&property_context_name,
+				property.comment.as_deref(),
+				&property.kind,
+				KindContext::Value {
+					existing_value_expression: name.clone(),
+				},
+			)?;
+
+			writeln!(
+				&mut fields,
+				"{}\t{name}{}: {kind_type}",
+				doc_comment(property.comment.as_deref(), "\t"),
+				if property.is_optional { "?" } else { "" }
+			)
+			.unwrap();
+
+			// booleans are usually being turned on, so let the argument be left off
+			let argument = if let Kind::Bool = resolved_kind {
+				format!("{name} = true")
+			} else {
+				format!("{name}: {kind_call_signature}")
+			};
+
+			write!(
+				&mut methods,
+				"\n\twith{pascal_name}({argument}): {context_name} {{\n\t\tthis.{name} = {kind_value}\n\n\t\treturn this\n\t}}\n"
+			)
+			.unwrap();
+
+			if let Kind::List { of } = resolved_kind {
+				let singular_name = name.to_singular();
+
+				if *name != singular_name {
+					let inner_call_signature = self.gen_kind(&property_context_name, None, of, KindContext::CallSignature)?;
+					let inner_value = self.gen_kind(
+						&property_context_name,
+						None,
+						of,
+						KindContext::Value {
+							existing_value_expression: singular_name.clone(),
+						},
+					)?;
+					let list = if property.is_optional {
+						format!("(this.{name} ??= [])")
+					} else {
+						format!("this.{name}")
+					};
+
+					write!(
+						&mut methods,
+						"\n\tadd{}({singular_name}: {inner_call_signature}): {context_name} {{\n\t\t{list}.push({inner_value})\n\n\t\treturn this\n\t}}\n",
+						singular_name.to_pascal_case()
+					)
+					.unwrap();
+				}
+			}
+		}
+
+		if self.component_names.contains(context_name) {
+			write!(
+				&mut methods,
+				"\n\tintoIndex(): {index_name} {{\n\t\treturn {{ type: '{context_name}', def: this }}\n\t}}\n",
+				index_name = self.index_name
+			)
+			.unwrap();
+		}
+
+		let item = format!(
+			"{}export class {context_name} {{\n{fields}\n{constructor}{methods}}}\n",
+			doc_comment(comment, "")
+		);
+		self.add_item(context_name, item);
+
+		Ok(())
+	}
+
+	/// Generate a constructor that takes every required property. If there are only a few of them, they are taken as separate arguments.
+	fn gen_constructor(&mut self, class_name: &str, properties: &[ObjectProperty]) -> Result<String> {
+		let required = properties.iter().filter(|property| !property.is_optional).collect::<Vec<_>>();
+		let is_spread = required.len() <= CONSTRUCTOR_ARGUMENT_LIMIT;
+
+		let mut arguments = Vec::new();
+		let mut body = String::new();
+		let mut comment = format!("Construct a new {class_name}.");
+
+		for property in required {
+			let property_context_name = get_class_property_context_name(class_name, &property.name);
+			let name = &property.name;
+			let argument = if is_spread { name.clone() } else { format!("props.{name}") };
+
+			let call_signature = self.gen_kind(&property_context_name, property.comment.as_deref(), &property.kind, KindContext::CallSignature)?;
+			let value = self.gen_kind(
+				&property_context_name,
+				property.comment.as_deref(),
+				&property.kind,
+				KindContext::Value {
+					existing_value_expression: argument,
+				},
+			)?;
+
+			arguments.push(format!("{name}: {call_signature}"));
+			writeln!(&mut body, "\t\tthis.{name} = {value}").unwrap();
+
+			if let Some(property_comment) = &property.comment {
+				write!(&mut comment, "\n\nArgument `{name}`: {property_comment}").unwrap();
+			}
+		}
+
+		let arguments = if is_spread {
+			arguments.join(", ")
+		} else {
+			format!("props: {{ {} }}", arguments.join("; "))
+		};
+
+		Ok(format!("{}\tconstructor({arguments}) {{\n{body}\t}}\n", doc_comment(Some(&comment), "\t")))
+	}
+}
+
+fn get_class_property_context_name(class_context_name: &str, property_name: &str) -> String {
+	// all property names are camel case, but all property names must be pascal case
+	format!("{class_context_name}{}", property_name.to_pascal_case())
+}
+
+fn get_keyed_enum_variant_context_name(enum_context_name: &str, variant_name: &str) -> String {
+	// all variant names must be pascal case, so nothing to do here
+	format!("{enum_context_name}{variant_name}")
+}
+
+/// Render `comment` as a jsdoc comment, with each line prefixed by `indent`. Renders nothing if there is no comment.
+fn doc_comment(comment: Option<&str>, indent: &str) -> String {
+	let comment = match comment.map(str::trim) {
+		Some(comment) if !comment.is_empty() => comment,
+		_ => return String::new(),
+	};
+
+	let mut output = format!("{indent}/**\n");
+
+	for line in comment.replace("*/", "*\\/").lines() {
+		if line.is_empty() {
+			writeln!(&mut output, "{indent} *").unwrap();
+		} else {
+			writeln!(&mut output, "{indent} * {line}").unwrap();
+		}
+	}
+
+	writeln!(&mut output, "{indent} */").unwrap();
+
+	output
+}
@@ -1,10 +1,12 @@
+use deno_doc::Location;
 use inflector::Inflector;
-use std::{collections::HashMap, fmt::Display};
+use std::fmt::Display;
 
 use crate::{
 	collect::Collection,
 	convert::Kind,
 	diagnostic::{Diagnostic, DiagnosticList},
+	engine::Engine,
 };
 
 const RUST_RESERVED_WORDS: &[&str] = &[
@@ -13,16 +15,62 @@ const RUST_RESERVED_WORDS: &[&str] = &[
 	"dyn", "abstract", "become", "box", "do", "final", "macro", "override", "priv", "typeof", "unsized", "virtual", "yield", "try",
 ];
 
-#[derive(Debug, Clone, Copy)]
-enum ReservationTarget {
-	Rust,
-}
+// only the words that can't be used as parameter names, because properties are only ever used as such
+const TYPESCRIPT_RESERVED_WORDS: &[&str] = &[
+	"break",
+	"case",
+	"catch",
+	"class",
+	"const",
+	"continue",
+	"debugger",
+	"default",
+	"delete",
+	"do",
+	"else",
+	"enum",
+	"export",
+	"extends",
+	"false",
+	"finally",
+	"for",
+	"function",
+	"if",
+	"import",
+	"in",
+	"instanceof",
+	"new",
+	"null",
+	"return",
+	"super",
+	"switch",
+	"this",
+	"throw",
+	"true",
+	"try",
+	"typeof",
+	"var",
+	"void",
+	"while",
+	"with",
+	"yield",
+	"let",
+	"static",
+	"implements",
+	"interface",
+	"package",
+	"private",
+	"protected",
+	"public",
+	"await",
+	"arguments",
+	"eval",
+];
 
-impl Display for ReservationTarget {
-	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-		write!(f, "rust")
-	}
-}
+const PYTHON_RESERVED_WORDS: &[&str] = &[
+	"False", "None", "True", "and", "as", "assert", "async", "await", "break", "class", "continue", "def", "del", "elif", "else", "except", "finally", "for",
+	"from", "global", "if", "import", "in", "is", "lambda", "nonlocal", "not", "or", "pass", "raise", "return", "try", "while", "with", "yield",
+];
 
 #[derive(Debug, Clone, Copy)]
 enum NameContext {
@@ -43,57 +91,66 @@ impl Display for NameContext {
 
 pub struct Inspector<'a> {
 	collection: &'a Collection,
-	reserved_words: HashMap<&'static str, ReservationTarget>,
+	engine: Engine,
+	reserved_words: &'static [&'static str],
 }
 
-// TODO we should be passing a context along into these functions, so that errors like "type is a reserved word" show where the offensive property is located
 impl Inspector<'_> {
-	pub fn new<'a>(collection: &'a Collection) -> Inspector<'a> {
-		let mut reserved_words = HashMap::new();
-
-		reserved_words.extend(RUST_RESERVED_WORDS.iter().copied().map(|word| (word, ReservationTarget::Rust)));
+	/// Only the words that are reserved by `engine` are checked, because the bindings are only generated for that engine
+	pub fn new<'a>(collection: &'a Collection, engine: Engine) -> Inspector<'a> {
+		let reserved_words = match engine {
+			Engine::Rust => RUST_RESERVED_WORDS,
+			Engine::TypeScript => TYPESCRIPT_RESERVED_WORDS,
+			Engine::Python => PYTHON_RESERVED_WORDS,
+			Engine::JsonSchema => &[],
+		};
 
-		Inspector { collection, reserved_words }
+		Inspector {
+			collection,
+			engine,
+			reserved_words,
+		}
 	}
 
 	pub fn inspect(self, diagnostic_list: &mut DiagnosticList) {
 		for def in self.collection.get_kinds() {
-			self.inspect_name(def.name, NameContext::Type, diagnostic_list);
-			self.inspect_kind(def.kind, diagnostic_list);
+			self.inspect_name(def.name, NameContext::Type, def.location, diagnostic_list);
+			self.inspect_kind(def.kind, def.location, diagnostic_list);
 		}
 	}
 
-	fn inspect_kind(&self, kind: &Kind, diagnostic_list: &mut DiagnosticList) {
+	/// Inspect the names within `kind`. `location` is the location of the kind, which is used for the names that don't have one of their own.
+	fn inspect_kind(&self, kind: &Kind, location: &Location, diagnostic_list: &mut DiagnosticList) {
 		match kind {
 			Kind::Dynamic | Kind::String | Kind::Number | Kind::Bool | Kind::Null | Kind::Ref { .. } => (),
-			Kind::ActionKey { data_type } | Kind::EventKey { data_type } => self.inspect_kind(data_type, diagnostic_list),
-			Kind::List { of } => self.inspect_kind(of, diagnostic_list),
+			Kind::ActionKey { data_type } | Kind::EventKey { data_type } => self.inspect_kind(data_type, location, diagnostic_list),
+			Kind::List { of } => self.inspect_kind(of, location, diagnostic_list),
 			Kind::Tuple { items } => {
 				for item in items {
-					self.inspect_kind(item, diagnostic_list);
+					self.inspect_kind(item, location, diagnostic_list);
 				}
 			}
 			Kind::StringEnum { variants } => {
 				for name in variants {
-					self.inspect_name(name.as_str(), NameContext::Variant, diagnostic_list)
+					self.inspect_name(name.as_str(), NameContext::Variant, location, diagnostic_list)
 				}
 			}
 			Kind::KeyedEnum { variants } => {
 				for variant in variants {
-					self.inspect_name(&variant.name, NameContext::Variant, diagnostic_list);
-					self.inspect_kind(&variant.kind, diagnostic_list);
+					self.inspect_name(&variant.name, NameContext::Variant, &variant.location, diagnostic_list);
+					self.inspect_kind(&variant.kind, &variant.location, diagnostic_list);
 				}
 			}
 			Kind::Object { properties } => {
 				for property in properties {
-					self.inspect_name(&property.name, NameContext::Property, diagnostic_list);
-					self.inspect_kind(&property.kind, diagnostic_list);
+					self.inspect_name(&property.name, NameContext::Property, &property.location, diagnostic_list);
+					self.inspect_kind(&property.kind, &property.location, diagnostic_list);
 				}
 			}
 		}
 	}
 
-	fn inspect_name(&self, name: &str, context: NameContext, diagnostic_list: &mut DiagnosticList) {
+	fn inspect_name(&self, name: &str, context: NameContext, location: &Location, diagnostic_list: &mut DiagnosticList) {
 		let (is_valid_case, expected, expected_type) = match context {
 			NameContext::Variant | NameContext::Type => {
 				let expected = name.to_pascal_case();
@@ -114,24 +171,21 @@ impl Inspector<'_> {
 					.text(" ")
 					.inline_code(name)
 					.shift()
-					.text("Expected the ")
-					.text(expected_type)
-					.text(" form of the word: ")
-					.inline_code(expected)
+					.location(location)
+					.hint(format!("expected the {expected_type} form of the word: `{expected}`"))
 					.build(),
 			)
 		}
 
-		if let Some(reservation_target) = self.reserved_words.get(&name) {
+		if self.reserved_words.contains(&name) {
 			diagnostic_list.add(
 				Diagnostic::start("Use of reserved ")
 					.text(context)
 					.text(" ")
 					.inline_code(name)
 					.shift()
-					.text("This word is a reserved in ")
-					.text(reservation_target)
-					.text(", which is an engine that could be targeted")
+					.location(location)
+					.hint(format!("this word is reserved in {}, which is the engine being targeted", self.engine))
 					.build(),
 			)
 		}
@@ -0,0 +1,123 @@
+use anyhow::{bail, Context, Result};
+use log::info;
+use serde::{Deserialize, Serialize};
+use serde_json::{from_slice, to_string_pretty};
+use sha2::{Digest, Sha256};
+use std::{
+	collections::BTreeMap,
+	io::ErrorKind,
+	path::{Path, PathBuf},
+};
+use tokio::fs::{read, write};
+use url::Url;
+
+use crate::diagnostic::Diagnostic;
+
+const LOCKFILE_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct LockfileContent {
+	version: u32,
+	/// The hex encoded sha256 of each remote module
+	modules: BTreeMap<Url, String>,
+}
+
+/// Records the hash of every remote module that is loaded, so that a remote module changing upstream is an error, rather than a silent
+/// change to the build. Local modules are not recorded, because they are expected to change.
+#[derive(Debug)]
+pub struct Lockfile {
+	path: PathBuf,
+	frozen: bool,
+	modules: BTreeMap<Url, String>,
+	did_change: bool,
+}
+
+impl Lockfile {
+	/// Load the lockfile at `path`. If it doesn't exist, an empty lockfile is used, unless `frozen` is set, in which case nothing could be
+	/// verified, so it is an error.
+	pub async fn load(path: &Path, frozen: bool) -> Result<Lockfile> {
+		let modules = match read(path).await {
+			Ok(bytes) => {
+				let content = from_slice::<LockfileContent>(&bytes).with_context(|| format!("failed to parse the lockfile at {path:?}"))?;
+
+				if content.version != LOCKFILE_VERSION {
+					bail!(
+						"the lockfile at {path:?} is version {}, but only version {LOCKFILE_VERSION} is supported",
+						content.version
+					);
+				}
+
+				content.modules
+			}
+			Err(error) if error.kind() == ErrorKind::NotFound => match frozen {
+				true => bail!("expected a lockfile at {path:?} because `--frozen` was passed, but there isn't one"),
+				false => BTreeMap::new(),
+			},
+			Err(error) => return Err(error).with_context(|| format!("failed to read the lockfile at {path:?}")),
+		};
+
+		Ok(Lockfile {
+			path: path.to_path_buf(),
+			frozen,
+			modules,
+			did_change: false,
+		})
+	}
+
+	/// Verify that `content` matches the recorded hash of `specifier`. If `specifier` has not been recorded yet, it is recorded, unless the
+	/// lockfile is frozen.
+	pub fn verify(&mut self, specifier: &Url, content: &[u8]) -> Result<()> {
+		if specifier.scheme() == "file" {
+			return Ok(());
+		}
+
+		let mut hasher = Sha256::new();
+		hasher.update(content);
+		let hash = hex::encode(hasher.finalize());
+
+		match self.modules.get(specifier) {
+			Some(expected_hash) if expected_hash == &hash => Ok(()),
+			Some(_) => Diagnostic::start("The contents of ")
+				.inline_code(specifier)
+				.text(" do not match the hash in ")
+				.inline_code(self.path.display())
+				.hint("if this change was expected, remove the module from the lockfile so that it's new hash is recorded")
+				.build()
+				.err(),
+			None if self.frozen => Diagnostic::start("Module ")
+				.inline_code(specifier)
+				.text(" is not in ")
+				.inline_code(self.path.display())
+				.text(", which is frozen")
+				.hint("run without `--frozen` to record it")
+				.build()
+				.err(),
+			None => {
+				self.modules.insert(specifier.clone(), hash);
+				self.did_change = true;
+
+				Ok(())
+			}
+		}
+	}
+
+	/// Write the lockfile, if any modules were recorded since it was loaded
+	pub async fn save(&self) -> Result<()> {
+		if !self.did_change {
+			return Ok(());
+		}
+
+		let content = LockfileContent {
+			version: LOCKFILE_VERSION,
+			modules: self.modules.clone(),
+		};
+
+		write(&self.path, format!("{}\n", to_string_pretty(&content)?))
+			.await
+			.with_context(|| format!("failed to write the lockfile at {:?}", self.path))?;
+
+		info!("Updated {:?}", self.path);
+
+		Ok(())
+	}
+}
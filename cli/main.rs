@@ -1,31 +1,44 @@
+mod asset_index;
 mod asset_loader;
+mod asset_pipeline;
 mod build;
 mod bundle;
 mod collect;
 mod convert;
 mod diagnostic;
 mod engine;
+mod engine_proxy;
+mod engine_watcher;
+mod gen_json_schema;
+mod gen_python;
 mod gen_rust;
+mod gen_typescript;
 mod inspect;
+mod lockfile;
+mod module_cache;
 mod module_loader;
 mod platform;
-mod tcp_watcher;
+mod source_watcher;
 mod web;
 mod writer;
 
 use anstyle::{AnsiColor, Color as AnsColor, Style};
 use anyhow::{Context, Result};
-use build::BuildOptions;
+use asset_index::{index_assets, IndexAssetsParams};
+use build::{check, BuildOptions};
+use bundle::SourceMapKind;
 use clap::{builder::Styles, Parser, Subcommand};
 use colored::{Color, Colorize};
+use diagnostic::{DiagnosticList, MessageFormat};
 use engine::Engine;
 use env_logger::Env;
-use log::{error, Level};
-use platform::{BuildParams, Platform, RunParams};
+use log::{info, Level};
+use module_loader::{cache_modules, ModuleOptions};
+use platform::{BuildParams, Platform, RunParams, ServeParams};
 use std::{
 	env::{self, current_dir},
 	io::Write,
-	path::PathBuf,
+	path::{Path, PathBuf},
 	process::exit,
 };
 use tokio::runtime::Builder;
@@ -49,21 +62,37 @@ struct Command {
 	#[arg(long, default_value_t = Default::default())]
 	engine: Engine,
 
-	/// The path that engine bindings should be written to.
+	/// The path that engine bindings should be written to. Required by `run` and `build`.
 	#[arg(long)]
-	bindings_path: PathBuf,
+	bindings_path: Option<PathBuf>,
 
-	/// The url that the engine will be running at. Can be a websocket or http url.
+	/// The url that the engine will be running at. Can be a websocket or http url. Required by `run` and `build`.
 	#[arg(long)]
-	engine_url: Url,
+	engine_url: Option<Url>,
+
+	/// How diagnostics should be reported. `json` is intended for editors and CI.
+	#[arg(long, default_value_t = Default::default())]
+	message_format: MessageFormat,
+
+	/// The lockfile that the hash of every remote module is recorded in, and verified against.
+	#[arg(long, default_value = "objection.lock")]
+	lockfile: PathBuf,
+
+	/// Never fetch remote modules, and fail if any remote module is not already recorded in the lockfile. Intended for CI and offline builds.
+	#[arg(long)]
+	frozen: bool,
+
+	/// Cache remote modules in this directory instead of the global cache. Populated by `vendor`, and required by it.
+	#[arg(long)]
+	vendor_dir: Option<PathBuf>,
+
+	/// An import map that is used to resolve the runtime's imports.
+	#[arg(long)]
+	import_map: Option<PathBuf>,
 
 	/// The type of operation to run
 	#[command(subcommand)]
 	operation: Operation,
-
-	/// The deno script to use for bundling the runtime. Primarily useful if one wants to test a modified version of the default bundler.
-	#[arg(long, default_value_t = Url::parse(&format!("https://raw.githubusercontent.com/radical-ui/objection/blob/{VERSION}/bundle/mod.ts")).unwrap())]
-	bundler: Url,
 }
 
 #[derive(Subcommand, Debug, Clone)]
@@ -71,7 +100,8 @@ enum Operation {
 	/// Run the application using the configured runtime (see --runtime) and platform (see --platform). Engine is expected to be
 	/// already running at the configured engine url (see --engine-url)
 	///
-	/// If the engine is restarted, the generated client will hot-reload with the changes.
+	/// If the engine is restarted, the generated client will hot-reload with the changes. If any of the runtime's local modules change, the
+	/// runtime will be rebuilt, the bindings rewritten, and the generated client reloaded.
 	Run {
 		/// What port to use for when running the web and web-ssr platforms
 		#[arg(long, default_value_t = 3000)]
@@ -80,6 +110,16 @@ enum Operation {
 		/// Do not hot-reload the generated client if the engine is restarted.
 		#[arg(long)]
 		no_reload: bool,
+
+		/// Do not rebuild the runtime if one of it's local modules changes.
+		#[arg(long)]
+		no_watch: bool,
+
+		/// Proxy the engine at this path of the web server (such as `/ui`), and have the client connect to the engine through it. Requests
+		/// are then same-origin, so the engine doesn't need to allow cross-origin requests, and it's cookies behave as they would when
+		/// served from the same domain in production.
+		#[arg(long)]
+		proxy_engine: Option<String>,
 	},
 	/// Build the configured runtime (see --runtime) for the configured platform (see --platform), which, when run, will access the
 	/// engine at the configured engine url (see --engine-url). Code will be written to the configured output dir (see --out-dir).
@@ -87,6 +127,69 @@ enum Operation {
 		/// The directory to where the generated client code will be written
 		#[arg(long, default_value_t = String::from("target/objection_build"))]
 		out_dir: String,
+
+		/// Where to put the bundle's source map. `external` writes it to `bundle.js.map`, next to the bundle.
+		#[arg(long, value_enum, default_value_t = Default::default())]
+		source_map: SourceMapKind,
+
+		/// Build for deployment. The bundle and assets are fingerprinted with the hash of their content so that they can be cached forever,
+		/// precompressed `.gz` and `.br` siblings are written next to each file, and `asset-manifest.json` maps each original path to it's
+		/// fingerprinted path.
+		#[arg(long)]
+		production: bool,
+	},
+	/// Serve a build of the configured platform (see --platform) from it's output dir. Fingerprinted files from a production build are served
+	/// with immutable cache headers, and precompressed files are sent to clients that accept them.
+	Serve {
+		/// The directory that the build was written to
+		#[arg(long, default_value_t = String::from("target/objection_build"))]
+		out_dir: String,
+
+		/// What port to serve the build on
+		#[arg(long, default_value_t = 3000)]
+		web_port: u16,
+	},
+	/// Load, mount, and validate the configured runtime (see --runtime) without bundling it or writing any bindings. Exits with a non-zero
+	/// status if any errors were found.
+	Check,
+	/// Download the configured runtime (see --runtime), along with all of it's remote modules, into the vendor directory (see --vendor-dir),
+	/// recording them in the lockfile (see --lockfile). Other operations can then be run offline by passing the same vendor directory along
+	/// with --frozen.
+	Vendor,
+	/// Work with asset indexes, which are what `@assets` tags point to
+	Assets {
+		#[command(subcommand)]
+		operation: AssetsOperation,
+	},
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum AssetsOperation {
+	/// Write an asset index of every file in a directory, hashing each one. If the index already exists, the entries for the directory are
+	/// updated, keeping their order, and any other entries, such as remote assets, are left alone.
+	Index {
+		/// The directory to index. Must be inside of the index's directory, because the index's local paths are relative to it.
+		directory: PathBuf,
+
+		/// The asset index to write
+		#[arg(long)]
+		index: PathBuf,
+
+		/// Only index files that match this glob, relative to the directory. Can be passed more than once.
+		#[arg(long)]
+		include: Vec<String>,
+
+		/// Don't index files that match this glob, relative to the directory. Can be passed more than once.
+		#[arg(long)]
+		exclude: Vec<String>,
+
+		/// Prepended to each file's path relative to the directory to get it's web path
+		#[arg(long, default_value_t = String::new())]
+		web_prefix: String,
+
+		/// Don't write the index, but fail if it is not up to date, reporting every difference
+		#[arg(long)]
+		check: bool,
 	},
 }
 
@@ -110,51 +213,126 @@ fn main() {
 		.try_init()
 		.unwrap();
 
+	let args = Command::parse();
+	let message_format = args.message_format;
+
 	Builder::new_current_thread().enable_all().build().unwrap().block_on(async {
-		match main_async().await {
+		match main_async(args).await {
 			Ok(_) => (),
 			Err(err) => {
-				error!("{:?}", err);
+				message_format.print_error(&err);
 				exit(1);
 			}
 		}
 	});
 }
 
-async fn main_async() -> Result<()> {
-	let args = Command::parse();
+async fn main_async(args: Command) -> Result<()> {
+	let home = PathBuf::from(env::var("HOME").context("Failed to find the HOME env variable")?).join(".cache/objection");
+	let module_options = ModuleOptions {
+		lockfile_path: &args.lockfile,
+		frozen: args.frozen,
+		vendor_dir: args.vendor_dir.as_deref(),
+		cache_dir: &home,
+		import_map_path: args.import_map.as_deref(),
+	};
+
+	if let Operation::Check = args.operation {
+		check(&mut DiagnosticList::new(args.message_format), &args.runtime, args.engine, module_options).await?;
+
+		return Ok(());
+	}
+
+	if let Operation::Assets {
+		operation: AssetsOperation::Index {
+			directory,
+			index,
+			include,
+			exclude,
+			web_prefix,
+			check,
+		},
+	} = &args.operation
+	{
+		let params = IndexAssetsParams {
+			directory,
+			index_path: index,
+			include,
+			exclude,
+			web_prefix,
+			check: *check,
+		};
+
+		return index_assets(params, &mut DiagnosticList::new(args.message_format)).await;
+	}
+
+	if let Operation::Vendor = args.operation {
+		let vendor_dir = module_options.vendor_dir.context("--vendor-dir is required for this operation")?;
+		let mut diagnostic_list = DiagnosticList::new(args.message_format);
+
+		cache_modules(&args.runtime, module_options, &mut diagnostic_list).await?;
+		info!("Vendored the runtime into {vendor_dir:?}");
+
+		return Ok(());
+	}
+
+	if let Operation::Serve { out_dir, web_port } = &args.operation {
+		return args
+			.platform
+			.serve(ServeParams {
+				directory: Path::new(out_dir),
+				web_port: *web_port,
+			})
+			.await;
+	}
+
+	let engine_url = args.engine_url.context("--engine-url is required for this operation")?;
+	let bindings_path = args.bindings_path.context("--bindings-path is required for this operation")?;
 	let build_options = BuildOptions {
-		bundler: &args.bundler,
 		runtime: &args.runtime,
-		engine_url: &args.engine_url,
+		engine_url: &engine_url,
 		engine: args.engine,
+		message_format: args.message_format,
+		module_options,
+		source_map: match args.operation {
+			Operation::Build { source_map, .. } => source_map,
+			_ => SourceMapKind::Inline,
+		},
 	};
-	let bindings_writer = Writer::new(current_dir().context("failed to get the current working directory")?).into_file_writer(args.bindings_path);
-	let home = PathBuf::from(env::var("HOME").context("Failed to find the HOME env variable")?).join(".cache/objection");
-	let cache_writer = Writer::new(home);
+	let bindings_writer = Writer::new(current_dir().context("failed to get the current working directory")?).into_file_writer(bindings_path);
+	let cache_writer = Writer::new(&home);
 
 	match args.operation {
-		Operation::Run { web_port, no_reload } => {
+		Operation::Run {
+			web_port,
+			no_reload,
+			no_watch,
+			proxy_engine,
+		} => {
 			args.platform
 				.run(RunParams {
 					build_options,
 					web_port,
 					reload: !no_reload,
+					watch: !no_watch,
+					engine_proxy_path: proxy_engine.as_deref(),
 					bindings_writer: &bindings_writer,
 					cache_writer: &cache_writer,
 				})
 				.await
 		}
-		Operation::Build { out_dir } => {
+		Operation::Build { out_dir, production, .. } => {
 			args.platform
 				.build(BuildParams {
 					build_options,
 					bindings_writer: &bindings_writer,
 					output_writer: &Writer::new(out_dir),
 					cache_writer: &cache_writer,
+					production,
 				})
 				.await
 		}
+		Operation::Check | Operation::Vendor | Operation::Serve { .. } | Operation::Assets { .. } => unreachable!(),
 	}
 }
 
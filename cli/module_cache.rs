@@ -0,0 +1,149 @@
+use anyhow::{anyhow, bail, Context, Result};
+use deno_graph::source::{LoadFuture, LoadOptions, LoadResponse, Loader};
+use log::info;
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::{from_slice, to_vec};
+use sha2::{Digest, Sha256};
+use std::{collections::HashMap, io::ErrorKind, path::PathBuf, sync::Arc};
+use tokio::fs::{create_dir_all, read, write};
+use url::Url;
+
+/// What is known about a cached module, besides it's content
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedModuleInfo {
+	/// The url that the module was served from, which is different from the requested url if there were redirects
+	url: Url,
+	/// The response headers, with lowercase names
+	headers: HashMap<String, String>,
+}
+
+/// A file-backed cache of remote modules. This is the loader that deno_graph loads modules through, so remote modules are fetched the first
+/// time that they are loaded, and read from the cache after that. Local modules are always read from disk.
+///
+/// Each module is stored as two files, named by the hash of it's url: the content, and a `.json` file of `CachedModuleInfo`.
+#[derive(Debug, Clone)]
+pub struct ModuleCache {
+	directory: PathBuf,
+	cached_only: bool,
+	client: Client,
+}
+
+impl ModuleCache {
+	/// If `cached_only` is set, modules that are not already cached are an error, rather than being fetched.
+	pub fn new(directory: impl Into<PathBuf>, cached_only: bool) -> ModuleCache {
+		ModuleCache {
+			directory: directory.into(),
+			cached_only,
+			client: Client::new(),
+		}
+	}
+
+	/// Read the content of a module, fetching and caching it first if it is remote. This is for files that are not part of the module graph,
+	/// such as asset indexes.
+	pub async fn read(&self, specifier: &Url) -> Result<Vec<u8>> {
+		match self.load_module(specifier.clone()).await? {
+			Some(LoadResponse::Module { content, .. }) => Ok(content.to_vec()),
+			Some(_) => bail!("{specifier} did not resolve to a file"),
+			None => bail!("{specifier} does not exist"),
+		}
+	}
+
+	async fn load_module(&self, specifier: Url) -> Result<Option<LoadResponse>> {
+		match specifier.scheme() {
+			"file" => {
+				let path = specifier.to_file_path().map_err(|_| anyhow!("{specifier} is not a valid file path"))?;
+
+				match read(&path).await {
+					Ok(content) => Ok(Some(LoadResponse::Module {
+						content: Arc::from(content),
+						specifier,
+						maybe_headers: None,
+					})),
+					Err(error) if error.kind() == ErrorKind::NotFound => Ok(None),
+					Err(error) => Err(error).with_context(|| format!("failed to read {path:?}")),
+				}
+			}
+			"http" | "https" => self.load_remote_module(specifier).await,
+			scheme => bail!("cannot load {specifier}, because '{scheme}' urls are not supported"),
+		}
+	}
+
+	async fn load_remote_module(&self, specifier: Url) -> Result<Option<LoadResponse>> {
+		let (content_path, info_path) = self.get_paths(&specifier);
+
+		if let (Ok(content), Ok(info)) = (read(&content_path).await, read(&info_path).await) {
+			let info = from_slice::<CachedModuleInfo>(&info).with_context(|| format!("failed to parse {info_path:?}; the cache may be corrupt"))?;
+
+			return Ok(Some(LoadResponse::Module {
+				content: Arc::from(content),
+				specifier: info.url,
+				maybe_headers: Some(info.headers),
+			}));
+		}
+
+		if self.cached_only {
+			bail!("{specifier} is not cached, and can't be fetched because `--frozen` was passed");
+		}
+
+		let response = self
+			.client
+			.get(specifier.clone())
+			.send()
+			.await
+			.with_context(|| format!("failed to fetch {specifier}"))?;
+
+		if response.status() == StatusCode::NOT_FOUND {
+			return Ok(None);
+		}
+
+		if !response.status().is_success() {
+			bail!("failed to fetch {specifier}; server responded with {}", response.status());
+		}
+
+		let info = CachedModuleInfo {
+			url: response.url().clone(),
+			headers: response
+				.headers()
+				.iter()
+				.filter_map(|(name, value)| Some((name.as_str().to_lowercase(), value.to_str().ok()?.to_string())))
+				.collect(),
+		};
+		let content = response.bytes().await.with_context(|| format!("failed to download {specifier}"))?;
+
+		create_dir_all(&self.directory)
+			.await
+			.with_context(|| format!("failed to create the module cache at {:?}", self.directory))?;
+		write(&content_path, &content)
+			.await
+			.with_context(|| format!("failed to write {content_path:?}"))?;
+		write(&info_path, to_vec(&info)?)
+			.await
+			.with_context(|| format!("failed to write {info_path:?}"))?;
+
+		info!("Downloaded {specifier}");
+
+		Ok(Some(LoadResponse::Module {
+			content: Arc::from(content.to_vec()),
+			specifier: info.url,
+			maybe_headers: Some(info.headers),
+		}))
+	}
+
+	fn get_paths(&self, specifier: &Url) -> (PathBuf, PathBuf) {
+		let mut hasher = Sha256::new();
+		hasher.update(specifier.as_str().as_bytes());
+		let name = hex::encode(hasher.finalize());
+
+		(self.directory.join(&name), self.directory.join(format!("{name}.json")))
+	}
+}
+
+impl Loader for ModuleCache {
+	fn load(&self, specifier: &Url, _options: LoadOptions) -> LoadFuture {
+		let cache = self.clone();
+		let specifier = specifier.clone();
+
+		Box::pin(async move { cache.load_module(specifier).await })
+	}
+}
@@ -1,143 +1,298 @@
-use anyhow::{anyhow, Context, Result};
-use deno_graph::source::{MemoryLoader, Source};
-use serde::{Deserialize, Serialize};
-use serde_json::from_slice;
-use std::{env, path::PathBuf, process::Stdio};
-use tokio::{fs::read_to_string, process::Command};
+use anyhow::{anyhow, bail, Context, Result};
+use deno_ast::{EmitOptions, MediaType, ParseParams, SourceMapOption, TranspileOptions};
+use deno_graph::{
+	source::{MemoryLoader, ResolutionMode, ResolveError, Resolver, Source},
+	BuildOptions, GraphKind, Module, ModuleGraph, Range,
+};
+use import_map::{parse_from_json, ImportMap};
+use log::warn;
+use sha2::{Digest, Sha256};
+use std::{
+	path::{Path, PathBuf},
+	sync::Arc,
+};
+use tokio::fs::{canonicalize, create_dir_all, read_to_string, try_exists, write};
 use url::Url;
 
-use crate::bundle::Bundler;
+use crate::{
+	bundle::Bundler,
+	diagnostic::{Diagnostic, DiagnosticList},
+	lockfile::Lockfile,
+	module_cache::ModuleCache,
+};
 
-pub async fn load_modules(entry_url: &Url, memory_loader: &mut MemoryLoader, bundler: &mut Bundler) -> Result<()> {
-	cache_graph(entry_url).await?;
+/// How remote modules are fetched and verified
+#[derive(Debug, Clone, Copy)]
+pub struct ModuleOptions<'a> {
+	/// The lockfile that remote modules are verified against
+	pub lockfile_path: &'a Path,
+	/// Never fetch remote modules, and fail if a remote module is not already in the lockfile
+	pub frozen: bool,
+	/// If set, remote modules are cached in this directory, rather than in `cache_dir`
+	pub vendor_dir: Option<&'a Path>,
+	/// Where fetched modules and transpiled sources are cached
+	pub cache_dir: &'a Path,
+	/// An import map that is used to resolve the runtime's imports
+	pub import_map_path: Option<&'a Path>,
+}
 
-	let info_graph = InfoGraph::load(entry_url).await?;
+impl ModuleOptions<'_> {
+	pub fn get_module_cache(&self) -> ModuleCache {
+		ModuleCache::new(self.vendor_dir.unwrap_or(self.cache_dir).join("modules"), self.frozen)
+	}
+}
 
-	for module in info_graph.modules {
-		if let Some(error) = module.error {
-			return Err(anyhow!(error).context(format!("Failed to load {}", module.specifier)));
-		}
+/// Resolves imports through an import map
+#[derive(Debug)]
+struct ImportMapResolver(ImportMap);
+
+impl ImportMapResolver {
+	async fn load(path: &Path) -> Result<ImportMapResolver> {
+		let path = canonicalize(path).await.with_context(|| format!("failed to find the import map at {path:?}"))?;
+		let text = read_to_string(&path)
+			.await
+			.with_context(|| format!("failed to read the import map at {path:?}"))?;
+		let base_url = Url::from_file_path(&path).map_err(|_| anyhow!("{path:?} is not a valid import map path"))?;
+
+		let result = parse_from_json(&base_url, &text).with_context(|| format!("failed to parse the import map at {path:?}"))?;
 
-		for dependency in module.dependencies {
-			bundler.register_dependency(&module.specifier, dependency.specifier, dependency.resolution.specifier);
+		for diagnostic in result.diagnostics {
+			warn!("{diagnostic} (in the import map at {path:?})");
 		}
 
-		let local = module.local.ok_or(anyhow!("Expected there to be a local path because there was no error"))?;
+		Ok(ImportMapResolver(result.import_map))
+	}
+}
+
+impl Resolver for ImportMapResolver {
+	fn resolve(&self, specifier_text: &str, referrer_range: &Range, _mode: ResolutionMode) -> Result<Url, ResolveError> {
+		self.0
+			.resolve(specifier_text, &referrer_range.specifier)
+			.map_err(|error| ResolveError::Other(error.into()))
+	}
+}
 
-		bundler.register_source_file(module.specifier.clone(), module.emit.as_ref().unwrap_or(&local));
+/// Load the module graph of `entry_url` into `memory_loader` and `bundler`, returning the paths of every module that is a local file. Remote
+/// modules are fetched into the module cache, and verified against the lockfile.
+pub async fn load_modules(
+	entry_url: &Url,
+	options: ModuleOptions<'_>,
+	memory_loader: &mut MemoryLoader,
+	bundler: &mut Bundler,
+	diagnostic_list: &mut DiagnosticList,
+) -> Result<Vec<PathBuf>> {
+	let graph = load_graph(entry_url, options, diagnostic_list).await?;
+	let mut local_modules = Vec::new();
 
-		let content = read_to_string(&local)
-			.await
-			.with_context(|| format!("tried to read '{}', the local path for {}", local.to_string_lossy(), module.specifier))?;
+	for module in graph.modules() {
+		let (source, media_type) = match module {
+			Module::Js(module) => {
+				for (dependency_specifier, dependency) in &module.dependencies {
+					if let Some(resolved) = dependency.get_code() {
+						bundler.register_dependency(&module.specifier, dependency_specifier, graph.resolve(resolved).clone());
+					}
+				}
 
-		let specifier_string = module.specifier.to_string();
+				(&module.source, module.media_type)
+			}
+			Module::Json(module) => (&module.source, module.media_type),
+			module => bail!("{} is not a javascript, typescript, or json module, which is not supported", module.specifier()),
+		};
 
+		let specifier = module.specifier();
+
+		if media_type != MediaType::Dts {
+			let emitted = emit(specifier, media_type, source, options.cache_dir).await?;
+
+			if let Some(source_map_path) = emitted.source_map_path {
+				bundler.register_source_map(specifier.clone(), source_map_path);
+			}
+
+			bundler.register_source_file(specifier.clone(), emitted.path);
+		}
+
+		if specifier.scheme() == "file" {
+			local_modules.push(specifier.to_file_path().map_err(|_| anyhow!("{specifier} is not a valid file path"))?);
+		}
+
+		diagnostic_list.register_source(specifier, source.clone());
 		memory_loader.add_source(
-			module.specifier,
+			specifier.clone(),
 			Source::Module {
-				specifier: specifier_string,
+				specifier: specifier.to_string(),
 				maybe_headers: None,
-				content,
+				content: source.to_string(),
 			},
 		);
 	}
 
-	Ok(())
+	Ok(local_modules)
 }
 
-/// A resolved module dependency
-#[derive(Debug, Serialize, Deserialize)]
-pub struct InfoGraphModuleDependencyResolution {
-	/// The url of the
-	pub specifier: Url,
+/// Fetch the module graph of `entry_url` into the module cache, verifying it against the lockfile, without loading it anywhere.
+pub async fn cache_modules(entry_url: &Url, options: ModuleOptions<'_>, diagnostic_list: &mut DiagnosticList) -> Result<()> {
+	load_graph(entry_url, options, diagnostic_list).await?;
+
+	Ok(())
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct InfoGraphModuleDependency {
-	/// The raw specifier that the user used to refer to this particular dependency
-	pub specifier: String,
+async fn load_graph(entry_url: &Url, options: ModuleOptions<'_>, diagnostic_list: &mut DiagnosticList) -> Result<ModuleGraph> {
+	let loader = options.get_module_cache();
+	let resolver = match options.import_map_path {
+		Some(path) => Some(ImportMapResolver::load(path).await?),
+		None => None,
+	};
 
-	/// The resolved dependency
-	#[serde(rename = "code")]
-	pub resolution: InfoGraphModuleDependencyResolution,
-}
+	let mut graph = ModuleGraph::new(GraphKind::All);
+	let diagnostics = graph
+		.build(
+			Vec::from([entry_url.clone()]),
+			&loader,
+			BuildOptions {
+				resolver: resolver.as_ref().map(|resolver| resolver as &dyn Resolver),
+				..Default::default()
+			},
+		)
+		.await;
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct InfoGraphModule {
-	/// The full specifier of this module
-	pub specifier: Url,
+	for diagnostic in diagnostics {
+		let builder = Diagnostic::start(&diagnostic);
 
-	/// The local path to this module's source code. It will be a path to the cache folder if `specifier` is not a local url. Would only be `None`
-	/// if there is an `error`
-	#[serde(default)]
-	pub local: Option<PathBuf>,
+		diagnostic_list.add_warning(match &diagnostic.maybe_range {
+			Some(range) => builder.shift().location(range).build(),
+			None => builder.build(),
+		});
+	}
 
-	/// The error that occurred while evaluating this module
-	#[serde(default)]
-	pub error: Option<String>,
+	if let Err(error) = graph.valid() {
+		let builder = Diagnostic::start("Failed to load the module graph of ")
+			.inline_code(entry_url)
+			.text(": ")
+			.text(&error);
 
-	/// The local path to this module's transpiled code. May be unspecified if the source does not need to be transpiled (it's not typescript or jsx),
-	/// or if the module has not been cached
-	#[serde(default)]
-	pub emit: Option<PathBuf>,
+		diagnostic_list.add(match error.maybe_range() {
+			Some(range) => builder.shift().location(range).build(),
+			None => builder.build(),
+		});
+	}
+
+	diagnostic_list.flush("load modules")?;
+
+	let mut lockfile = Lockfile::load(options.lockfile_path, options.frozen).await?;
+
+	for module in graph.modules() {
+		let source = match module {
+			Module::Js(module) => &module.source,
+			Module::Json(module) => &module.source,
+			_ => continue,
+		};
+
+		if let Err(error) = lockfile.verify(module.specifier(), source.as_bytes()) {
+			diagnostic_list.add_error(error);
+		}
+	}
 
-	/// The modules that this module imports
-	#[serde(default)]
-	pub dependencies: Vec<InfoGraphModuleDependency>,
+	diagnostic_list.flush("verify modules")?;
+	lockfile.save().await?;
+
+	Ok(graph)
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct InfoGraph {
-	pub roots: Vec<Url>,
-	pub modules: Vec<InfoGraphModule>,
+/// The javascript of a module, as emitted for the bundler
+struct EmittedModule {
+	path: PathBuf,
+	/// Maps the emitted javascript back to the original source. Only set for modules that were transpiled
+	source_map_path: Option<PathBuf>,
 }
 
-impl InfoGraph {
-	pub async fn load(entry_url: &Url) -> Result<InfoGraph> {
-		let mut command = Command::new("deno");
-
-		command
-			.arg("info")
-			.arg("--json")
-			.arg(entry_url.as_str())
-			.env_clear()
-			.env("PATH", env::var("PATH").unwrap())
-			.stdout(Stdio::piped())
-			.stderr(Stdio::inherit())
-			.stdin(Stdio::null());
-
-		let process = command.spawn()?;
-		let output = process.wait_with_output().await?;
-
-		if !output.status.success() {
-			return Err(anyhow!(
-				"failed to get info on module graph due to `deno info` exiting with a non-zero exit code"
-			));
-		}
+/// Get a path to the javascript of a module, for the bundler to read. Typescript and jsx are transpiled into the emit cache, and json is
+/// converted into a module with a default export. Remote modules are always written to the emit cache, because the module cache is not
+/// meant to be read directly.
+async fn emit(specifier: &Url, media_type: MediaType, source: &Arc<str>, cache_dir: &Path) -> Result<EmittedModule> {
+	let needs_transpile = matches!(
+		media_type,
+		MediaType::TypeScript | MediaType::Mts | MediaType::Cts | MediaType::Jsx | MediaType::Tsx
+	);
 
-		Ok(from_slice(&output.stdout)
-			.context("failed to deserialize the json output of `deno info`; this is probably caused by a regression in deno itself")?)
+	if !needs_transpile && media_type != MediaType::Json && specifier.scheme() == "file" {
+		return Ok(EmittedModule {
+			path: specifier.to_file_path().map_err(|_| anyhow!("{specifier} is not a valid file path"))?,
+			source_map_path: None,
+		});
 	}
-}
 
-async fn cache_graph(entry_url: &Url) -> Result<()> {
-	let mut command = Command::new("deno");
+	let mut hasher = Sha256::new();
+	hasher.update(specifier.as_str().as_bytes());
+	hasher.update(source.as_bytes());
 
-	command
-		.arg("cache")
-		.arg(entry_url.as_str())
-		.env_clear()
-		.env("PATH", env::var("PATH").unwrap())
-		.stdout(Stdio::inherit())
-		.stdout(Stdio::inherit())
-		.stdin(Stdio::null());
+	let directory = cache_dir.join("emit");
+	let name = hex::encode(hasher.finalize());
+	let path = directory.join(format!("{name}.js"));
+	let source_map_path = needs_transpile.then(|| directory.join(format!("{name}.js.map")));
 
-	let mut process = command.spawn()?;
-	let status = process.wait().await?;
+	let is_cached = try_exists(&path).await.unwrap_or(false)
+		&& match &source_map_path {
+			Some(source_map_path) => try_exists(source_map_path).await.unwrap_or(false),
+			None => true,
+		};
 
-	if !status.success() {
-		return Err(anyhow!("failed to cache module graph due to `deno cache` exiting with a non-zero exit code"));
+	if is_cached {
+		return Ok(EmittedModule { path, source_map_path });
 	}
 
-	Ok(())
+	let (code, source_map) = match media_type {
+		MediaType::Json => (format!("export default {source};"), None),
+		_ if needs_transpile => {
+			let (code, source_map) = transpile(specifier, media_type, source)?;
+
+			(code, Some(source_map))
+		}
+		_ => (source.to_string(), None),
+	};
+
+	create_dir_all(&directory)
+		.await
+		.with_context(|| format!("failed to create the emit cache at {directory:?}"))?;
+
+	if let (Some(source_map_path), Some(source_map)) = (&source_map_path, source_map) {
+		write(source_map_path, source_map)
+			.await
+			.with_context(|| format!("failed to write {source_map_path:?}"))?;
+	}
+
+	write(&path, code).await.with_context(|| format!("failed to write {path:?}"))?;
+
+	Ok(EmittedModule { path, source_map_path })
+}
+
+/// Transpile a typescript or jsx module into javascript, returning the code along with a source map that points back into the original source
+fn transpile(specifier: &Url, media_type: MediaType, source: &Arc<str>) -> Result<(String, String)> {
+	let parsed = deno_ast::parse_module(ParseParams {
+		specifier: specifier.clone(),
+		text: source.clone(),
+		media_type,
+		capture_tokens: false,
+		scope_analysis: false,
+		maybe_syntax: None,
+	})
+	.with_context(|| format!("failed to parse {specifier}"))?;
+
+	let transpiled = parsed
+		.transpile(
+			&TranspileOptions::default(),
+			// the bundler chains this map into the bundle's source map
+			&EmitOptions {
+				source_map: SourceMapOption::Separate,
+				inline_sources: true,
+				..Default::default()
+			},
+		)
+		.with_context(|| format!("failed to transpile {specifier}"))?
+		.into_source();
+
+	let source_map = transpiled
+		.source_map
+		.ok_or_else(|| anyhow!("expected a source map to be emitted when transpiling {specifier}"))?;
+
+	Ok((transpiled.text, source_map))
 }
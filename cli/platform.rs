@@ -1,9 +1,10 @@
-use anyhow::Result;
+use anyhow::{bail, Result};
 use clap::ValueEnum;
+use std::path::Path;
 
 use crate::{
 	build::BuildOptions,
-	web::{build_web_static, run_web_static, BuildWebStaticParams, RunWebStaticParams},
+	web::{build_web_static, run_web_static, serve_web_static, BuildWebStaticParams, RunWebStaticParams, ServeWebStaticParams},
 	writer::{FileWriter, Writer},
 };
 
@@ -12,6 +13,9 @@ pub struct RunParams<'a> {
 	pub build_options: BuildOptions<'a>,
 	pub web_port: u16,
 	pub reload: bool,
+	pub watch: bool,
+	/// If set, the engine is proxied at this path of the web server, and the client is pointed at it instead of the engine url
+	pub engine_proxy_path: Option<&'a str>,
 	pub bindings_writer: &'a FileWriter,
 	pub cache_writer: &'a Writer,
 }
@@ -22,6 +26,14 @@ pub struct BuildParams<'a> {
 	pub bindings_writer: &'a FileWriter,
 	pub output_writer: &'a Writer,
 	pub cache_writer: &'a Writer,
+	pub production: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ServeParams<'a> {
+	/// The output directory of a build
+	pub directory: &'a Path,
+	pub web_port: u16,
 }
 
 #[derive(Debug, ValueEnum, Clone, Default)]
@@ -51,6 +63,8 @@ impl Platform {
 					build_options: params.build_options,
 					web_port: params.web_port,
 					reload: params.reload,
+					watch: params.watch,
+					engine_proxy_path: params.engine_proxy_path,
 					bindings_writer: params.bindings_writer,
 					cache_writer: params.cache_writer,
 				})
@@ -67,6 +81,7 @@ impl Platform {
 					build_options: params.build_options,
 					bindings_writer: params.bindings_writer,
 					output_writer: params.output_writer,
+					production: params.production,
 				})
 				.await
 			}
@@ -75,4 +90,17 @@ impl Platform {
 			}
 		}
 	}
+
+	pub async fn serve(self, params: ServeParams<'_>) -> Result<()> {
+		match self {
+			Platform::WebStatic => {
+				serve_web_static(ServeWebStaticParams {
+					directory: params.directory,
+					web_port: params.web_port,
+				})
+				.await
+			}
+			Platform::WebSSR => bail!("serving is not supported for the {} platform", self.to_string()),
+		}
+	}
 }
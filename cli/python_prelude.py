@@ -0,0 +1,199 @@
+from __future__ import annotations
+
+import dataclasses
+import inspect
+import secrets
+from dataclasses import dataclass, field
+from typing import Any, Awaitable, Callable, Dict, Generic, List, Literal, Optional, Protocol, Tuple, TypeAlias, TypedDict, TypeVar, Union
+
+T = TypeVar("T")
+
+
+@dataclass
+class EventKey(Generic[T]):
+    """An event that could be triggered by the runtime, where `T` is the data that the event will contain"""
+
+    event_path: List[str] = field(metadata={"json": "eventPath"})
+    debug_symbol: Optional[str] = field(default=None, metadata={"json": "debugSymbol"})
+
+
+@dataclass
+class ActionKey(Generic[T]):
+    """An action that can be emitted to the runtime, where `T` is the data that the action will contain"""
+
+    action_path: List[str] = field(metadata={"json": "actionPath"})
+    debug_symbol: Optional[str] = field(default=None, metadata={"json": "debugSymbol"})
+
+
+def create_action_key(debug_symbol: Optional[str] = None) -> ActionKey[Any]:
+    """Create a new action key, which can be given to a component, and then emitted via `Client.emit`."""
+
+    return ActionKey(action_path=[str(secrets.randbits(64))], debug_symbol=debug_symbol)
+
+
+class IntoComponentIndex(Protocol):
+    """Anything that can be converted into the component index, which is generally a component."""
+
+    def into_index(self) -> COMPONENT_INDEX: ...
+
+
+def _into_component_index(component: Any) -> Any:
+    return component.into_index() if hasattr(component, "into_index") else component
+
+
+def _to_json(value: Any) -> Any:
+    """Convert generated classes into json values. Dataclass fields are renamed to their `json` metadata, if they have it."""
+
+    if dataclasses.is_dataclass(value) and not isinstance(value, type):
+        return {item.metadata.get("json", item.name): _to_json(getattr(value, item.name)) for item in dataclasses.fields(value)}
+    if isinstance(value, dict):
+        return {key: _to_json(item) for key, item in value.items()}
+    if isinstance(value, (list, tuple)):
+        return [_to_json(item) for item in value]
+
+    return value
+
+
+class _EventState:
+    def __init__(self, path: List[str], data: Any):
+        self.path = path
+        self.data = data
+        self.is_taken = False
+
+
+class Ui:
+    def __init__(self, scope: List[str]):
+        self._scope = scope
+
+    def event_key(self) -> EventKey[Any]:
+        """An event key for the current scope."""
+
+        return EventKey(event_path=list(self._scope))
+
+    def scope(self, symbol: str) -> Ui:
+        """A ui that creates event keys within `symbol`, so that events from different parts of the ui can be told apart. Symbols are opaque
+        to the runtime, so any string will do."""
+
+        return Ui([*self._scope, symbol])
+
+
+class Client:
+    def __init__(self, event: _EventState, actions: List[Any]):
+        self._event = event
+        self._actions = actions
+
+    def ui(self) -> Ui:
+        return Ui(["main"])
+
+    def take_data(self, key: EventKey[T]) -> T:
+        """Take the data of the incoming event, as decoded json. Raises if `key` is not the key of the incoming event, or if the data was
+        already taken. The event path should always be checked before taking the data."""
+
+        if key.event_path != self._event.path:
+            raise ValueError(
+                f"this event path is different from the incoming event path; this event path: {key.event_path}; incoming event path: {self._event.path}"
+            )
+
+        if self._event.is_taken:
+            raise ValueError("tried to take event data, but it was already taken; this is probably caused by calling take_data more than once for a single event")
+
+        self._event.is_taken = True
+
+        return self._event.data
+
+    def emit(self, key: ActionKey[T], data: T) -> None:
+        """Emit `data` to the runtime, through `key`."""
+
+        self._actions.append(_to_json({"key": key, "data": data}))
+
+
+class MountEventData(TypedDict):
+    token: Optional[str]
+
+
+@dataclass
+class UiResponse:
+    actions: List[Any]
+
+
+class RootUi:
+    def __init__(self, event: Dict[str, Any]):
+        self._event = _EventState(event["key"]["eventPath"], event["data"])
+        self._actions: List[Any] = []
+
+    def get_client(self) -> Client:
+        return Client(self._event, self._actions)
+
+    def take_mount_event(self) -> Optional[MountEventData]:
+        """If the incoming event is the runtime being mounted, take it's data."""
+
+        if not self._event.path:
+            raise ValueError("found an empty path when trying to check for a mount event, which is never valid")
+
+        if self._event.path[0] != "root_app_ready":
+            return None
+
+        if self._event.is_taken:
+            raise ValueError("event key stated that this is a mount event, but no event data was given, which is not valid")
+
+        self._event.is_taken = True
+
+        return self._event.data
+
+    def set_root_ui(self, ui: Union[COMPONENT_INDEX, IntoComponentIndex]) -> None:
+        self._actions.append({"key": {"actionPath": ["root_mount"]}, "data": _to_json(_into_component_index(ui))})
+
+    def into_response(self) -> UiResponse:
+        return UiResponse(self._actions)
+
+
+# A random id that is unique to this process. `objection run` polls for it to detect engine restarts.
+INSTANCE_ID = str(secrets.randbits(64))
+
+
+def _parse_request(body: Any) -> Tuple[str, List[Dict[str, Any]]]:
+    if not isinstance(body, dict):
+        raise ValueError("expected an object")
+    if not isinstance(body.get("sessionId"), str):
+        raise ValueError("expected `sessionId` to be a string")
+    if not isinstance(body.get("events"), list):
+        raise ValueError("expected `events` to be an array")
+
+    for event in body["events"]:
+        if not isinstance(event, dict) or not isinstance(event.get("key"), dict) or "data" not in event:
+            raise ValueError("expected every event to have a `key` object and `data`")
+
+        path = event["key"].get("eventPath")
+        if not isinstance(path, list) or not all(isinstance(symbol, str) for symbol in path):
+            raise ValueError("expected every event key to have an `eventPath` of strings")
+
+    return body["sessionId"], body["events"]
+
+
+async def handle_request(body: Any, handler: Callable[[str, RootUi], Union[UiResponse, Awaitable[UiResponse]]]) -> Any:
+    """Handle a request from the runtime, calling `handler` once for each event that it contains. `handler` can be a regular or an async
+    function. Returns the actions that should be sent back to the runtime as the json response body. This uses the same protocol as
+    `handle_request` in the objection crate, including it's heartbeat requests."""
+
+    # heartbeat requests are answered with the instance id, instead of a list of actions
+    if isinstance(body, dict) and body.get("heartbeat") is True:
+        return {"instanceId": INSTANCE_ID}
+
+    try:
+        session_id, events = _parse_request(body)
+    except ValueError as error:
+        return [{"key": {"actionPath": ["root_error"]}, "data": f"Invalid request body. {error}"}]
+
+    actions: List[Any] = []
+
+    for event in events:
+        try:
+            response = handler(session_id, RootUi(event))
+            if inspect.isawaitable(response):
+                response = await response
+
+            actions.extend(response.actions)
+        except Exception as error:
+            actions.append({"key": {"actionPath": ["root_error"]}, "data": str(error)})
+
+    return actions
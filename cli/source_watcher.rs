@@ -0,0 +1,107 @@
+use anyhow::{Context, Result};
+use log::{debug, warn};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use notify_debouncer_full::{new_debouncer, DebounceEventResult, Debouncer, FileIdMap};
+use std::{
+	collections::HashSet,
+	path::PathBuf,
+	sync::{Arc, Mutex},
+	time::Duration,
+};
+use tokio::sync::mpsc;
+
+/// How long to wait for more changes before reporting them, so that saving several files at once only causes a single rebuild
+const DEBOUNCE_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// Watches a set of local source files for changes.
+///
+/// The directories of the files are watched, rather than the files themselves, because many editors save by replacing the file, which
+/// would silently end a watch on the file itself.
+pub struct SourceWatcher {
+	debouncer: Debouncer<RecommendedWatcher, FileIdMap>,
+	files: Arc<Mutex<HashSet<PathBuf>>>,
+	directories: HashSet<PathBuf>,
+	receiver: mpsc::Receiver<Vec<PathBuf>>,
+}
+
+impl SourceWatcher {
+	pub fn new() -> Result<SourceWatcher> {
+		let (sender, receiver) = mpsc::channel(1);
+		let files = Arc::new(Mutex::new(HashSet::<PathBuf>::new()));
+		let watched_files = files.clone();
+
+		let debouncer = new_debouncer(DEBOUNCE_TIMEOUT, None, move |result: DebounceEventResult| {
+			let events = match result {
+				Ok(events) => events,
+				Err(errors) => {
+					for error in errors {
+						warn!("Error while watching sources: {error}");
+					}
+
+					return;
+				}
+			};
+
+			let watched_files = watched_files.lock().unwrap();
+			let mut changed = events
+				.into_iter()
+				.filter(|event| !event.kind.is_access())
+				.flat_map(|event| event.event.paths)
+				.filter(|path| watched_files.contains(path))
+				.collect::<Vec<_>>();
+
+			changed.sort();
+			changed.dedup();
+
+			// if a change is already waiting to be picked up, the rebuild that it causes will include this change as well
+			if !changed.is_empty() && sender.try_send(changed).is_err() {
+				debug!("dropping source change notification because a rebuild is already pending");
+			}
+		})
+		.context("failed to start watching for source changes")?;
+
+		Ok(SourceWatcher {
+			debouncer,
+			files,
+			directories: HashSet::new(),
+			receiver,
+		})
+	}
+
+	/// Watch exactly `files`, which replaces any previously watched files.
+	pub fn watch(&mut self, files: impl IntoIterator<Item = PathBuf>) -> Result<()> {
+		let files = files.into_iter().collect::<HashSet<_>>();
+		let directories = files
+			.iter()
+			.filter_map(|file| file.parent().map(|parent| parent.to_path_buf()))
+			.collect::<HashSet<_>>();
+
+		for directory in self.directories.difference(&directories) {
+			if let Err(error) = self.debouncer.watcher().unwatch(directory) {
+				debug!("failed to unwatch {directory:?}: {error}");
+			}
+
+			self.debouncer.cache().remove_root(directory);
+		}
+
+		for directory in directories.difference(&self.directories) {
+			self.debouncer
+				.watcher()
+				.watch(directory, RecursiveMode::NonRecursive)
+				.with_context(|| format!("failed to watch {directory:?}"))?;
+			self.debouncer.cache().add_root(directory, RecursiveMode::NonRecursive);
+		}
+
+		debug!("watching {} source files in {} directories", files.len(), directories.len());
+
+		*self.files.lock().unwrap() = files;
+		self.directories = directories;
+
+		Ok(())
+	}
+
+	/// Wait for some of the watched files to change, returning the paths of those that did.
+	pub async fn next_change(&mut self) -> Option<Vec<PathBuf>> {
+		self.receiver.recv().await
+	}
+}
@@ -0,0 +1,232 @@
+/**
+ * An event that could be triggered by the runtime, where `T` is the data that the event will contain
+ */
+export interface EventKey<T> {
+	eventPath: string[]
+	debugSymbol?: string | null
+	/** Never set. Keeps keys with different data types from being mixed up */
+	__data?: T
+}
+
+/**
+ * An action that can be emitted to the runtime, where `T` is the data that the action will contain
+ */
+export interface ActionKey<T> {
+	actionPath: string[]
+	debugSymbol?: string | null
+	/** Never set. Keeps keys with different data types from being mixed up */
+	__data?: T
+}
+
+/**
+ * Create a new action key, which can be given to a component, and then emitted via `Client.emit`.
+ */
+export function createActionKey<T>(debugSymbol?: string): ActionKey<T> {
+	return { actionPath: [crypto.randomUUID()], debugSymbol: debugSymbol ?? null }
+}
+
+/**
+ * Anything that can be converted into the component index, which is generally a component.
+ */
+export interface IntoComponentIndex {
+	intoIndex(): COMPONENT_INDEX
+}
+
+function intoComponentIndex(component: COMPONENT_INDEX | IntoComponentIndex): COMPONENT_INDEX {
+	return 'intoIndex' in component ? component.intoIndex() : component
+}
+
+interface EventState {
+	path: string[]
+	data: unknown
+	isTaken: boolean
+}
+
+export class Ui {
+	#scope: string[]
+
+	constructor(scope: string[]) {
+		this.#scope = scope
+	}
+
+	/**
+	 * An event key for the current scope.
+	 */
+	eventKey<T>(): EventKey<T> {
+		return { eventPath: [...this.#scope], debugSymbol: null }
+	}
+
+	/**
+	 * A ui that creates event keys within `symbol`, so that events from different parts of the ui can be told apart. Symbols are opaque to the
+	 * runtime, so any string will do.
+	 */
+	scope(symbol: string): Ui {
+		return new Ui([...this.#scope, symbol])
+	}
+}
+
+export class Client {
+	#event: EventState
+	#actions: unknown[]
+
+	constructor(event: EventState, actions: unknown[]) {
+		this.#event = event
+		this.#actions = actions
+	}
+
+	ui(): Ui {
+		return new Ui(['main'])
+	}
+
+	/**
+	 * Take the data of the incoming event. Throws if `key` is not the key of the incoming event, or if the data was already taken. The event path
+	 * should always be checked before taking the data.
+	 */
+	takeData<T>(key: EventKey<T>): T {
+		const incoming = this.#event.path
+
+		if (key.eventPath.length !== incoming.length || key.eventPath.some((symbol, index) => symbol !== incoming[index])) {
+			throw new Error(
+				`this event path is different from the incoming event path; this event path: ${JSON.stringify(key.eventPath)}; incoming event path: ${
+					JSON.stringify(incoming)
+				}`,
+			)
+		}
+
+		if (this.#event.isTaken) {
+			throw new Error('tried to take event data, but it was already taken; this is probably caused by calling takeData more than once for a single event')
+		}
+
+		this.#event.isTaken = true
+
+		return this.#event.data as T
+	}
+
+	/**
+	 * Emit `data` to the runtime, through `key`.
+	 */
+	emit<T>(key: ActionKey<T>, data: T) {
+		this.#actions.push({ key, data })
+	}
+}
+
+export interface MountEventData {
+	token: string | null
+}
+
+export interface UiResponse {
+	readonly actions: unknown[]
+}
+
+export class RootUi {
+	#event: EventState
+	#actions: unknown[] = []
+
+	constructor(event: RawEvent) {
+		this.#event = { path: event.key.eventPath, data: event.data, isTaken: false }
+	}
+
+	getClient(): Client {
+		return new Client(this.#event, this.#actions)
+	}
+
+	/**
+	 * If the incoming event is the runtime being mounted, take it's data.
+	 */
+	takeMountEvent(): MountEventData | null {
+		if (this.#event.path.length === 0) {
+			throw new Error('found an empty path when trying to check for a mount event, which is never valid')
+		}
+
+		if (this.#event.path[0] !== 'root_app_ready') return null
+
+		if (this.#event.isTaken) {
+			throw new Error('event key stated that this is a mount event, but no event data was given, which is not valid')
+		}
+
+		this.#event.isTaken = true
+
+		return this.#event.data as MountEventData
+	}
+
+	setRootUi(ui: COMPONENT_INDEX | IntoComponentIndex) {
+		this.#actions.push({ key: { actionPath: ['root_mount'] }, data: intoComponentIndex(ui) })
+	}
+
+	intoResponse(): UiResponse {
+		return { actions: this.#actions }
+	}
+}
+
+interface RawEvent {
+	key: { eventPath: string[] }
+	data: unknown
+}
+
+interface RawRequest {
+	sessionId: string
+	events: RawEvent[]
+}
+
+function parseRequest(body: unknown): RawRequest {
+	const isObject = (value: unknown): value is Record<string, unknown> => typeof value === 'object' && value !== null
+
+	if (!isObject(body)) throw new Error('expected an object')
+	if (typeof body.sessionId !== 'string') throw new Error('expected `sessionId` to be a string')
+	if (!Array.isArray(body.events)) throw new Error('expected `events` to be an array')
+
+	for (const event of body.events) {
+		if (!isObject(event) || !isObject(event.key)) throw new Error('expected every event to have a `key` object')
+
+		const path = event.key.eventPath
+		if (!Array.isArray(path) || path.some((symbol) => typeof symbol !== 'string')) {
+			throw new Error('expected every event key to have an `eventPath` of strings')
+		}
+	}
+
+	return body as unknown as RawRequest
+}
+
+function getErrorMessage(error: unknown) {
+	return error instanceof Error ? error.message : String(error)
+}
+
+/**
+ * A random id that is unique to this process. `objection run` polls for it to detect engine restarts.
+ */
+export const INSTANCE_ID = crypto.randomUUID()
+
+/**
+ * Handle a request from the runtime, calling `handler` once for each event that it contains. Returns the actions that should be sent back to
+ * the runtime as the response body. This uses the same protocol as `handle_request` in the objection crate, including it's heartbeat requests.
+ */
+export async function handleRequest(
+	body: unknown,
+	handler: (sessionId: string, ui: RootUi) => UiResponse | Promise<UiResponse>,
+): Promise<unknown> {
+	// heartbeat requests are answered with the instance id, instead of a list of actions
+	if (typeof body === 'object' && body !== null && 'heartbeat' in body && body.heartbeat === true) {
+		return { instanceId: INSTANCE_ID }
+	}
+
+	let request: RawRequest
+
+	try {
+		request = parseRequest(body)
+	} catch (error) {
+		return [{ key: { actionPath: ['root_error'] }, data: `Invalid request body. ${getErrorMessage(error)}` }]
+	}
+
+	const actions: unknown[] = []
+
+	for (const event of request.events) {
+		try {
+			const response = await handler(request.sessionId, new RootUi(event))
+			actions.push(...response.actions)
+		} catch (error) {
+			actions.push({ key: { actionPath: ['root_error'] }, data: getErrorMessage(error) })
+		}
+	}
+
+	return actions
+}
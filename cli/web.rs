@@ -1,26 +1,35 @@
 use aho_corasick::AhoCorasick;
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use axum::{
 	extract::{ws::Message, Request, WebSocketUpgrade},
-	http::HeaderMap,
+	http::{header::CACHE_CONTROL, HeaderMap, HeaderValue},
 	response::{Html, IntoResponse},
 	routing::get,
 	serve, Router,
 };
 use axum_extra::TypedHeader;
-use log::{debug, info, warn};
+use log::{debug, error, info, warn};
 use rand::random;
 use reqwest::StatusCode;
-use std::{collections::HashMap, sync::Arc};
+use serde_json::to_string;
+use std::{
+	collections::HashMap,
+	future::{pending, IntoFuture},
+	path::{Path, PathBuf},
+	sync::{Arc, RwLock},
+};
 use tokio::{net::TcpListener, select, sync::mpsc};
 use tower::ServiceExt;
-use tower_http::services::ServeFile;
-use url::Url;
+use tower_http::services::{ServeDir, ServeFile};
 
 use crate::{
+	asset_loader::AccessibleAssets,
+	asset_pipeline::{load_fingerprinted_paths, AssetPipeline},
 	build::{build, Build, BuildOptions},
 	diagnostic::DiagnosticList,
-	tcp_watcher::{TcpState, TcpWatcher},
+	engine_proxy::EngineProxy,
+	engine_watcher::{EngineState, EngineWatcher},
+	source_watcher::SourceWatcher,
 	writer::{FileWriter, Writer},
 };
 
@@ -36,7 +45,26 @@ enum DevConnectionMessage {
 
 #[derive(Debug, Clone, Copy)]
 enum DevRefreshMessage {
+	/// The engine has restarted, so the component tree should be remounted
 	HotReload,
+	/// The runtime has been rebuilt, so the page must be reloaded to pick up the new bundle
+	Reload,
+}
+
+impl DevRefreshMessage {
+	/// The message that `dev.js` expects for this kind of refresh
+	fn notification(&self) -> &'static str {
+		match self {
+			DevRefreshMessage::HotReload => "remount",
+			DevRefreshMessage::Reload => "reload",
+		}
+	}
+}
+
+/// The parts of a build that are served by the dev server. These are replaced each time the runtime is rebuilt.
+struct Served {
+	client_bundle: String,
+	accessible_assets: AccessibleAssets,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -44,35 +72,70 @@ pub struct RunWebStaticParams<'a> {
 	pub build_options: BuildOptions<'a>,
 	pub web_port: u16,
 	pub reload: bool,
+	pub watch: bool,
+	pub engine_proxy_path: Option<&'a str>,
 	pub bindings_writer: &'a FileWriter,
 	pub cache_writer: &'a Writer,
 }
 
+/// Routes that are handled by the dev server itself, and so can't be used to proxy the engine
+const DEV_ROUTES: &[&str] = &["/bundle.js", "/dev.ws"];
+
 pub async fn run_web_static(params: RunWebStaticParams<'_>) -> Result<()> {
-	let mut diagnostic_list = DiagnosticList::new();
+	let engine_proxy = match params.engine_proxy_path {
+		Some(path) => {
+			let proxy = EngineProxy::new(path, params.build_options.engine_url).context("failed to setup the engine proxy")?;
+
+			if DEV_ROUTES.contains(&proxy.path()) {
+				bail!("cannot proxy the engine at `{}`, because the dev server uses that route", proxy.path());
+			}
+
+			Some(proxy)
+		}
+		None => None,
+	};
+
+	let mut diagnostic_list = DiagnosticList::new(params.build_options.message_format);
 	let Build {
 		client_bundle,
 		bindings,
 		assets_loader,
+		local_modules,
+		..
 	} = build(&mut diagnostic_list, params.build_options).await?;
 
-	let index = get_index_html(params.build_options.engine_url, true);
-	let (dev_connection_sender, mut dev_connection_receiver) = mpsc::channel(10);
+	// when proxied, the client reaches the engine on the same origin that it was served from
+	let client_engine_url = match &engine_proxy {
+		Some(proxy) => proxy.path().to_string(),
+		None => params.build_options.engine_url.to_string(),
+	};
+	let index = get_index_html(&client_engine_url, "/bundle.js", None, true);
+	let (dev_connection_sender, dev_connection_receiver) = mpsc::channel(10);
+	let (refresh_sender, refresh_receiver) = mpsc::channel(1);
 
-	let accessible_assets = Arc::new(assets_loader.download(params.cache_writer, &mut diagnostic_list).await?);
+	let accessible_assets = assets_loader.download(params.cache_writer, &mut diagnostic_list).await?;
 	diagnostic_list.flush("download assets")?;
 
-	params.bindings_writer.write(bindings).await?;
+	params.bindings_writer.write(&bindings).await?;
+
+	let served = Arc::new(RwLock::new(Served {
+		client_bundle,
+		accessible_assets,
+	}));
 
 	let app = Router::new()
 		.route("/", get(move || async { Html(index) }))
 		.route(
 			"/bundle.js",
-			get(|| async {
-				let mut headers = HeaderMap::new();
-				headers.insert("content-type", "appliaction/json".parse().unwrap());
+			get({
+				let served = served.clone();
 
-				(headers, client_bundle)
+				move || async move {
+					let mut headers = HeaderMap::new();
+					headers.insert("content-type", "appliaction/json".parse().unwrap());
+
+					(headers, served.read().unwrap().client_bundle.clone())
+				}
 			}),
 		)
 		.route(
@@ -105,11 +168,7 @@ pub async fn run_web_static(params: RunWebStaticParams<'_>) -> Result<()> {
 								}
 							};
 
-							let notification = match message {
-								DevRefreshMessage::HotReload => "remount",
-							};
-
-							if let Err(_) = socket.send(Message::Text(notification.to_string())).await {
+							if let Err(_) = socket.send(Message::Text(message.notification().to_string())).await {
 								debug!("socket for dev connection {id} appears to be closed");
 
 								break;
@@ -125,105 +184,266 @@ pub async fn run_web_static(params: RunWebStaticParams<'_>) -> Result<()> {
 				})
 			}),
 		)
-		.fallback(|request: Request| async move {
-			let local_path = accessible_assets.get_local_path(request.uri().path());
-
-			match local_path {
-				Some(local_path) => ServeFile::new(local_path).oneshot(request).await.into_response(),
-				None => StatusCode::NOT_FOUND.into_response(),
+		.fallback({
+			let served = served.clone();
+
+			move |request: Request| async move {
+				let local_path = served
+					.read()
+					.unwrap()
+					.accessible_assets
+					.get_local_path(request.uri().path())
+					.map(|path| path.to_string());
+
+				match local_path {
+					Some(local_path) => ServeFile::new(local_path).oneshot(request).await.into_response(),
+					None => StatusCode::NOT_FOUND.into_response(),
+				}
 			}
 		});
 
+	let app = match &engine_proxy {
+		Some(proxy) => proxy.route(app),
+		None => app,
+	};
+
 	let listener = TcpListener::bind(("localhost", params.web_port))
 		.await
 		.with_context(|| format!("failed to bind to localhost:{}", params.web_port))?;
 
 	info!("Serving the static website at http://localhost:{}", params.web_port);
 
-	if params.reload {
-		if let Some(port) = params.build_options.engine_url.port() {
-			let url_text = params.build_options.engine_url.to_string();
-
-			tokio::spawn(async move {
-				let mut watcher = TcpWatcher::new(port);
-				let mut clients = HashMap::<u64, mpsc::Sender<DevRefreshMessage>>::new();
-
-				loop {
-					select! {
-						change = watcher.next_change() => {
-							let change = match change {
-								Some(change) => change,
-								None => break,
-							};
-
-							match change {
-								TcpState::Connected => info!("Engine is online at {url_text}"),
-								TcpState::Disconnected => (),
-								TcpState::Reconnected => {
-									info!("Engine has restarted. Triggering a hot-reload in {} client{}", clients.len(), if clients.len() == 1 { "" } else { "s" });
+	if let Some(proxy) = &engine_proxy {
+		info!(
+			"Proxying http://localhost:{}{} to the engine at {}",
+			params.web_port,
+			proxy.path(),
+			params.build_options.engine_url
+		);
+	}
 
-									for (id, sender) in &clients {
-										if let Err(_) = sender.send(DevRefreshMessage::HotReload).await {
-											debug!("couldn't send refresh message to client {id}; the socket was probably closed at nearly the same time as the reload was triggered");
-										}
-									}
-								},
-							}
-						}
-						connection_message = dev_connection_receiver.recv() => {
-							let message = match connection_message {
-								Some(message) => message,
-								None => {
-									debug!("dev_connection sender gave None; suspecting that the dev server was terminated");
-									break;
-								}
-							};
+	tokio::spawn(drive_dev_connections(dev_connection_receiver, refresh_receiver));
 
-							match message {
-								DevConnectionMessage::Connected{ id, sender, user_agent } => {
-									clients.insert(id, sender);
-									info!("Dev connection ({id}) received from {user_agent}; total connections: {}", clients.len());
-								}
-								DevConnectionMessage::Disconnected(id) => {
-									info!("Dev connection ({id}) has been closed; total connections: {}", clients.len());
-									clients.remove(&id);
-								}
-							};
+	if params.reload {
+		let engine_url = params.build_options.engine_url.clone();
+		let refresh_sender = refresh_sender.clone();
+
+		tokio::spawn(async move {
+			let url_text = engine_url.to_string();
+			let mut watcher = EngineWatcher::new(engine_url);
+
+			while let Some(change) = watcher.next_change().await {
+				match change {
+					EngineState::Connected => info!("Engine is online at {url_text}"),
+					EngineState::Disconnected => (),
+					EngineState::Reconnected => {
+						info!("Engine has restarted. Triggering a hot-reload");
+
+						if let Err(_) = refresh_sender.send(DevRefreshMessage::HotReload).await {
+							break;
 						}
-					};
+					}
 				}
-			});
-		} else {
-			warn!("Engine url is not local, or does not have an explicit port specification. Disabling hot-reloading.");
+			}
+		});
+	}
+
+	let watch = async {
+		if params.watch {
+			if let Err(error) = watch_runtime(params, local_modules, bindings, &served, &refresh_sender)
+				.await
+				.context("Failed to watch the runtime for changes. Disabling rebuilds and proceeding without them.")
+			{
+				warn!("{error:?}");
+			}
 		}
+
+		pending::<()>().await
+	};
+
+	select! {
+		result = serve(listener, app).into_future() => result.context("failed to serve the generated web static platform code")?,
+		_ = watch => (),
 	}
 
-	serve(listener, app).await.context("failed to serve the generated web static platform code")?;
+	Ok(())
+}
+
+/// Keep track of the dev connections, and forward each refresh message to all of them.
+async fn drive_dev_connections(mut connection_receiver: mpsc::Receiver<DevConnectionMessage>, mut refresh_receiver: mpsc::Receiver<DevRefreshMessage>) {
+	let mut clients = HashMap::<u64, mpsc::Sender<DevRefreshMessage>>::new();
+
+	loop {
+		select! {
+			Some(refresh) = refresh_receiver.recv() => {
+				info!("Sending `{}` to {} client{}", refresh.notification(), clients.len(), if clients.len() == 1 { "" } else { "s" });
+
+				for (id, sender) in &clients {
+					if let Err(_) = sender.send(refresh).await {
+						debug!("couldn't send refresh message to client {id}; the socket was probably closed at nearly the same time as the refresh was triggered");
+					}
+				}
+			}
+			connection_message = connection_receiver.recv() => {
+				let message = match connection_message {
+					Some(message) => message,
+					None => {
+						debug!("dev_connection sender gave None; suspecting that the dev server was terminated");
+						break;
+					}
+				};
+
+				match message {
+					DevConnectionMessage::Connected{ id, sender, user_agent } => {
+						clients.insert(id, sender);
+						info!("Dev connection ({id}) received from {user_agent}; total connections: {}", clients.len());
+					}
+					DevConnectionMessage::Disconnected(id) => {
+						info!("Dev connection ({id}) has been closed; total connections: {}", clients.len());
+						clients.remove(&id);
+					}
+				};
+			}
+		};
+	}
+}
+
+/// Rebuild the runtime each time that one of it's local modules changes, and tell the dev connections to reload. If a rebuild fails, the
+/// previous build continues to be served.
+async fn watch_runtime(
+	params: RunWebStaticParams<'_>,
+	local_modules: Vec<PathBuf>,
+	mut bindings: String,
+	served: &RwLock<Served>,
+	refresh_sender: &mpsc::Sender<DevRefreshMessage>,
+) -> Result<()> {
+	let mut watcher = SourceWatcher::new()?;
+	watcher.watch(local_modules)?;
+
+	info!("Watching the runtime for changes");
+
+	while let Some(changed_paths) = watcher.next_change().await {
+		let changed_paths = changed_paths.iter().map(|path| format!("{path:?}")).collect::<Vec<_>>();
+		info!("{} changed. Rebuilding the runtime", changed_paths.join(", "));
+
+		let local_modules = match rebuild(params, &mut bindings, served).await {
+			Ok(local_modules) => local_modules,
+			Err(error) => {
+				error!("{error:?}");
+				warn!("Continuing to serve the previous build");
+
+				continue;
+			}
+		};
+
+		watcher.watch(local_modules)?;
+
+		if let Err(_) = refresh_sender.send(DevRefreshMessage::Reload).await {
+			break;
+		}
+	}
 
 	Ok(())
 }
 
+/// Build the runtime again, replacing what is served and rewriting the bindings. Returns the local modules of the new build.
+async fn rebuild(params: RunWebStaticParams<'_>, previous_bindings: &mut String, served: &RwLock<Served>) -> Result<Vec<PathBuf>> {
+	let mut diagnostic_list = DiagnosticList::new(params.build_options.message_format);
+	let Build {
+		client_bundle,
+		bindings,
+		assets_loader,
+		local_modules,
+		..
+	} = build(&mut diagnostic_list, params.build_options).await?;
+
+	let accessible_assets = assets_loader.download(params.cache_writer, &mut diagnostic_list).await?;
+	diagnostic_list.flush("download assets")?;
+
+	// rewriting identical bindings would needlessly restart any engine that is watching them
+	if bindings != *previous_bindings {
+		params.bindings_writer.write(&bindings).await?;
+		*previous_bindings = bindings;
+	}
+
+	*served.write().unwrap() = Served {
+		client_bundle,
+		accessible_assets,
+	};
+	info!("Rebuilt the runtime");
+
+	Ok(local_modules)
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct BuildWebStaticParams<'a> {
 	pub build_options: BuildOptions<'a>,
 	pub bindings_writer: &'a FileWriter,
 	pub output_writer: &'a Writer,
+	/// Fingerprint and precompress every file, and write an asset manifest (see `AssetPipeline`)
+	pub production: bool,
 }
 
 pub async fn build_web_static(params: BuildWebStaticParams<'_>) -> Result<()> {
-	let mut diagnostic_list = DiagnosticList::new();
+	let mut diagnostic_list = DiagnosticList::new(params.build_options.message_format);
 	let Build {
 		client_bundle,
+		client_source_map,
 		bindings,
 		assets_loader,
+		..
 	} = build(&mut diagnostic_list, params.build_options).await?;
 
 	params.bindings_writer.write(bindings).await?;
+
+	if params.production {
+		let mut pipeline = AssetPipeline::new(params.output_writer);
+
+		assets_loader.write_fingerprinted(&mut pipeline, &mut diagnostic_list).await?;
+		diagnostic_list.flush("write assets")?;
+		info!("Wrote assets");
+
+		// the source map is fingerprinted first, so that the bundle's reference to it is part of the bundle's hash
+		let client_bundle = match client_source_map {
+			Some(source_map) => {
+				let source_map_path = pipeline.write_fingerprinted("/bundle.js.map", source_map).await?;
+
+				format!("{client_bundle}\n//# sourceMappingURL={source_map_path}")
+			}
+			None => client_bundle,
+		};
+		let bundle_path = pipeline.write_fingerprinted("/bundle.js", client_bundle).await?;
+		let manifest_path = pipeline.write_client_manifest().await?;
+
+		pipeline
+			.write(
+				"/index.html",
+				get_index_html(params.build_options.engine_url.as_str(), &bundle_path, Some(&manifest_path), false),
+			)
+			.await?;
+		pipeline.finish().await?;
+
+		return Ok(());
+	}
+
 	params
 		.output_writer
-		.write_file("index.html", get_index_html(params.build_options.engine_url, false))
+		.write_file(
+			"index.html",
+			get_index_html(params.build_options.engine_url.as_str(), "/bundle.js", None, false),
+		)
 		.await?;
-	params.output_writer.write_file("bundle.js", client_bundle).await?;
+
+	match client_source_map {
+		Some(source_map) => {
+			params
+				.output_writer
+				.write_file("bundle.js", format!("{client_bundle}\n//# sourceMappingURL=bundle.js.map"))
+				.await?;
+			params.output_writer.write_file("bundle.js.map", source_map).await?;
+		}
+		None => params.output_writer.write_file("bundle.js", client_bundle).await?,
+	}
 
 	assets_loader.write(params.output_writer, &mut diagnostic_list, Default::default()).await?;
 	info!("Wrote assets");
@@ -233,11 +453,61 @@ pub async fn build_web_static(params: BuildWebStaticParams<'_>) -> Result<()> {
 	Ok(())
 }
 
+#[derive(Debug, Clone, Copy)]
+pub struct ServeWebStaticParams<'a> {
+	pub directory: &'a Path,
+	pub web_port: u16,
+}
+
+/// The `cache-control` of fingerprinted files, which can never change
+const IMMUTABLE_CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+
+/// Serve a build of the web static platform. Fingerprinted files (see `AssetPipeline`) are cached forever, everything else must be
+/// revalidated, and precompressed siblings are sent to clients that accept them.
+pub async fn serve_web_static(params: ServeWebStaticParams<'_>) -> Result<()> {
+	let fingerprinted_paths = Arc::new(load_fingerprinted_paths(params.directory).await?);
+	let serve_dir = ServeDir::new(params.directory).precompressed_br().precompressed_gzip();
+
+	if fingerprinted_paths.is_empty() {
+		warn!(
+			"{:?} is not a production build (see `build --production`), so nothing will be cached",
+			params.directory
+		);
+	}
+
+	let app = Router::new().fallback(move |request: Request| async move {
+		let cache_control = match fingerprinted_paths.contains(request.uri().path()) {
+			true => IMMUTABLE_CACHE_CONTROL,
+			false => "no-cache",
+		};
+		let mut response = serve_dir.oneshot(request).await.into_response();
+
+		if response.status().is_success() {
+			response.headers_mut().insert(CACHE_CONTROL, HeaderValue::from_static(cache_control));
+		}
+
+		response
+	});
+
+	let listener = TcpListener::bind(("localhost", params.web_port))
+		.await
+		.with_context(|| format!("failed to bind to localhost:{}", params.web_port))?;
+
+	info!("Serving {:?} at http://localhost:{}", params.directory, params.web_port);
+
+	serve(listener, app).await.context("failed to serve the web static platform build")
+}
+
 const STATIC_HTML: &str = include_str!("web_index.html");
 const DEV_JS: &str = include_str!("dev.js");
 
-fn get_index_html(engine_url: &Url, is_dev: bool) -> String {
-	AhoCorasick::new(&["ENGINE_URL", "\"DEV_SCRIPT\""])
+/// `engine_url` may be relative to the page, such as when the engine is proxied. `manifest_path` is the path of the manifest that the runtime
+/// finds fingerprinted assets with (see `AssetPipeline::write_client_manifest`), if the build was fingerprinted.
+fn get_index_html(engine_url: &str, bundle_path: &str, manifest_path: Option<&str>, is_dev: bool) -> String {
+	// a `</script>` in the path would otherwise end the script early
+	let manifest_path = to_string(&manifest_path).unwrap().replace('<', "\\u003c");
+
+	AhoCorasick::new(&["ENGINE_URL", "BUNDLE_PATH", "\"ASSET_MANIFEST_PATH\"", "\"DEV_SCRIPT\""])
 		.unwrap()
-		.replace_all(STATIC_HTML, &[engine_url.as_str(), if is_dev { DEV_JS } else { "" }])
+		.replace_all(STATIC_HTML, &[engine_url, bundle_path, &manifest_path, if is_dev { DEV_JS } else { "" }])
 }
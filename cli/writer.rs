@@ -1,13 +1,11 @@
 use anyhow::{anyhow, Context, Result};
 use log::info;
-use reqwest::get;
 use sha2::{Digest, Sha256};
 use std::path::{Path, PathBuf};
 use tokio::{
 	fs::{create_dir_all, File},
 	io::{AsyncReadExt, AsyncWriteExt, BufReader, BufWriter},
 };
-use url::Url;
 
 #[derive(Debug)]
 pub struct Writer {
@@ -27,6 +25,7 @@ impl Writer {
 			.write_all(data.as_ref())
 			.await
 			.with_context(|| format!("failed to write {joined_path:?}"))?;
+		writer.flush().await?;
 
 		info!("Wrote {joined_path:?}");
 
@@ -54,50 +53,6 @@ impl Writer {
 		Ok(hasher.finalize().to_vec())
 	}
 
-	/// Download `url` to `path`, returning the sha256 of the downloaded file
-	pub async fn download_file(&self, path: impl AsRef<Path>, url: &Url) -> Result<Vec<u8>> {
-		let mut hasher = Sha256::new();
-		let (file, joined_path) = self.create(path).await?;
-		let mut writer = BufWriter::new(file);
-
-		if url.scheme() == "file" {
-			let mut reader = BufReader::new(File::open(url.path()).await.with_context(|| format!("failed to open {}", url.path()))?);
-			let mut buffer = [0; 1024];
-
-			loop {
-				let bytes_read = reader.read(&mut buffer).await.with_context(|| format!("failed to read {}", url.path()))?;
-				if bytes_read == 0 {
-					break;
-				}
-
-				hasher.update(&buffer[..bytes_read]);
-
-				writer
-					.write_all(&buffer[..bytes_read])
-					.await
-					.with_context(|| format!("failed to write to {joined_path:?}"))?;
-			}
-
-			info!("Copied {}", url.path());
-		} else {
-			let mut response = get(url.clone()).await.with_context(|| format!("Failed to fetch {url}"))?;
-
-			while let Some(chunk) = response.chunk().await? {
-				hasher.update(&chunk[..]);
-
-				writer
-					.write_all(&chunk[..])
-					.await
-					.with_context(|| format!("failed to write to {joined_path:?}"))?;
-			}
-
-			info!("Downloaded {url}");
-		}
-
-		writer.flush().await?;
-		Ok(hasher.finalize().to_vec())
-	}
-
 	pub fn get_full_path(&self, path: impl AsRef<Path>) -> PathBuf {
 		self.directory.join(path.as_ref())
 	}
@@ -1,7 +1,6 @@
 objection() {
 	cargo run -p objection_cli --\
 		--runtime "file://$(pwd)/runtime/mod.tsx" \
-		--bundler "file://$(pwd)/bundle/main.ts" \
 		--engine rust \
 		--bindings-path runtime_test/bindings.rs \
 		--engine-url http://localhost:8000/ui $@
@@ -31,6 +30,14 @@ task_build() {
 	objection build
 }
 
+task_check() {
+	objection check
+}
+
+task_index_icons() {
+	objection assets index runtime/icons --index runtime/icon_index.json --web-prefix icons $@
+}
+
 task_preview() {
 	runner build
 	runner_parallel run_example serve_web
@@ -5,7 +5,7 @@ import { SkeletonBlock, useSkeletonDetection } from './skeleton.tsx'
 import { Spinner } from './spinner.tsx'
 import { Tooltip } from './tooltip.tsx'
 import { Color } from './theme.tsx'
-import { getColor } from './utils.ts'
+import { getAssetPath, getColor } from './utils.ts'
 
 const ICON_REGISTRY_URL = '/icons'
 const ICONS_CACHE = new Map<string, string>()
@@ -36,7 +36,8 @@ export function IconRender(props: Icon) {
 		setSvg(null)
 		let didStop = false
 
-		fetch(`${ICON_REGISTRY_URL}/${props.name}.svg`)
+		getAssetPath(`${ICON_REGISTRY_URL}/${props.name}.svg`)
+			.then((path) => fetch(path))
 			.then(async (res) => {
 				if (!res.ok) {
 					console.error(`Failed to load icon ${props.name}. Recieved HTTP status ${res.status}`)
@@ -18,6 +18,36 @@ export function makeDebounce<P>(fn: (param: P) => void, ms: number): (param: P)
 	}
 }
 
+let assetPaths: Promise<Record<string, string>> | null = null
+
+/**
+ * Get the path that an asset is served at, which is different from it's web path if a production build fingerprinted it. The fingerprinted
+ * paths are loaded from the build's asset manifest the first time that they are needed.
+ */
+export async function getAssetPath(webPath: string): Promise<string> {
+	// deno-lint-ignore no-explicit-any
+	const manifestPath: string | null | undefined = (globalThis as any).OBJECTION?.assetManifestPath
+	if (!manifestPath) return webPath
+
+	if (!assetPaths) {
+		assetPaths = fetch(manifestPath)
+			.then((res) => {
+				if (!res.ok) throw new Error(`Recieved HTTP status ${res.status}`)
+
+				return res.json()
+			})
+			.catch((error) => {
+				console.error('Failed to load the asset manifest:', error)
+
+				// try again the next time that an asset is needed
+				assetPaths = null
+				return {}
+			})
+	}
+
+	return (await assetPaths)[webPath] ?? webPath
+}
+
 export function isOk<T>(value: T | undefined | null): value is T {
 	return value !== undefined && value !== null
 }
@@ -1,5 +1,5 @@
 use anyhow::{anyhow, Error, Result};
-use async_worker::{Queue, QueueBuilder, Worker};
+use async_worker::{Mailbox, Queue, QueueBuilder, Worker};
 use axum::{extract::State, routing::post, Json, Router};
 use basic_ui::get_basic_ui;
 use bindings::ThemeManager;
@@ -22,11 +22,11 @@ impl Worker for Session {
 	type Response = Result<UiResponse, Error>;
 	type Id = String;
 
-	async fn create(id: &Self::Id, context: Self::Context) -> Self {
+	async fn create(id: &Self::Id, context: Self::Context, _: &Mailbox<Self>) -> Self {
 		Session {}
 	}
 
-	async fn handle(&mut self, mut request: Self::Request) -> Self::Response {
+	async fn handle(&mut self, mut request: Self::Request, _: &Mailbox<Self>) -> Self::Response {
 		if let Some(_) = request.take_mount_event()? {
 			let body = get_basic_ui(request.get_client().ui());
 
@@ -71,9 +71,11 @@ fn map_async_worker_error(error: async_worker::Error) -> Error {
 	match error {
 		async_worker::Error::WorkerAtCapacity => anyhow!("Slow down a little!! You've been rate-limited."),
 		async_worker::Error::NoWorker => anyhow!("No session is associated with the mentioned session id."),
+		async_worker::Error::TooManyWorkers => anyhow!("The server is at capacity. Please try again later."),
 		async_worker::Error::Ceeded => anyhow!("Ceeding response to a newer request on the same session."),
 		async_worker::Error::WorkerTerminated => anyhow!("Your session has been closed."),
 		async_worker::Error::Timeout => anyhow!("Poll has timed out. Please try again."),
+		async_worker::Error::Journal(error) => anyhow!("Failed to persist your request: {error}"),
 	}
 }
 
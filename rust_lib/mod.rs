@@ -2,7 +2,7 @@ use log::error;
 use rand::random;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_json::{from_value, json, to_value, Value};
-use std::{fmt::Display, future::Future, marker::PhantomData};
+use std::{fmt::Display, future::Future, marker::PhantomData, sync::OnceLock};
 use thiserror::Error;
 
 enum EventScope<'a> {
@@ -164,6 +164,19 @@ struct RawEventKey {
 	event_path: Vec<String>,
 }
 
+/// A random id that is unique to this process. `objection run` polls for it to detect engine restarts, even if the new process is listening
+/// before the old one is noticed to be gone.
+pub fn instance_id() -> &'static str {
+	static INSTANCE_ID: OnceLock<String> = OnceLock::new();
+
+	INSTANCE_ID.get_or_init(|| random::<u64>().to_string())
+}
+
+/// Heartbeat requests are `{ "heartbeat": true }`, and are answered with `{ "instanceId": instance_id() }` instead of a list of actions.
+fn is_heartbeat(json: &Value) -> bool {
+	json.get("heartbeat").and_then(Value::as_bool).unwrap_or(false)
+}
+
 fn parse_request(json: Value) -> Result<RawRequest, RequestError> {
 	from_value::<RawRequest>(json).map_err(|e| RequestError { serde_error: e.to_string() })
 }
@@ -174,6 +187,10 @@ where
 	Output: Future<Output = std::result::Result<UiResponse, Error>>,
 	Func: FnMut(String, RootUi) -> Output,
 {
+	if is_heartbeat(&request_body) {
+		return json!({ "instanceId": instance_id() });
+	}
+
 	let RawRequest { session_id, events } = match parse_request(request_body) {
 		Ok(infos) => infos,
 		Err(err) => {